@@ -6,24 +6,22 @@ use core::borrow::Borrow;
 use core::sync::atomic::AtomicUsize;
 use rand::{Rng, SeedableRng};
 
-use m4vga::util::rw_lock::ReadWriteLock;
-use m4vga::Pixel;
+use m4vga::color::{self, Color};
+use m4vga::util::flip_buf::{FlipBuffer, FlipReader, FlipWriter};
 use m4vga_fx_common::{Demo, Raster, Render};
 
 pub struct State<B> {
-    pub fg: ReadWriteLock<B>,
-    pub bg: B,
+    pub buffers: FlipBuffer<[B; 2], B>,
     pub clut: AtomicUsize,
 }
 
 pub struct RasterState<'a, B> {
-    fg: &'a ReadWriteLock<B>,
+    fg: FlipReader<'a, B>,
     clut: &'a AtomicUsize,
 }
 
 pub struct RenderState<'a, B> {
-    fg: &'a ReadWriteLock<B>,
-    bg: &'a mut B,
+    bg: FlipWriter<'a, B>,
 }
 
 impl<B> State<B>
@@ -31,62 +29,58 @@ where
     B: AsMut<[u32]>,
 {
     pub fn new(
-        fg_buf: B,
-        mut bg_buf: B,
-        fg_color: Pixel,
-        bg_color: Pixel,
+        mut fg_buf: B,
+        bg_buf: B,
+        fg_color: Color,
+        bg_color: Color,
     ) -> Self {
+        // The foreground buffer is displayed first, and seeds the first
+        // generation.
         let mut rng = rand::rngs::SmallRng::seed_from_u64(11181981);
-        for word in bg_buf.as_mut().iter_mut() {
+        for word in fg_buf.as_mut().iter_mut() {
             *word = rng.gen();
         }
 
         State {
-            fg: ReadWriteLock::new(fg_buf),
-            bg: bg_buf,
-            clut: AtomicUsize::new(
-                bg_color as usize | ((fg_color as usize) << 8),
-            ),
+            buffers: FlipBuffer::new([fg_buf, bg_buf]),
+            clut: AtomicUsize::new(color::bitmap_1_clut(bg_color, fg_color)),
         }
     }
 }
 
 impl<'a, B> Demo<'a> for State<B>
 where
-    B: AsMut<[u32]> + Borrow<[u32]> + Send + 'a,
+    B: AsMut<[u32]> + Borrow<[u32]> + Send + Sync + 'a,
 {
     type Raster = RasterState<'a, B>;
     type Render = RenderState<'a, B>;
 
     fn split(&'a mut self) -> (Self::Raster, Self::Render) {
+        let (fg, bg) = self.buffers.split();
         (
             RasterState {
-                fg: &self.fg,
+                fg,
                 clut: &self.clut,
             },
-            RenderState {
-                fg: &self.fg,
-                bg: &mut self.bg,
-            },
+            RenderState { bg },
         )
     }
 }
 
 impl<'a, B> Raster for RasterState<'a, B>
 where
-    B: Borrow<[u32]> + Send,
+    B: Borrow<[u32]> + Sync,
 {
     fn raster_callback(
         &mut self,
         ln: usize,
         target: &mut m4vga::rast::TargetBuffer,
         ctx: &mut m4vga::rast::RasterCtx,
-        _: m4vga::priority::I0,
+        priority: m4vga::priority::I0,
     ) {
         m4vga::util::measurement::sig_d_set();
 
-        let fg = self.fg.try_lock().expect("fg unavail");
-        let fg = (*fg).borrow();
+        let fg = self.fg.front(ln, &priority).borrow();
 
         m4vga::util::measurement::sig_d_clear();
 
@@ -104,8 +98,8 @@ impl<'a, B> Render for RenderState<'a, B>
 where
     B: AsMut<[u32]> + Borrow<[u32]>,
 {
-    fn render_frame(&mut self, _: usize, _: m4vga::priority::Thread) {
-        core::mem::swap(self.bg, &mut *self.fg.lock_mut());
-        conway::step((*self.fg.lock()).borrow(), self.bg.as_mut());
+    fn render_frame(&mut self, _: usize, priority: m4vga::priority::Thread) {
+        let mut bg = self.bg.back(&priority);
+        conway::step(bg.prev().borrow(), bg.as_mut());
     }
 }
@@ -6,11 +6,9 @@ use m4vga::rast::direct;
 use m4vga::util::race_buf::{RaceBuffer, RaceReader, RaceWriter};
 use m4vga_fx_common::{Demo, Raster, Render};
 
+use math::approx::{Approx, Fast};
 use math::{Augment, Mat3f, Matrix, Project, Vec2};
 
-#[cfg(target_os = "none")]
-use libm::F32Ext;
-
 pub const NATIVE_WIDTH: usize = 800;
 pub const NATIVE_HEIGHT: usize = 600;
 
@@ -101,8 +99,11 @@ where
         self.writer.reset(&thread);
 
         m4vga::util::measurement::sig_d_set();
-        let s = (frame as f32 / 50.).sin() * 0.7 + 1.;
-        let tx = (frame as f32 / 100.).cos() * 100.;
+        // `Fast` only handles angles up to 100 radians, so wrap the frame count
+        // after about ten periods of each oscillation. The jump is too small to
+        // notice.
+        let s = Fast::sin((frame % 3142) as f32 / 50.) * 0.7 + 1.;
+        let tx = Fast::cos((frame % 6283) as f32 / 100.) * 100.;
         let ty = 0.;
 
         let m_ = *self.m * Mat3f::translate(tx, ty) * Mat3f::scale(s, s);
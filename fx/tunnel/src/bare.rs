@@ -4,7 +4,7 @@
 //! spinlocks, and calling assembly rasterizer routines, none of which are
 //! relevant to the hosted version.
 
-use m4vga::util::spin_lock::SpinLock;
+use m4vga::util::flip_buf::FlipBuffer;
 
 use super::table;
 
@@ -28,8 +28,8 @@ pub unsafe fn init() -> super::State<&'static mut [u32], &'static table::Table>
     table::compute(table);
     let table = &*table;
 
-    let fg = SpinLock::new(&mut BUF0 as &mut [u32]);
-    let bg = &mut BUF1 as &mut [u32];
+    let buffers =
+        FlipBuffer::new([&mut BUF0 as &mut [u32], &mut BUF1 as &mut [u32]]);
 
-    super::State { fg, bg, table }
+    super::State { buffers, table }
 }
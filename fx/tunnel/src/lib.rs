@@ -1,6 +1,6 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use m4vga::util::spin_lock::SpinLock;
+use m4vga::util::flip_buf::{FlipBuffer, FlipReader, FlipWriter};
 use m4vga_fx_common::{Demo, Raster, Render};
 
 pub mod render;
@@ -25,35 +25,33 @@ mod bare;
 pub use bare::*;
 
 pub struct State<B, T> {
-    pub fg: SpinLock<B>,
-    pub bg: B,
+    pub buffers: FlipBuffer<[B; 2], B>,
     pub table: T,
 }
 
 pub struct RasterState<'a, B> {
-    fg: &'a SpinLock<B>,
+    fg: FlipReader<'a, B>,
 }
 
 pub struct RenderState<'a, B, T> {
-    fg: &'a SpinLock<B>,
-    bg: &'a mut B,
+    bg: FlipWriter<'a, B>,
     table: &'a T,
 }
 
 impl<'a, B, T> Demo<'a> for State<B, T>
 where
-    B: AsMut<[u32]> + Send + 'a,
+    B: AsMut<[u32]> + AsRef<[u32]> + Send + Sync + 'a,
     T: core::borrow::Borrow<table::Table> + 'a,
 {
     type Raster = RasterState<'a, B>;
     type Render = RenderState<'a, B, T>;
 
     fn split(&'a mut self) -> (Self::Raster, Self::Render) {
+        let (fg, bg) = self.buffers.split();
         (
-            RasterState { fg: &self.fg },
+            RasterState { fg },
             RenderState {
-                fg: &self.fg,
-                bg: &mut self.bg,
+                bg,
                 table: &self.table,
             },
         )
@@ -62,14 +60,14 @@ where
 
 impl<'a, B> Raster for RasterState<'a, B>
 where
-    B: AsMut<[u32]> + Send,
+    B: AsRef<[u32]> + Sync,
 {
     fn raster_callback(
         &mut self,
         ln: usize,
         target: &mut m4vga::rast::TargetBuffer,
         ctx: &mut m4vga::rast::RasterCtx,
-        _: m4vga::priority::I0,
+        priority: m4vga::priority::I0,
     ) {
         // Our image is slightly smaller than the display. Black the top and
         // bottom borders.
@@ -78,8 +76,7 @@ where
             return;
         }
 
-        let mut buf = self.fg.try_lock().expect("rast fg access");
-        let buf = buf.as_mut();
+        let buf = self.fg.front(ln, &priority).as_ref();
 
         let ln = ln / SCALE;
 
@@ -112,12 +109,13 @@ where
     B: AsMut<[u32]> + Send,
     T: core::borrow::Borrow<table::Table>,
 {
-    fn render_frame(&mut self, frame: usize, _: m4vga::priority::Thread) {
-        core::mem::swap(
-            self.bg,
-            &mut *self.fg.try_lock().expect("swap access"),
-        );
-        let bg = u32_as_u8_mut(self.bg.as_mut());
+    fn render_frame(
+        &mut self,
+        frame: usize,
+        priority: m4vga::priority::Thread,
+    ) {
+        let mut bg = self.bg.back(&priority);
+        let bg = u32_as_u8_mut(bg.as_mut());
         m4vga::util::measurement::sig_d_set();
         self::render::render(self.table.borrow(), bg, frame);
         m4vga::util::measurement::sig_d_clear();
@@ -2,8 +2,7 @@
 
 use core::f32::consts::PI;
 
-#[cfg(not(feature = "std"))]
-use libm::F32Ext;
+use math::approx::{Accurate, Approx};
 
 pub const SUB: usize = 4;
 
@@ -36,8 +35,10 @@ impl Entry {
         let x = x as f32 + 0.5;
         let y = y as f32 + 0.5;
         Entry {
-            distance: TEX_PERIOD_D as f32 / f32::sqrt(x * x + y * y),
-            angle: TEX_PERIOD_A as f32 * 0.5 * (f32::atan2(y, x) / PI + 1.),
+            distance: TEX_PERIOD_D as f32 * Accurate::rsqrt(x * x + y * y),
+            angle: TEX_PERIOD_A as f32
+                * 0.5
+                * (Accurate::atan2(y, x) / PI + 1.),
         }
     }
 }
@@ -0,0 +1,492 @@
+//! Bit-blits between 1bpp buffers.
+//!
+//! A blit combines a rectangle of pixels from a source image with a
+//! `PackedBitBuffer`, using one of the classic raster operations (`Rop`). The
+//! source and destination can be at any bit alignment relative to one
+//! another: rather than touching pixels one at a time (as the bit-band line
+//! drawing does), source bits are shifted into place a word at a time, and
+//! combined with each destination word under a mask.
+//!
+//! Source images are described by `BitImage`, a read-only view of packed
+//! words that can be narrowed down to a sub-rectangle, e.g. one frame of a
+//! sprite sheet. A `PackedBitBuffer` can be blitted onto itself using
+//! `scroll`, which handles overlap.
+
+use core::cmp::{max, min};
+
+use crate::PackedBitBuffer;
+
+/// Raster operations, which determine how each source pixel `S` combines with
+/// the destination pixel `D` it lands on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Rop {
+    /// `D = S`
+    Copy,
+    /// `D = D | S`: draws the source's 1 pixels.
+    Or,
+    /// `D = D & S`: draws the source's 0 pixels.
+    And,
+    /// `D = D ^ S`: inverts under the source's 1 pixels.
+    Xor,
+    /// `D = D & !S`: erases under the source's 1 pixels.
+    AndNot,
+}
+
+impl Rop {
+    fn apply(self, d: u32, s: u32) -> u32 {
+        match self {
+            Rop::Copy => s,
+            Rop::Or => d | s,
+            Rop::And => d & s,
+            Rop::Xor => d ^ s,
+            Rop::AndNot => d & !s,
+        }
+    }
+}
+
+/// A read-only 1bpp image, used as the source of blits.
+///
+/// Pixels are packed into words in the same arrangement as `PackedBitBuffer`.
+/// The image may be a sub-rectangle of the words it views; see `sub`.
+#[derive(Copy, Clone, Debug)]
+pub struct BitImage<'a> {
+    words: &'a [u32],
+    /// Words per row.
+    stride: usize,
+    /// Horizontal offset of the image within each row, in pixels.
+    x: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> BitImage<'a> {
+    /// Views `words` as an image `stride * 32` pixels wide, and as tall as the
+    /// number of complete rows.
+    pub fn new(words: &'a [u32], stride: usize) -> Self {
+        BitImage {
+            words,
+            stride,
+            x: 0,
+            width: stride * 32,
+            height: words.len().checked_div(stride).unwrap_or(0),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Narrows the image to the rectangle with its top-left corner at
+    /// `(x, y)` and the given size. The rectangle is clipped to the current
+    /// image.
+    pub fn sub(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let x = min(x, self.width);
+        let y = min(y, self.height);
+        BitImage {
+            words: &self.words[y * self.stride..],
+            stride: self.stride,
+            x: self.x + x,
+            width: min(width, self.width - x),
+            height: min(height, self.height - y),
+        }
+    }
+
+    /// Reads the pixel at `(x, y)`, which must be within the image.
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        assert!(x < self.width && y < self.height);
+        let bit = self.bit(x, y);
+        self.words[bit / 32] & (1 << (bit % 32)) != 0
+    }
+
+    /// Index of the pixel at `(x, y)` as a bit offset into `words`.
+    fn bit(&self, x: usize, y: usize) -> usize {
+        y * self.stride * 32 + self.x + x
+    }
+}
+
+impl<'a> PackedBitBuffer<'a> {
+    /// Views the buffer as a `BitImage`, e.g. to blit from it into another
+    /// buffer.
+    pub fn as_image(&self) -> BitImage<'_> {
+        BitImage::new(self.mem, self.stride)
+    }
+
+    /// Combines `src` into the buffer with its top-left corner at `(x, y)`,
+    /// using `rop`. Parts of `src` that fall outside the buffer are ignored.
+    pub fn blit(&mut self, x: i32, y: i32, src: &BitImage<'_>, rop: Rop) {
+        self.blit_inner(x, y, src, None, rop)
+    }
+
+    /// Combines `src` into the buffer with its top-left corner at `(x, y)`,
+    /// using `rop`, but only where `mask` is 1. This is the usual way to draw
+    /// a sprite that isn't rectangular, like a mouse cursor: with `Rop::Copy`,
+    /// the sprite's 0 and 1 pixels both replace the destination within the
+    /// mask, and the destination shows through outside it.
+    ///
+    /// # Panics
+    ///
+    /// If `mask` is not the same size as `src`.
+    pub fn blit_masked(
+        &mut self,
+        x: i32,
+        y: i32,
+        src: &BitImage<'_>,
+        mask: &BitImage<'_>,
+        rop: Rop,
+    ) {
+        assert!(
+            mask.width == src.width && mask.height == src.height,
+            "mask size doesn't match source"
+        );
+        self.blit_inner(x, y, src, Some(mask), rop)
+    }
+
+    /// Moves the contents of the rectangle with opposite corners `(x0, y0)`
+    /// and `(x1, y1)` by `dx` pixels to the right and `dy` pixels down.
+    ///
+    /// Pixels that move out of the rectangle are lost. Pixels in the area that
+    /// is uncovered are left unchanged; you may want to clear them afterwards,
+    /// e.g. with `fill_rect`.
+    pub fn scroll(
+        &mut self,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        dx: i32,
+        dy: i32,
+    ) {
+        let width = self.width() as i64;
+        let height = self.height() as i64;
+        let (dx, dy) = (i64::from(dx), i64::from(dy));
+        // Clip the rectangle to the buffer, and then to the part whose pixels
+        // stay inside the rectangle after moving.
+        let left = max(i64::from(min(x0, x1)), 0);
+        let right = min(i64::from(max(x0, x1)), width - 1);
+        let top = max(i64::from(min(y0, y1)), 0);
+        let bottom = min(i64::from(max(y0, y1)), height - 1);
+        let (src_left, src_right) =
+            (max(left, left - dx), min(right, right - dx));
+        let (src_top, src_bottom) =
+            (max(top, top - dy), min(bottom, bottom - dy));
+        if src_left > src_right || src_top > src_bottom {
+            return;
+        }
+
+        let row_bits = self.stride * 32;
+        let count = (src_right - src_left + 1) as usize;
+        let rows = src_top..=src_bottom;
+        // Copy rows in the direction that reads each one before it's
+        // overwritten. Within a row, `blit_row` handles it.
+        let mut copy_row = |y: i64| {
+            let src_bit = y as usize * row_bits + src_left as usize;
+            let dst_bit =
+                (y + dy) as usize * row_bits + (src_left + dx) as usize;
+            blit_row(
+                self.mem,
+                dst_bit,
+                Source::Within(src_bit),
+                None,
+                count,
+                Rop::Copy,
+            );
+        };
+        if dy > 0 {
+            rows.rev().for_each(&mut copy_row)
+        } else {
+            rows.for_each(&mut copy_row)
+        }
+    }
+
+    fn blit_inner(
+        &mut self,
+        x: i32,
+        y: i32,
+        src: &BitImage<'_>,
+        mask: Option<&BitImage<'_>>,
+        rop: Rop,
+    ) {
+        // Find the part of the source that lands in the buffer, in source
+        // coordinates.
+        let (x, y) = (i64::from(x), i64::from(y));
+        let left = max(0, -x);
+        let right = min(src.width as i64, self.width() as i64 - x);
+        let top = max(0, -y);
+        let bottom = min(src.height as i64, self.height() as i64 - y);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        let row_bits = self.stride * 32;
+        let count = (right - left) as usize;
+        for sy in top as usize..bottom as usize {
+            let sx = left as usize;
+            let dst_bit =
+                (sy as i64 + y) as usize * row_bits + (sx as i64 + x) as usize;
+            let src = Source::Image(src.words, src.bit(sx, sy));
+            let mask = mask.map(|m| (m.words, m.bit(sx, sy)));
+            blit_row(self.mem, dst_bit, src, mask, count, rop);
+        }
+    }
+}
+
+/// Where `blit_row` gets its source bits.
+enum Source<'s> {
+    /// Starting at a bit offset into some other words.
+    Image(&'s [u32], usize),
+    /// Starting at a bit offset into the destination itself.
+    Within(usize),
+}
+
+/// Combines `count` bits of `src` into `dst`, starting at bit `dst_bit`,
+/// using `rop`. If `mask` is given, as words and a starting bit offset, only
+/// destination bits whose corresponding mask bit is 1 are changed.
+///
+/// This works a destination word at a time, so that we read and write each
+/// destination word once. When the source is within `dst`, the words are
+/// processed in whichever order reads source bits before they're overwritten.
+fn blit_row(
+    dst: &mut [u32],
+    dst_bit: usize,
+    src: Source<'_>,
+    mask: Option<(&[u32], usize)>,
+    count: usize,
+    rop: Rop,
+) {
+    if count == 0 {
+        return;
+    }
+    let end = dst_bit + count;
+    let first_word = dst_bit / 32;
+    let last_word = (end - 1) / 32;
+
+    let mut do_word = |w: usize| {
+        // The part of this word that's in the destination range.
+        let lo = max(dst_bit, w * 32);
+        let hi = min(end, w * 32 + 32);
+        let (shift, n) = (lo % 32, hi - lo);
+        let offset = lo - dst_bit;
+
+        let s = match src {
+            Source::Image(words, bit) => fetch(words, bit + offset, n),
+            Source::Within(bit) => fetch(dst, bit + offset, n),
+        } << shift;
+        let mut m = (!0 >> (32 - n)) << shift;
+        if let Some((words, bit)) = mask {
+            m &= fetch(words, bit + offset, n) << shift;
+        }
+
+        let d = dst[w];
+        dst[w] = (d & !m) | (rop.apply(d, s) & m);
+    };
+
+    let backward = match src {
+        Source::Within(bit) => bit < dst_bit,
+        _ => false,
+    };
+    if backward {
+        (first_word..=last_word).rev().for_each(&mut do_word)
+    } else {
+        (first_word..=last_word).for_each(&mut do_word)
+    }
+}
+
+/// Reads `n` bits (1 to 32) starting at bit offset `bit` of `words`, returning
+/// them in the least significant bits of the result. The other bits of the
+/// result are unspecified.
+fn fetch(words: &[u32], bit: usize, n: usize) -> u32 {
+    let (w, shift) = (bit / 32, bit % 32);
+    if shift == 0 {
+        words[w]
+    } else if shift + n <= 32 {
+        words[w] >> shift
+    } else {
+        (words[w] >> shift) | (words[w + 1] << (32 - shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Lcg;
+
+    const ROPS: [Rop; 5] =
+        [Rop::Copy, Rop::Or, Rop::And, Rop::Xor, Rop::AndNot];
+
+    fn get(words: &[u32], stride: usize, x: usize, y: usize) -> bool {
+        words[y * stride + x / 32] & (1 << (x % 32)) != 0
+    }
+
+    fn put(words: &mut [u32], stride: usize, x: usize, y: usize, v: bool) {
+        let mask = 1 << (x % 32);
+        if v {
+            words[y * stride + x / 32] |= mask
+        } else {
+            words[y * stride + x / 32] &= !mask
+        }
+    }
+
+    /// Per-pixel reference for `blit` and `blit_masked`.
+    fn reference_blit(
+        dst: &mut [u32],
+        stride: usize,
+        (x, y): (i32, i32),
+        src: &BitImage,
+        mask: Option<&BitImage>,
+        rop: Rop,
+    ) {
+        let height = (dst.len() / stride) as i32;
+        for sy in 0..src.height() {
+            for sx in 0..src.width() {
+                let (dx, dy) = (x + sx as i32, y + sy as i32);
+                if dx < 0 || dy < 0 || dx >= stride as i32 * 32 || dy >= height
+                {
+                    continue;
+                }
+                if let Some(mask) = mask {
+                    if !mask.get(sx, sy) {
+                        continue;
+                    }
+                }
+                let (dx, dy) = (dx as usize, dy as usize);
+                let d = get(dst, stride, dx, dy) as u32;
+                let s = src.get(sx, sy) as u32;
+                put(dst, stride, dx, dy, rop.apply(d, s) & 1 != 0);
+            }
+        }
+    }
+
+    #[test]
+    fn image_views() {
+        let words = [0b11 << 30, 0b10, 0, 0b10];
+        let image = BitImage::new(&words, 2);
+        assert_eq!((image.width(), image.height()), (64, 2));
+        let sub = image.sub(30, 0, 4, 10);
+        assert_eq!((sub.width(), sub.height()), (4, 2));
+        assert_eq!(
+            [sub.get(0, 0), sub.get(1, 0), sub.get(2, 0), sub.get(3, 0)],
+            [true, true, false, true]
+        );
+        let sub = sub.sub(1, 1, 10, 10);
+        assert_eq!((sub.width(), sub.height()), (3, 1));
+        assert!(sub.get(2, 0));
+        let empty = image.sub(100, 100, 10, 10);
+        assert_eq!((empty.width(), empty.height()), (0, 0));
+    }
+
+    #[test]
+    fn blits_match_reference() {
+        let mut rng = Lcg(11);
+        for _ in 0..3000 {
+            let src_words = rng.words(3 * 12);
+            let full = BitImage::new(&src_words, 3);
+            let src = full.sub(
+                rng.below(96) as usize,
+                rng.below(12) as usize,
+                rng.below(80) as usize,
+                rng.below(14) as usize,
+            );
+            let mask_words = rng.words(3 * 12);
+            let mask = BitImage::new(&mask_words, 3).sub(
+                rng.below(96 - src.width() as u32 + 1) as usize,
+                rng.below(12 - src.height() as u32 + 1) as usize,
+                src.width(),
+                src.height(),
+            );
+            let rop = ROPS[rng.below(5) as usize];
+            let at = (rng.below(140) as i32 - 40, rng.below(30) as i32 - 10);
+            let use_mask = rng.below(2) == 0;
+
+            let mut dst = rng.words(2 * 10);
+            let mut expected = dst.clone();
+            let mask = if use_mask { Some(&mask) } else { None };
+            reference_blit(&mut expected, 2, at, &src, mask, rop);
+            let mut buf = PackedBitBuffer::new(&mut dst, 2);
+            match mask {
+                Some(mask) => buf.blit_masked(at.0, at.1, &src, mask, rop),
+                None => buf.blit(at.0, at.1, &src, rop),
+            }
+            assert_eq!(
+                dst, expected,
+                "{:?} at {:?} mask {}",
+                rop, at, use_mask
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn mask_size_mismatch() {
+        let words = [0; 4];
+        let image = BitImage::new(&words, 1);
+        let mut dst = [0; 4];
+        PackedBitBuffer::new(&mut dst, 1).blit_masked(
+            0,
+            0,
+            &image,
+            &image.sub(0, 0, 31, 4),
+            Rop::Copy,
+        );
+    }
+
+    #[test]
+    fn buffer_to_buffer() {
+        let mut src = [0; 2 * 2];
+        let mut src_buf = PackedBitBuffer::new(&mut src, 2);
+        src_buf.fill_rect(30, 0, 33, 1, crate::Mode::Set);
+        let mut dst = [0; 2];
+        let mut dst_buf = PackedBitBuffer::new(&mut dst, 1);
+        let image = src_buf.as_image();
+        dst_buf.blit(3, 1, &image.sub(29, 0, 6, 1), Rop::Copy);
+        assert_eq!(dst, [0, 0b011110 << 3]);
+    }
+
+    #[test]
+    fn scroll_matches_reference() {
+        let mut rng = Lcg(5);
+        for _ in 0..3000 {
+            let mut mem = rng.words(3 * 10);
+            let (x0, y0) =
+                (rng.below(110) as i32 - 5, rng.below(14) as i32 - 2);
+            let (x1, y1) =
+                (rng.below(110) as i32 - 5, rng.below(14) as i32 - 2);
+            let (dx, dy) = (rng.below(81) as i32 - 40, rng.below(9) as i32 - 4);
+
+            let mut expected = mem.clone();
+            for y in 0..10 {
+                for x in 0..96 {
+                    let inside = |x: i32, y: i32| {
+                        x >= min(x0, x1)
+                            && x <= max(x0, x1)
+                            && y >= min(y0, y1)
+                            && y <= max(y0, y1)
+                    };
+                    let (sx, sy) = (x - dx, y - dy);
+                    if inside(x, y)
+                        && inside(sx, sy)
+                        && sx >= 0
+                        && sy >= 0
+                        && sx < 96
+                        && sy < 10
+                    {
+                        let v = get(&mem, 3, sx as usize, sy as usize);
+                        put(&mut expected, 3, x as usize, y as usize, v);
+                    }
+                }
+            }
+
+            PackedBitBuffer::new(&mut mem, 3).scroll(x0, y0, x1, y1, dx, dy);
+            assert_eq!(
+                mem,
+                expected,
+                "{:?} by {:?}",
+                (x0, y0, x1, y1),
+                (dx, dy)
+            );
+        }
+    }
+}
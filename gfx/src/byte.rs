@@ -0,0 +1,421 @@
+//! 8-bit-per-pixel software framebuffers.
+//!
+//! This is the byte-per-pixel counterpart of `PackedBitBuffer`, for demos that
+//! render into a buffer of pixels and scan it out with `rast::direct` -- or
+//! that want to draw a HUD or overlay over their rendering. Each byte is one
+//! pixel, in whatever color format the display uses.
+//!
+//! Drawing operations are clipped to the buffer, using the same signed
+//! coordinates and inclusive corners as the `shape` module. Lines use the same
+//! algorithm as the 1bpp `draw_line`, and set exactly the same pixels.
+
+use core::cmp::{max, min};
+
+use font_10x16::FONT;
+
+use crate::{draw_line_in, Cursor, Pixels};
+
+/// Width of a character cell drawn by `ByteBuffer::draw_text`: eight pixels
+/// from the font, plus a two-pixel gutter.
+pub const GLYPH_COLS: usize = 10;
+/// Height of a character cell drawn by `ByteBuffer::draw_text`.
+pub const GLYPH_ROWS: usize = 16;
+
+/// An 8-bit-per-pixel framebuffer.
+#[derive(Debug)]
+pub struct ByteBuffer<'a> {
+    mem: &'a mut [u8],
+    stride: usize,
+}
+
+impl<'a> ByteBuffer<'a> {
+    /// Creates a buffer `stride` pixels wide, using `mem` as storage. The
+    /// height is the number of complete rows in `mem`.
+    pub fn new(mem: &'a mut [u8], stride: usize) -> Self {
+        ByteBuffer { mem, stride }
+    }
+
+    /// Creates a buffer `stride` pixels wide from a word buffer, like the ones
+    /// used with `rast::direct`.
+    pub fn from_words(mem: &'a mut [u32], stride: usize) -> Self {
+        // Safety: u8 has no alignment or validity requirements, and the
+        // lifetime is carried over.
+        let mem = unsafe {
+            core::slice::from_raw_parts_mut(
+                mem.as_mut_ptr() as *mut u8,
+                mem.len() * 4,
+            )
+        };
+        Self::new(mem, stride)
+    }
+
+    pub fn as_byte_slice(&mut self) -> &[u8] {
+        self.mem
+    }
+
+    /// Width of the buffer in pixels.
+    pub fn width(&self) -> usize {
+        self.stride
+    }
+
+    /// Height of the buffer in pixels.
+    pub fn height(&self) -> usize {
+        self.mem.len().checked_div(self.stride).unwrap_or(0)
+    }
+
+    /// Sets every pixel to `color`.
+    pub fn clear(&mut self, color: u8) {
+        for p in self.mem.iter_mut() {
+            *p = color
+        }
+    }
+
+    /// Reads the pixel at `(x, y)`, or `None` if it's outside the buffer.
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u8> {
+        self.index(x, y).map(|i| self.mem[i])
+    }
+
+    /// Sets the pixel at `(x, y)` to `color`, if it's within the buffer.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        if let Some(i) = self.index(x, y) {
+            self.mem[i] = color
+        }
+    }
+
+    /// Draws a horizontal line in row `y`, from `x0` to `x1` inclusive.
+    pub fn hline(&mut self, y: i32, x0: i32, x1: i32, color: u8) {
+        if let Some(row) = self.row_mut(y) {
+            if let Some((left, right)) =
+                clip(min(x0, x1), max(x0, x1), row.len())
+            {
+                for p in &mut row[left..=right] {
+                    *p = color
+                }
+            }
+        }
+    }
+
+    /// Draws a vertical line in column `x`, from `y0` to `y1` inclusive.
+    pub fn vline(&mut self, x: i32, y0: i32, y1: i32, color: u8) {
+        if x < 0 || x as usize >= self.stride {
+            return;
+        }
+        let height = self.height();
+        if let Some((top, bottom)) = clip(min(y0, y1), max(y0, y1), height) {
+            for y in top..=bottom {
+                self.mem[y * self.stride + x as usize] = color
+            }
+        }
+    }
+
+    /// Draws a line from `(x0, y0)` to `(x1, y1)`, clipped to the bounds of
+    /// the buffer. This draws the same pixels as `draw_line` does in a 1bpp
+    /// buffer.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u8) {
+        let mut pixels = BytePixels {
+            mem: self.mem,
+            color,
+        };
+        draw_line_in(x0, y0, x1, y1, &mut pixels, self.stride)
+    }
+
+    /// Fills the rectangle with opposite corners `(x0, y0)` and `(x1, y1)`.
+    pub fn fill_rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u8) {
+        let height = self.height();
+        if let Some((top, bottom)) = clip(min(y0, y1), max(y0, y1), height) {
+            for y in top..=bottom {
+                self.hline(y as i32, x0, x1, color)
+            }
+        }
+    }
+
+    /// Copies an image into the buffer with its top-left corner at `(x, y)`.
+    ///
+    /// The image is `src_width` pixels wide, and as tall as the number of
+    /// complete rows in `src`. Any pixels equal to `key`, if given, are
+    /// treated as transparent and left alone.
+    pub fn blit(
+        &mut self,
+        x: i32,
+        y: i32,
+        src: &[u8],
+        src_width: usize,
+        key: Option<u8>,
+    ) {
+        if src_width == 0 {
+            return;
+        }
+        let src_height = src.len() / src_width;
+        let x = i64::from(x);
+        let y = i64::from(y);
+
+        // Find the part of the image that lands in the buffer, in image
+        // coordinates.
+        let left = max(0, -x);
+        let right = min(src_width as i64, self.stride as i64 - x);
+        let top = max(0, -y);
+        let bottom = min(src_height as i64, self.height() as i64 - y);
+        if left >= right || top >= bottom {
+            return;
+        }
+        let (left, right) = (left as usize, right as usize);
+
+        for sy in top..bottom {
+            let src_row = &src[sy as usize * src_width..][left..right];
+            let start =
+                (sy + y) as usize * self.stride + (left as i64 + x) as usize;
+            let dst_row = &mut self.mem[start..start + (right - left)];
+            match key {
+                None => dst_row.copy_from_slice(src_row),
+                Some(key) => {
+                    for (d, &s) in dst_row.iter_mut().zip(src_row) {
+                        if s != key {
+                            *d = s
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws a single character cell with its top-left corner at `(x, y)`,
+    /// using glyph `c` of `font_10x16::FONT`.
+    ///
+    /// Glyph pixels are drawn in `fg`. The rest of the cell, including the
+    /// two-pixel gutter on the right, is filled with `bg` -- or left alone if
+    /// `bg` is `None`.
+    pub fn draw_char(&mut self, x: i32, y: i32, c: u8, fg: u8, bg: Option<u8>) {
+        let glyphs = FONT.as_glyph_slices();
+        for (row, glyph_row) in glyphs.iter().enumerate() {
+            let py = y.saturating_add(row as i32);
+            // Bit 0 of each font byte is the leftmost pixel.
+            let bits = u32::from(glyph_row[usize::from(c)]);
+            for col in 0..GLYPH_COLS {
+                let px = x.saturating_add(col as i32);
+                if bits & (1 << col) != 0 {
+                    self.set_pixel(px, py, fg)
+                } else if let Some(bg) = bg {
+                    self.set_pixel(px, py, bg)
+                }
+            }
+        }
+    }
+
+    /// Draws a line of text with its top-left corner at `(x, y)`, in
+    /// `GLYPH_COLS` by `GLYPH_ROWS` cells. See `draw_char` for the meaning of
+    /// the colors.
+    ///
+    /// The font only covers ASCII reliably, so other characters are drawn as
+    /// `?`. Control characters, including newlines, are drawn as whatever the
+    /// font has for them.
+    ///
+    /// Returns the x coordinate just past the end of the text, for continuing
+    /// the line.
+    pub fn draw_text(
+        &mut self,
+        x: i32,
+        y: i32,
+        text: &str,
+        fg: u8,
+        bg: Option<u8>,
+    ) -> i32 {
+        let mut x = x;
+        for c in text.chars() {
+            let c = if c.is_ascii() { c as u8 } else { b'?' };
+            self.draw_char(x, y, c, fg, bg);
+            x = x.saturating_add(GLYPH_COLS as i32);
+        }
+        x
+    }
+
+    /// Finds the index of `(x, y)` in `mem`, if it's within the buffer.
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x < self.stride && y < self.height() {
+            Some(y * self.stride + x)
+        } else {
+            None
+        }
+    }
+
+    /// Borrows row `y`, if it's within the buffer.
+    fn row_mut(&mut self, y: i32) -> Option<&mut [u8]> {
+        if y < 0 || y as usize >= self.height() {
+            None
+        } else {
+            let start = y as usize * self.stride;
+            Some(&mut self.mem[start..start + self.stride])
+        }
+    }
+}
+
+/// Clips the inclusive range `lo..=hi` to `0..limit`, returning `None` if
+/// nothing is left.
+fn clip(lo: i32, hi: i32, limit: usize) -> Option<(usize, usize)> {
+    let lo = max(i64::from(lo), 0);
+    let hi = min(i64::from(hi), limit as i64 - 1);
+    if lo <= hi {
+        Some((lo as usize, hi as usize))
+    } else {
+        None
+    }
+}
+
+/// Bytes viewed as pixels for the line drawing algorithms, which draw in
+/// `color`.
+struct BytePixels<'a> {
+    mem: &'a mut [u8],
+    color: u8,
+}
+
+impl<'a> Pixels for BytePixels<'a> {
+    type Cursor = ByteCursor;
+
+    fn pixel_count(&self) -> usize {
+        self.mem.len()
+    }
+
+    fn cursor(&mut self, index: usize) -> Self::Cursor {
+        ByteCursor {
+            pixel: &mut self.mem[index],
+            color: self.color,
+        }
+    }
+}
+
+struct ByteCursor {
+    pixel: *mut u8,
+    color: u8,
+}
+
+impl Cursor for ByteCursor {
+    unsafe fn plot(&mut self) {
+        *self.pixel = self.color
+    }
+
+    unsafe fn advance(&mut self, delta: isize) {
+        self.pixel = self.pixel.offset(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Lcg;
+    use crate::PackedBitBuffer;
+
+    #[test]
+    fn pixels_clip() {
+        let mut mem = [0; 6 * 4];
+        let mut buf = ByteBuffer::new(&mut mem, 6);
+        assert_eq!((buf.width(), buf.height()), (6, 4));
+        buf.set_pixel(5, 3, 7);
+        buf.set_pixel(6, 0, 1);
+        buf.set_pixel(-1, 0, 1);
+        buf.set_pixel(0, 4, 1);
+        assert_eq!(buf.get_pixel(5, 3), Some(7));
+        assert_eq!(buf.get_pixel(6, 3), None);
+        assert_eq!(buf.as_byte_slice().iter().filter(|&&p| p != 0).count(), 1);
+    }
+
+    #[test]
+    fn straight_lines() {
+        let mut mem = [0; 6 * 4];
+        let mut buf = ByteBuffer::new(&mut mem, 6);
+        buf.hline(1, 100, 4, 1);
+        buf.vline(2, -5, 2, 2);
+        buf.hline(-1, 0, 5, 3);
+        buf.vline(6, 0, 3, 3);
+        #[rustfmt::skip]
+        assert_eq!(mem, [
+            0, 0, 2, 0, 0, 0,
+            0, 0, 2, 0, 1, 1,
+            0, 0, 2, 0, 0, 0,
+            0, 0, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn lines_match_1bpp() {
+        let mut rng = Lcg(5);
+        let mut coord = || rng.coord(-30, 90);
+        for _ in 0..500 {
+            let (x0, y0, x1, y1) = (coord(), coord(), coord(), coord());
+            let mut bits = [0; 2 * 40];
+            PackedBitBuffer::new(&mut bits, 2).draw_line(x0, y0, x1, y1);
+            let mut bytes = [0; 64 * 40];
+            ByteBuffer::new(&mut bytes, 64).draw_line(x0, y0, x1, y1, 9);
+            for (i, &p) in bytes.iter().enumerate() {
+                let bit = bits[i / 32] & (1 << (i % 32)) != 0;
+                assert_eq!(p, if bit { 9 } else { 0 });
+            }
+        }
+    }
+
+    #[test]
+    fn fill_rect_clips() {
+        let mut mem = [0; 5 * 3];
+        ByteBuffer::new(&mut mem, 5).fill_rect(3, 1, -10, 10, 4);
+        #[rustfmt::skip]
+        assert_eq!(mem, [
+            0, 0, 0, 0, 0,
+            4, 4, 4, 4, 0,
+            4, 4, 4, 4, 0,
+        ]);
+    }
+
+    #[test]
+    fn blit_with_key() {
+        #[rustfmt::skip]
+        let sprite = [
+            1, 2, 3,
+            4, 0, 6,
+        ];
+        let mut mem = [9; 4 * 3];
+        let mut buf = ByteBuffer::new(&mut mem, 4);
+        buf.blit(2, 1, &sprite, 3, Some(0));
+        buf.blit(-2, -1, &sprite, 3, None);
+        buf.blit(4, 0, &sprite, 3, None);
+        buf.blit(0, i32::MIN, &sprite, 3, None);
+        #[rustfmt::skip]
+        assert_eq!(mem, [
+            6, 9, 9, 9,
+            9, 9, 1, 2,
+            9, 9, 4, 9,
+        ]);
+    }
+
+    #[test]
+    fn text() {
+        let mut mem = [0; 25 * 17];
+        let mut buf = ByteBuffer::new(&mut mem, 25);
+        buf.fill_rect(0, 0, 24, 16, 5);
+        let end = buf.draw_text(1, 1, "A\u{e9}", 1, None);
+        assert_eq!(end, 21);
+        buf.draw_char(21, 1, b'A', 1, Some(2));
+
+        let glyphs = FONT.as_glyph_slices();
+        for y in 0..17 {
+            for x in 0..25 {
+                let (cx, cy) = (x as i32 - 1, y as i32 - 1);
+                let expected = if cy < 0 || cx < 0 {
+                    5
+                } else {
+                    let (cell, col) = (cx as usize / 10, cx as usize % 10);
+                    let c = [b'A', b'?', b'A'][cell];
+                    let set = col < 8
+                        && glyphs[cy as usize][c as usize] & (1 << col) != 0;
+                    match (set, cell) {
+                        (true, _) => 1,
+                        (false, 2) => 2,
+                        (false, _) => 5,
+                    }
+                };
+                assert_eq!(mem[y * 25 + x], expected, "({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Adapters for the `embedded-graphics` ecosystem.
+//!
+//! With the `embedded-graphics` feature enabled, `PackedBitBuffer` and
+//! `ByteBuffer` implement `DrawTarget`, so fonts, primitives, and images from
+//! that ecosystem can draw straight into a framebuffer.
+//!
+//! `PackedBitBuffer` takes `BinaryColor`, with `On` as a 1 bit. `ByteBuffer`
+//! takes `Rgb222`, a color type matching the driver's `0bBB_GG_RR` pixel
+//! format. Other RGB colors can be converted to `Rgb222` with `From`, which
+//! rounds each channel to the nearest level.
+//!
+//! Drawing never fails: pixels outside the buffer are silently discarded, as
+//! `DrawTarget` requires.
+
+use core::convert::Infallible;
+
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{OriginDimensions, Size};
+use embedded_graphics_core::pixelcolor::raw::{RawData, RawU8};
+use embedded_graphics_core::pixelcolor::{
+    BinaryColor, PixelColor, Rgb888, RgbColor,
+};
+use embedded_graphics_core::primitives::Rectangle;
+use embedded_graphics_core::Pixel;
+
+use crate::{ByteBuffer, Mode, PackedBitBuffer};
+
+/// A color with two bits per channel, stored as `0bBB_GG_RR`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
+pub struct Rgb222(u8);
+
+impl Rgb222 {
+    /// Makes a color from 2-bit levels (0-3). Higher bits are ignored.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb222((r & 0b11) | (g & 0b11) << 2 | (b & 0b11) << 4)
+    }
+
+    /// Interprets a pixel value as a color. Bits above the bottom six are
+    /// ignored.
+    pub const fn from_pixel(pixel: u8) -> Self {
+        Rgb222(pixel & 0b11_11_11)
+    }
+
+    /// Gets the pixel value for this color.
+    pub const fn into_pixel(self) -> u8 {
+        self.0
+    }
+}
+
+impl PixelColor for Rgb222 {
+    type Raw = RawU8;
+}
+
+impl From<RawU8> for Rgb222 {
+    fn from(raw: RawU8) -> Self {
+        Rgb222::from_pixel(raw.into_inner())
+    }
+}
+
+impl From<Rgb222> for RawU8 {
+    fn from(c: Rgb222) -> Self {
+        RawU8::new(c.0)
+    }
+}
+
+impl RgbColor for Rgb222 {
+    fn r(&self) -> u8 {
+        self.0 & 0b11
+    }
+
+    fn g(&self) -> u8 {
+        (self.0 >> 2) & 0b11
+    }
+
+    fn b(&self) -> u8 {
+        (self.0 >> 4) & 0b11
+    }
+
+    const MAX_R: u8 = 3;
+    const MAX_G: u8 = 3;
+    const MAX_B: u8 = 3;
+
+    const BLACK: Self = Rgb222::new(0, 0, 0);
+    const RED: Self = Rgb222::new(3, 0, 0);
+    const GREEN: Self = Rgb222::new(0, 3, 0);
+    const BLUE: Self = Rgb222::new(0, 0, 3);
+    const YELLOW: Self = Rgb222::new(3, 3, 0);
+    const MAGENTA: Self = Rgb222::new(3, 0, 3);
+    const CYAN: Self = Rgb222::new(0, 3, 3);
+    const WHITE: Self = Rgb222::new(3, 3, 3);
+}
+
+impl From<Rgb888> for Rgb222 {
+    fn from(c: Rgb888) -> Self {
+        fn level(v: u8) -> u8 {
+            ((u16::from(v) * 3 + 127) / 255) as u8
+        }
+        Rgb222::new(level(c.r()), level(c.g()), level(c.b()))
+    }
+}
+
+impl From<Rgb222> for Rgb888 {
+    fn from(c: Rgb222) -> Self {
+        Rgb888::new(c.r() * 0x55, c.g() * 0x55, c.b() * 0x55)
+    }
+}
+
+impl From<BinaryColor> for Rgb222 {
+    fn from(c: BinaryColor) -> Self {
+        match c {
+            BinaryColor::Off => Rgb222::BLACK,
+            BinaryColor::On => Rgb222::WHITE,
+        }
+    }
+}
+
+/// Finds the inclusive corners of `area`, or `None` if it's empty.
+fn corners(area: &Rectangle) -> Option<(i32, i32, i32, i32)> {
+    let br = area.bottom_right()?;
+    Some((area.top_left.x, area.top_left.y, br.x, br.y))
+}
+
+fn mode(color: BinaryColor) -> Mode {
+    match color {
+        BinaryColor::Off => Mode::Clear,
+        BinaryColor::On => Mode::Set,
+    }
+}
+
+impl OriginDimensions for PackedBitBuffer<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.width() as u32, self.height() as u32)
+    }
+}
+
+impl DrawTarget for PackedBitBuffer<'_> {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            self.set_pixel(p.x, p.y, mode(color));
+        }
+        Ok(())
+    }
+
+    fn fill_solid(
+        &mut self,
+        area: &Rectangle,
+        color: Self::Color,
+    ) -> Result<(), Self::Error> {
+        if let Some((x0, y0, x1, y1)) = corners(area) {
+            self.fill_rect(x0, y0, x1, y1, mode(color));
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        match color {
+            BinaryColor::Off => PackedBitBuffer::clear(self),
+            BinaryColor::On => {
+                for word in self.mem.iter_mut() {
+                    *word = !0
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for ByteBuffer<'_> {
+    fn size(&self) -> Size {
+        Size::new(self.width() as u32, self.height() as u32)
+    }
+}
+
+impl DrawTarget for ByteBuffer<'_> {
+    type Color = Rgb222;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            self.set_pixel(p.x, p.y, color.0);
+        }
+        Ok(())
+    }
+
+    fn fill_solid(
+        &mut self,
+        area: &Rectangle,
+        color: Self::Color,
+    ) -> Result<(), Self::Error> {
+        if let Some((x0, y0, x1, y1)) = corners(area) {
+            self.fill_rect(x0, y0, x1, y1, color.0);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+        ByteBuffer::clear(self, color.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_graphics_core::geometry::Point;
+    use embedded_graphics_core::Drawable;
+
+    #[test]
+    fn bits_from_pixels_and_fills() {
+        let mut mem = [0; 2 * 4];
+        let mut buf = PackedBitBuffer::new(&mut mem, 2);
+        assert_eq!(buf.size(), Size::new(64, 4));
+
+        Pixel(Point::new(33, 1), BinaryColor::On)
+            .draw(&mut buf)
+            .unwrap();
+        // Off the edge; ignored.
+        Pixel(Point::new(-1, 0), BinaryColor::On)
+            .draw(&mut buf)
+            .unwrap();
+        buf.fill_solid(
+            &Rectangle::new(Point::new(30, 3), Size::new(4, 1)),
+            BinaryColor::On,
+        )
+        .unwrap();
+        assert_eq!(mem, [0, 0, 0, 0b10, 0, 0, 0xC000_0000, 0b11]);
+
+        let mut buf = PackedBitBuffer::new(&mut mem, 2);
+        DrawTarget::clear(&mut buf, BinaryColor::On).unwrap();
+        buf.fill_solid(
+            &Rectangle::new(Point::new(1, 0), Size::new(62, 4)),
+            BinaryColor::Off,
+        )
+        .unwrap();
+        // Empty rectangles draw nothing.
+        buf.fill_solid(&Rectangle::zero(), BinaryColor::Off)
+            .unwrap();
+        for row in mem.chunks(2) {
+            assert_eq!(row, [1, 0x8000_0000]);
+        }
+    }
+
+    #[test]
+    fn bytes_from_pixels_and_fills() {
+        let mut mem = [0; 4 * 3];
+        let mut buf = ByteBuffer::new(&mut mem, 4);
+        assert_eq!(buf.size(), Size::new(4, 3));
+
+        DrawTarget::clear(&mut buf, Rgb222::BLUE).unwrap();
+        buf.fill_solid(
+            &Rectangle::new(Point::new(2, 1), Size::new(5, 5)),
+            Rgb888::new(250, 90, 0).into(),
+        )
+        .unwrap();
+        Pixel(Point::new(0, 2), Rgb222::WHITE)
+            .draw(&mut buf)
+            .unwrap();
+        #[rustfmt::skip]
+        assert_eq!(mem, [
+            0x30, 0x30, 0x30, 0x30,
+            0x30, 0x30, 0x07, 0x07,
+            0x3f, 0x30, 0x07, 0x07,
+        ]);
+    }
+
+    #[test]
+    fn color_conversions() {
+        for pixel in 0..64 {
+            let c = Rgb222::from_pixel(pixel);
+            assert_eq!(Rgb222::from(Rgb888::from(c)), c);
+            assert_eq!(Rgb222::from(RawU8::from(c)), c);
+        }
+        assert_eq!(Rgb222::from(BinaryColor::On), Rgb222::WHITE);
+    }
+}
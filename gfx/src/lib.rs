@@ -2,13 +2,39 @@
 //!
 //! This module is deliberately architecture-independent to allow for testing on
 //! the host.
+//!
+//! The drawing algorithms are designed around the Cortex-M bit-band alias,
+//! which lets us set a pixel in a packed bitmap with a single store. Other
+//! platforms don't have bit-banding, so `PackedBitBuffer` also has a portable
+//! backend that manipulates the packed words with masks. It's selected
+//! automatically when not building for bare metal, and produces identical
+//! results.
+//!
+//! Rectangles, ellipses, polygons, and flood fill for `PackedBitBuffer` live in
+//! the `shape` module, and blits with raster operations in `blit`. For
+//! 8-bit-per-pixel buffers, see `ByteBuffer`. Both buffer types can be used
+//! as `embedded-graphics` draw targets by enabling the feature of that name.
 
 #![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 pub mod bit;
+pub mod blit;
+pub mod byte;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded_graphics;
+pub mod shape;
+#[cfg(test)]
+mod test_util;
+
+pub use blit::{BitImage, Rop};
+pub use byte::ByteBuffer;
+pub use shape::Mode;
 
 use bit::BandBit;
+use core::cmp::{max, min};
+use core::convert::TryFrom;
 use core::mem::swap;
+use core::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 
 /// A 1-bit-per-pixel framebuffer represented with 32 bits packed into each
 /// word.
@@ -35,6 +61,16 @@ impl<'a> PackedBitBuffer<'a> {
         self.mem
     }
 
+    /// Width of the buffer in pixels.
+    pub fn width(&self) -> usize {
+        self.stride * 32
+    }
+
+    /// Height of the buffer in pixels.
+    pub fn height(&self) -> usize {
+        self.mem.len().checked_div(self.stride).unwrap_or(0)
+    }
+
     /// Borrows a packed buffer as its bit-band alias.
     ///
     /// # Panics
@@ -53,9 +89,142 @@ impl<'a> PackedBitBuffer<'a> {
             *word = 0
         }
     }
+
+    /// Draws a line, clipped to the bounds of the buffer. See `draw_line`.
+    ///
+    /// On bare metal, this draws through the bit-band alias, and so panics if
+    /// the buffer is not in the bit-band target region. Elsewhere, it uses the
+    /// portable backend.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        if BIT_BAND {
+            self.as_bits().draw_line(x0, y0, x1, y1)
+        } else {
+            let stride = self.stride * 32;
+            draw_line_in(x0, y0, x1, y1, &mut PackedPixels(self.mem), stride)
+        }
+    }
+
+    /// Draws a line without clipping. See `draw_line_unclipped`.
+    ///
+    /// The choice of backend is the same as for `draw_line`.
+    pub fn draw_line_unclipped(
+        &mut self,
+        x0: usize,
+        y0: usize,
+        x1: usize,
+        y1: usize,
+    ) {
+        if BIT_BAND {
+            self.as_bits().draw_line_unclipped(x0, y0, x1, y1)
+        } else {
+            let stride = self.stride * 32;
+            let mut pixels = PackedPixels(self.mem);
+            draw_line_unclipped_in(x0, y0, x1, y1, &mut pixels, stride)
+        }
+    }
+}
+
+/// Whether `PackedBitBuffer` should draw using bit-banding. We assume that's
+/// available on all bare-metal targets, because those are Cortex-M4 for now.
+const BIT_BAND: bool = cfg!(target_os = "none");
+
+/// Pixel storage that the line drawing algorithms can draw into, addressed by
+/// pixel index.
+trait Pixels {
+    type Cursor: Cursor;
+
+    /// Number of pixels in the storage.
+    fn pixel_count(&self) -> usize;
+
+    /// Makes a cursor pointing at pixel `index`.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    fn cursor(&mut self, index: usize) -> Self::Cursor;
+}
+
+/// A position within some `Pixels`, at which we can set the pixel.
+trait Cursor {
+    /// Sets the pixel at the cursor.
+    ///
+    /// # Safety
+    ///
+    /// The cursor must be in bounds of the `Pixels` that produced it, and that
+    /// `Pixels` must not have been otherwise accessed since.
+    unsafe fn plot(&mut self);
+
+    /// Moves the cursor by `delta` pixels.
+    ///
+    /// # Safety
+    ///
+    /// The resulting cursor must be in bounds of the `Pixels` that produced it.
+    unsafe fn advance(&mut self, delta: isize);
+}
+
+impl Pixels for [BandBit] {
+    type Cursor = *mut BandBit;
+
+    fn pixel_count(&self) -> usize {
+        self.len()
+    }
+
+    fn cursor(&mut self, index: usize) -> Self::Cursor {
+        &mut self[index]
+    }
+}
+
+impl Cursor for *mut BandBit {
+    unsafe fn plot(&mut self) {
+        **self = BandBit::from(true)
+    }
+
+    unsafe fn advance(&mut self, delta: isize) {
+        *self = self.offset(delta)
+    }
+}
+
+/// Packed words viewed as pixels, with pixel `i` in bit `i % 32` of word `i /
+/// 32` -- the same arrangement the bit-band alias produces.
+struct PackedPixels<'a>(&'a mut [u32]);
+
+impl<'a> Pixels for PackedPixels<'a> {
+    type Cursor = PackedCursor;
+
+    fn pixel_count(&self) -> usize {
+        self.0.len() * 32
+    }
+
+    fn cursor(&mut self, index: usize) -> Self::Cursor {
+        assert!(index < self.pixel_count());
+        PackedCursor {
+            words: self.0.as_mut_ptr(),
+            index: index as isize,
+        }
+    }
+}
+
+struct PackedCursor {
+    words: *mut u32,
+    index: isize,
+}
+
+impl Cursor for PackedCursor {
+    unsafe fn plot(&mut self) {
+        *self.words.offset(self.index >> 5) |= 1 << (self.index & 31)
+    }
+
+    unsafe fn advance(&mut self, delta: isize) {
+        self.index += delta
+    }
 }
 
 impl<'a> BitBuffer<'a> {
+    /// Draws a line, clipped to the bounds of the buffer. See `draw_line`.
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        draw_line(x0, y0, x1, y1, self.mem, self.stride)
+    }
+
     pub fn draw_line_unclipped(
         &mut self,
         x0: usize,
@@ -119,30 +288,87 @@ impl XDirection for Right {
 /// `out` will be offset by `dx*x_adj` and by `dy*width_px` to find the last
 /// pixel in the line. All addresses along the line between the two points must
 /// be in the bounds of the mutable buffer we're writing into.
-unsafe fn draw_line_unclipped_unchecked<X: XDirection, Y: YDirection>(
-    mut out: *mut BandBit,
+unsafe fn draw_line_unclipped_unchecked<X, Y, C>(
+    out: C,
     dx: usize,
     dy: usize,
     width_px: usize,
-) {
+) where
+    X: XDirection,
+    Y: YDirection,
+    C: Cursor,
+{
     let (dmajor, dminor) = Y::dmajor_dminor(dx, dy);
+    let (dmajor, dminor) = (dmajor as isize, dminor as isize);
+    draw_line_unchecked::<X, Y, C, _>(
+        out,
+        dmajor as usize,
+        dmajor,
+        dminor,
+        dminor * 2 - dmajor,
+        width_px,
+    )
+}
+
+/// Integer type for the error term of the line drawing algorithm.
+///
+/// This is `isize` for any line that fits in a buffer, but clipped lines can
+/// have far-off endpoints, and their slopes may need `i64` to represent
+/// exactly.
+trait ErrorTerm:
+    Copy + PartialOrd + Add<Output = Self> + AddAssign + SubAssign
+{
+    const ZERO: Self;
+}
+
+impl ErrorTerm for isize {
+    const ZERO: isize = 0;
+}
+
+impl ErrorTerm for i64 {
+    const ZERO: i64 = 0;
+}
+
+/// Core of the line drawing algorithm. Sets the pixel at `out`, and then takes
+/// `steps` steps along the major axis, setting a pixel at each.
+///
+/// `dmajor` and `dminor` give the slope of the line, and `error` the initial
+/// value of the error term. Starting partway along a line requires the error
+/// term at that point, which is how `draw_line` implements clipping.
+///
+/// # Safety
+///
+/// All addresses visited along the line must be in the bounds of the mutable
+/// buffer we're writing into.
+unsafe fn draw_line_unchecked<X, Y, C, E>(
+    mut out: C,
+    steps: usize,
+    dmajor: E,
+    dminor: E,
+    mut error: E,
+    width_px: usize,
+) where
+    X: XDirection,
+    Y: YDirection,
+    C: Cursor,
+    E: ErrorTerm,
+{
     let (major_step, minor_step) =
         Y::major_minor_step(width_px as isize, X::X_ADV);
 
-    let dminor2 = (dminor * 2) as isize;
-    let dmajor2 = (dmajor * 2) as isize;
-    let mut error = dminor2 - dmajor as isize;
+    let dminor2 = dminor + dminor;
+    let dmajor2 = dmajor + dmajor;
 
-    *out = BandBit::from(true);
+    out.plot();
 
-    for _ in 0..dmajor {
-        if error >= 0 {
-            out = out.offset(minor_step as isize);
+    for _ in 0..steps {
+        if error >= E::ZERO {
+            out.advance(minor_step);
             error -= dmajor2;
         }
         error += dminor2;
-        out = out.offset(major_step as isize);
-        *out = BandBit::from(true);
+        out.advance(major_step);
+        out.plot();
     }
 }
 
@@ -158,11 +384,23 @@ unsafe fn draw_line_unclipped_unchecked<X: XDirection, Y: YDirection>(
 ///
 /// If either coordinate falls outside the buffer.
 pub fn draw_line_unclipped(
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+    buf: &mut [BandBit],
+    stride: usize,
+) {
+    draw_line_unclipped_in(x0, y0, x1, y1, buf, stride)
+}
+
+/// Implementation of `draw_line_unclipped` for any kind of `Pixels`.
+fn draw_line_unclipped_in<P: Pixels + ?Sized>(
     mut x0: usize,
     mut y0: usize,
     mut x1: usize,
     mut y1: usize,
-    buf: &mut [BandBit],
+    buf: &mut P,
     stride: usize,
 ) {
     // Flip things as necessary to ensure that we draw horizontal or
@@ -182,25 +420,26 @@ pub fn draw_line_unclipped(
 
     let start_offset = compute_offset(x0, y0, stride);
     assert!(
-        start_offset < buf.len() && compute_offset(x1, y1, stride) < buf.len()
+        start_offset < buf.pixel_count()
+            && compute_offset(x1, y1, stride) < buf.pixel_count()
     );
 
     let dx = x1 as isize - x0 as isize; // may be negative
     let dy = y1 - y0; // nonnegative
 
-    let out = &mut buf[start_offset];
+    let out = buf.cursor(start_offset);
 
     if dx > 0 {
         let dx = dx as usize;
         if dx > dy {
             unsafe {
-                draw_line_unclipped_unchecked::<Right, Horizontal>(
+                draw_line_unclipped_unchecked::<Right, Horizontal, _>(
                     out, dx, dy, stride,
                 )
             }
         } else {
             unsafe {
-                draw_line_unclipped_unchecked::<Right, Vertical>(
+                draw_line_unclipped_unchecked::<Right, Vertical, _>(
                     out, dx, dy, stride,
                 )
             }
@@ -209,13 +448,13 @@ pub fn draw_line_unclipped(
         let dx = -dx as usize;
         if dx as usize > dy {
             unsafe {
-                draw_line_unclipped_unchecked::<Left, Horizontal>(
+                draw_line_unclipped_unchecked::<Left, Horizontal, _>(
                     out, dx, dy, stride,
                 )
             }
         } else {
             unsafe {
-                draw_line_unclipped_unchecked::<Left, Vertical>(
+                draw_line_unclipped_unchecked::<Left, Vertical, _>(
                     out, dx, dy, stride,
                 )
             }
@@ -223,9 +462,319 @@ pub fn draw_line_unclipped(
     }
 }
 
+/// Draws a line from `(x0, y0)` to `(x1, y1)` by setting pixels, clipped to
+/// the bounds of the buffer. Either or both ends of the line may be outside the
+/// buffer, and the coordinates may be negative.
+///
+/// `buf` and `stride` are as for `draw_line_unclipped`. The buffer's height is
+/// the number of complete rows in `buf`.
+///
+/// Clipping is exact: the pixels set are precisely those that would be set by
+/// drawing the same line unclipped on an infinitely large buffer. The line is
+/// clipped in the parameter space of the line algorithm, Liang-Barsky style:
+/// we find the range of steps along the major axis that fall within the buffer,
+/// and start drawing at the first with the appropriate error term. This means
+/// that lines that zoom off the edge of the display don't wobble, as they would
+/// if we rounded intersection points with the edges.
+pub fn draw_line(
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    buf: &mut [BandBit],
+    stride: usize,
+) {
+    draw_line_in(x0, y0, x1, y1, buf, stride)
+}
+
+/// Implementation of `draw_line` for any kind of `Pixels`.
+fn draw_line_in<P: Pixels + ?Sized>(
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    buf: &mut P,
+    stride: usize,
+) {
+    if stride == 0 {
+        return;
+    }
+    let height = buf.pixel_count() / stride;
+
+    // Most lines lie entirely within the buffer, and the unclipped algorithm
+    // draws the same pixels without any setup.
+    let fits =
+        |c: i32, limit: usize| usize::try_from(c).is_ok_and(|c| c < limit);
+    if fits(x0, stride)
+        && fits(x1, stride)
+        && fits(y0, height)
+        && fits(y1, height)
+    {
+        draw_line_unclipped_in(
+            x0 as usize,
+            y0 as usize,
+            x1 as usize,
+            y1 as usize,
+            buf,
+            stride,
+        );
+    } else {
+        draw_line_clipped_in(x0, y0, x1, y1, buf, stride, height);
+    }
+}
+
+/// Clipping half of `draw_line_in`, for lines that may leave the buffer, which
+/// is `height` rows of `stride` pixels.
+fn draw_line_clipped_in<P: Pixels + ?Sized>(
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    buf: &mut P,
+    stride: usize,
+    height: usize,
+) {
+    let width = stride as i64;
+    let height = height as i64;
+
+    // Normalize the line as `draw_line_unclipped` does, so that we produce the
+    // same pixels: draw top to bottom.
+    let (mut x0, mut y0, mut x1, mut y1) =
+        (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+    if y0 > y1 {
+        swap(&mut y0, &mut y1);
+        swap(&mut x0, &mut x1);
+    }
+
+    let dx = x1 - x0; // may be negative
+    let dy = y1 - y0; // nonnegative
+    let right = dx > 0;
+    let x_sign = if right { 1 } else { -1 };
+    let horizontal = dx.abs() > dy;
+
+    // Describe the line in terms of its major and minor axes, as (origin,
+    // direction, limit) triples.
+    let x_axis = (x0, x_sign, width);
+    let y_axis = (y0, 1, height);
+    let (major, minor, dmajor, dminor) = if horizontal {
+        (x_axis, y_axis, dx.abs(), dy)
+    } else {
+        (y_axis, x_axis, dy, dx.abs())
+    };
+
+    let (major_lo, major_hi) = axis_range(major);
+    let k_range = (max(0, major_lo), min(dmajor, major_hi));
+    // The line's minor offset stays within `0..=dminor`, so restricting the
+    // buffer's range to that doesn't change the result, but keeps the
+    // arithmetic below in bounds.
+    let (minor_lo, minor_hi) = axis_range(minor);
+    let minor_range = (max(0, minor_lo), min(dminor, minor_hi));
+
+    let (k_lo, k_hi, minor_k, error) =
+        match clip_steps(dmajor, dminor, k_range, minor_range) {
+            Some(setup) => setup,
+            // The line misses the buffer entirely.
+            None => return,
+        };
+
+    let major_start = major.0 + major.1 * k_lo;
+    let minor_start = minor.0 + minor.1 * minor_k;
+    let (x, y) = if horizontal {
+        (major_start, minor_start)
+    } else {
+        (minor_start, major_start)
+    };
+
+    let out = buf.cursor(y as usize * stride + x as usize);
+    let steps = (k_hi - k_lo) as usize;
+
+    // The error term never strays further from zero than `2 * dmajor`, so if
+    // that fits in an `isize`, the loop can use native integers.
+    let narrow = isize::try_from(dmajor * 2).is_ok();
+    // Safety: we've restricted the steps to those within the buffer.
+    unsafe {
+        if narrow {
+            draw_line_oriented(
+                right,
+                horizontal,
+                out,
+                steps,
+                dmajor as isize,
+                dminor as isize,
+                error as isize,
+                stride,
+            )
+        } else {
+            draw_line_oriented(
+                right, horizontal, out, steps, dmajor, dminor, error, stride,
+            )
+        }
+    }
+}
+
+/// Integer type for the clipping setup in `clip_steps_in`.
+trait SetupTerm:
+    Copy
+    + Ord
+    + From<i64>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+    /// Divides by positive `d`, rounding toward positive infinity.
+    fn div_ceil(self, d: Self) -> Self;
+
+    /// Converts a result known to fit back to `i64`.
+    fn to_i64(self) -> i64;
+}
+
+impl SetupTerm for i64 {
+    fn div_ceil(self, d: Self) -> Self {
+        -((-self).div_euclid(d))
+    }
+
+    fn to_i64(self) -> i64 {
+        self
+    }
+}
+
+impl SetupTerm for i128 {
+    fn div_ceil(self, d: Self) -> Self {
+        -((-self).div_euclid(d))
+    }
+
+    fn to_i64(self) -> i64 {
+        self as i64
+    }
+}
+
+/// Restricts the steps of a line in `k_range` (inclusive) to those whose minor
+/// axis offsets fall within `minor_range` (inclusive). Returns the first and
+/// last such steps, along with the minor offset and error term at the first,
+/// or `None` if there are none.
+///
+/// `k_range` and `minor_range` must be within `0..=dmajor` and `0..=dminor`
+/// respectively.
+fn clip_steps(
+    dmajor: i64,
+    dminor: i64,
+    k_range: (i64, i64),
+    minor_range: (i64, i64),
+) -> Option<(i64, i64, i64, i64)> {
+    // With the ranges restricted, the products below are within
+    // `4 * dmajor * (dmajor + 1)`, which fits in an `i64` unless the line is
+    // enormous. `i128` is a lot slower, especially without a hardware divide.
+    if dmajor <= 1 << 30 {
+        clip_steps_in::<i64>(dmajor, dminor, k_range, minor_range)
+    } else {
+        clip_steps_in::<i128>(dmajor, dminor, k_range, minor_range)
+    }
+}
+
+/// Implementation of `clip_steps` using `W` for intermediate results.
+fn clip_steps_in<W: SetupTerm>(
+    dmajor: i64,
+    dminor: i64,
+    (k_lo, k_hi): (i64, i64),
+    (minor_lo, minor_hi): (i64, i64),
+) -> Option<(i64, i64, i64, i64)> {
+    if minor_lo > minor_hi {
+        return None;
+    }
+
+    // The pixel at step `k` along the major axis (where `0 <= k <= dmajor`)
+    // has been displaced along the minor axis by
+    //
+    //     minor(k) = floor((2 * dminor * k + dmajor) / (2 * dmajor))
+    //
+    // which is nondecreasing in `k`. So the steps that land inside the buffer
+    // form a contiguous range, which we can find by solving for `k` at each
+    // edge.
+    let w = W::from;
+    let (dmajor, dminor) = (w(dmajor), w(dminor));
+    let (dmajor2, dminor2) = (dmajor + dmajor, dminor + dminor);
+    let (mut k_lo, mut k_hi) = (w(k_lo), w(k_hi));
+    let (minor_lo, minor_hi) = (w(minor_lo), w(minor_hi));
+    let (zero, one) = (w(0), w(1));
+
+    if dminor != zero {
+        // minor(k) >= minor_lo
+        let k = (dmajor2 * minor_lo - dmajor).div_ceil(dminor2);
+        k_lo = max(k_lo, k);
+        // minor(k) <= minor_hi
+        let k = (dmajor2 * (minor_hi + one) - dmajor).div_ceil(dminor2) - one;
+        k_hi = min(k_hi, k);
+    }
+
+    if k_lo > k_hi {
+        return None;
+    }
+
+    // Work out where we start, and the state of the algorithm at that point.
+    let minor_k = if dmajor == zero {
+        zero
+    } else {
+        (dminor2 * k_lo + dmajor) / dmajor2
+    };
+    let error = dminor2 * (k_lo + one) - dmajor - dmajor2 * minor_k;
+
+    Some((
+        k_lo.to_i64(),
+        k_hi.to_i64(),
+        minor_k.to_i64(),
+        error.to_i64(),
+    ))
+}
+
+/// Calls `draw_line_unchecked` for a line heading right or left, whose major
+/// axis is horizontal or vertical.
+///
+/// # Safety
+///
+/// As for `draw_line_unchecked`.
+#[allow(clippy::too_many_arguments)]
+unsafe fn draw_line_oriented<C: Cursor, E: ErrorTerm>(
+    right: bool,
+    horizontal: bool,
+    out: C,
+    steps: usize,
+    dmajor: E,
+    dminor: E,
+    error: E,
+    stride: usize,
+) {
+    match (right, horizontal) {
+        (true, true) => draw_line_unchecked::<Right, Horizontal, _, _>(
+            out, steps, dmajor, dminor, error, stride,
+        ),
+        (true, false) => draw_line_unchecked::<Right, Vertical, _, _>(
+            out, steps, dmajor, dminor, error, stride,
+        ),
+        (false, true) => draw_line_unchecked::<Left, Horizontal, _, _>(
+            out, steps, dmajor, dminor, error, stride,
+        ),
+        (false, false) => draw_line_unchecked::<Left, Vertical, _, _>(
+            out, steps, dmajor, dminor, error, stride,
+        ),
+    }
+}
+
+/// Given an axis as an `(origin, direction, limit)` triple, finds the range of
+/// offsets `t` (inclusive) for which `origin + direction * t` is in
+/// `0..limit`.
+fn axis_range((origin, direction, limit): (i64, i64, i64)) -> (i64, i64) {
+    if direction > 0 {
+        (-origin, limit - 1 - origin)
+    } else {
+        (origin - (limit - 1), origin)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::Lcg;
 
     fn check_line(
         buf: &[BandBit],
@@ -304,4 +853,206 @@ mod tests {
         let mut buf = [BandBit::from(false); 10 * 10];
         draw_line_unclipped(0, 10, 0, 10, &mut buf, 10);
     }
+
+    /// Reference for clipped line drawing: draws the line unclipped into a
+    /// buffer with `margin` pixels of extra space on all sides, and then crops
+    /// it to `width` x `height`.
+    fn reference_line(
+        (x0, y0, x1, y1): (i32, i32, i32, i32),
+        width: usize,
+        height: usize,
+        margin: usize,
+    ) -> Vec<bool> {
+        let stride = width + 2 * margin;
+        let mut big =
+            vec![BandBit::from(false); stride * (height + 2 * margin)];
+        let m = margin as i32;
+        draw_line_unclipped(
+            (x0 + m) as usize,
+            (y0 + m) as usize,
+            (x1 + m) as usize,
+            (y1 + m) as usize,
+            &mut big,
+            stride,
+        );
+        let mut out = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                out.push(bool::from(big[(y + margin) * stride + x + margin]));
+            }
+        }
+        out
+    }
+
+    fn check_clipped(line: (i32, i32, i32, i32), width: usize, height: usize) {
+        let margin = [line.0, line.1, line.2, line.3]
+            .iter()
+            .map(|c| c.unsigned_abs() as usize)
+            .max()
+            .unwrap();
+        let expected = reference_line(line, width, height, margin);
+
+        let mut buf = vec![BandBit::from(false); width * height];
+        draw_line(line.0, line.1, line.2, line.3, &mut buf, width);
+
+        for (i, (&e, &p)) in expected.iter().zip(&buf).enumerate() {
+            assert_eq!(
+                e,
+                bool::from(p),
+                "line {:?}: pixel at ({}, {})",
+                line,
+                i % width,
+                i / width
+            );
+        }
+    }
+
+    #[test]
+    fn clipped_exhaustive() {
+        // Every line with endpoints in or near a small buffer.
+        const W: i32 = 7;
+        const H: i32 = 5;
+        const M: i32 = 4;
+        for x0 in -M..W + M {
+            for y0 in -M..H + M {
+                for x1 in -M..W + M {
+                    for y1 in -M..H + M {
+                        check_clipped((x0, y0, x1, y1), W as usize, H as usize);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_long_lines() {
+        // Long lines at a variety of slopes, most of which only clip the
+        // corner of the buffer. Coordinates come from a simple LCG so that the
+        // test is repeatable.
+        let mut rng = Lcg(1);
+        let mut coord = || rng.coord(-300, 300);
+        for _ in 0..2000 {
+            let line = (coord(), coord(), coord(), coord());
+            check_clipped(line, 16, 12);
+        }
+    }
+
+    #[test]
+    fn in_bounds_lines_match_unclipped() {
+        // `draw_line` skips clipping for these, so check both that shortcut
+        // and the clipping it skips against the baseline.
+        const W: usize = 16;
+        const H: usize = 12;
+        let mut rng = Lcg(3);
+        for _ in 0..2000 {
+            let (x0, x1) =
+                (rng.coord(0, W as i32 - 1), rng.coord(0, W as i32 - 1));
+            let (y0, y1) =
+                (rng.coord(0, H as i32 - 1), rng.coord(0, H as i32 - 1));
+            let mut expected = [BandBit::from(false); W * H];
+            draw_line_unclipped(
+                x0 as usize,
+                y0 as usize,
+                x1 as usize,
+                y1 as usize,
+                &mut expected,
+                W,
+            );
+            let expected = pack(&expected);
+
+            let mut buf = [BandBit::from(false); W * H];
+            draw_line(x0, y0, x1, y1, &mut buf, W);
+            assert_eq!(pack(&buf), expected, "{:?}", (x0, y0, x1, y1));
+
+            let mut buf = [BandBit::from(false); W * H];
+            draw_line_clipped_in(x0, y0, x1, y1, &mut buf[..], W, H);
+            assert_eq!(pack(&buf), expected, "{:?}", (x0, y0, x1, y1));
+        }
+    }
+
+    #[test]
+    fn clipped_extreme_coordinates() {
+        let mut buf = [BandBit::from(false); 8 * 4];
+        draw_line(i32::MIN, 2, i32::MAX, 2, &mut buf, 8);
+        check_line(&buf, 8, |_, y| y == 2);
+
+        let mut buf = [BandBit::from(false); 8 * 8];
+        draw_line(i32::MIN, i32::MIN, i32::MAX, i32::MAX, &mut buf, 8);
+        check_line(&buf, 8, |x, y| x == y);
+
+        let mut buf = [BandBit::from(false); 8 * 8];
+        draw_line(i32::MAX, i32::MIN, i32::MIN, i32::MAX, &mut buf, 8);
+        check_line(&buf, 8, |_, _| false);
+
+        // Either side of the switch to wider arithmetic for the setup.
+        for &half in &[1 << 29, (1 << 29) + 1] {
+            let mut buf = [BandBit::from(false); 8 * 8];
+            draw_line(-half, 1 - half, half, half + 1, &mut buf, 8);
+            check_line(&buf, 8, |x, y| x + 1 == y);
+        }
+    }
+
+    /// Packs a bit-band buffer into words, as seen through the bit-band target
+    /// region.
+    fn pack(bits: &[BandBit]) -> Vec<u32> {
+        bits.chunks(32)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &b)| u32::from(bool::from(b)) << i)
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Checks that the bit-band and portable backends produce the same
+    /// pixels for `line`, both clipped and (if it's in bounds) unclipped.
+    fn check_backends(line: (i32, i32, i32, i32), stride: usize, rows: usize) {
+        let (x0, y0, x1, y1) = line;
+        let width = (stride * 32) as i32;
+        let in_bounds = [x0, x1].iter().all(|&x| x >= 0 && x < width)
+            && [y0, y1].iter().all(|&y| y >= 0 && y < rows as i32);
+
+        let mut bits = vec![BandBit::from(false); stride * 32 * rows];
+        let mut words = vec![0; stride * rows];
+        draw_line(x0, y0, x1, y1, &mut bits, stride * 32);
+        PackedBitBuffer::new(&mut words, stride).draw_line(x0, y0, x1, y1);
+        assert_eq!(pack(&bits), words, "clipped line {:?}", line);
+
+        if in_bounds {
+            let (x0, y0, x1, y1) =
+                (x0 as usize, y0 as usize, x1 as usize, y1 as usize);
+            let mut bits = vec![BandBit::from(false); stride * 32 * rows];
+            let mut words = vec![0; stride * rows];
+            draw_line_unclipped(x0, y0, x1, y1, &mut bits, stride * 32);
+            PackedBitBuffer::new(&mut words, stride)
+                .draw_line_unclipped(x0, y0, x1, y1);
+            assert_eq!(pack(&bits), words, "unclipped line {:?}", line);
+        }
+    }
+
+    #[test]
+    fn backends_agree() {
+        // Endpoints near the edges of a two-word-wide buffer, and near the
+        // boundary between the words, where the packed backend is most likely
+        // to go wrong.
+        let xs: Vec<i32> = (-3..4).chain(28..36).chain(60..68).collect();
+        for &x0 in &xs {
+            for y0 in -2..7 {
+                for &x1 in &xs {
+                    for y1 in -2..7 {
+                        check_backends((x0, y0, x1, y1), 2, 5);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_empty_buffer() {
+        let mut buf = [];
+        draw_line(0, 0, 10, 10, &mut buf, 0);
+        draw_line(0, 0, 10, 10, &mut buf, 10);
+    }
 }
@@ -0,0 +1,1046 @@
+//! Filled and outlined shapes for `PackedBitBuffer`.
+//!
+//! All shapes are clipped to the bounds of the buffer, and combine with its
+//! existing contents according to a `Mode`.
+//!
+//! Shapes are built out of horizontal spans, which are drawn a word at a time
+//! using masks. Each outline is derived from the same spans as the matching
+//! fill: a pixel is on the outline if it's in the fill, but one of its four
+//! neighbors is not. So an outline always lies exactly on the edge of its fill,
+//! and every pixel of a shape is drawn exactly once, which means that drawing a
+//! shape twice with `Mode::Xor` restores the original contents.
+//!
+//! Like `draw_line`, coordinates are signed, and corners and endpoints are
+//! inclusive.
+
+use core::cmp::{max, min};
+
+use crate::PackedBitBuffer;
+
+/// How a drawing operation combines with the existing contents of a buffer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// Sets pixels to 1.
+    Set,
+    /// Clears pixels to 0.
+    Clear,
+    /// Inverts pixels.
+    Xor,
+}
+
+impl Mode {
+    /// Applies this mode to the bits of `word` selected by `mask`.
+    fn apply(self, word: &mut u32, mask: u32) {
+        match self {
+            Mode::Set => *word |= mask,
+            Mode::Clear => *word &= !mask,
+            Mode::Xor => *word ^= mask,
+        }
+    }
+}
+
+/// A horizontal run of pixels within a row, from `.0` to `.1` inclusive.
+type Span = (i32, i32);
+
+/// An entry in the work stack used by `flood_fill`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FillSpan {
+    left: i32,
+    right: i32,
+    y: i32,
+    /// Direction we were moving (+1 down, -1 up) when we found this span, so
+    /// the row on the other side has already been filled.
+    dy: i32,
+}
+
+/// Error produced by `flood_fill` when it runs out of stack. Part of the region
+/// may not have been filled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StackFull;
+
+impl<'a> PackedBitBuffer<'a> {
+    /// Reads the pixel at `(x, y)`. Pixels outside the buffer read as 0.
+    pub fn get_pixel(&self, x: i32, y: i32) -> bool {
+        if self.contains(x, y) {
+            let word = self.mem[y as usize * self.stride + x as usize / 32];
+            word & (1 << (x & 31)) != 0
+        } else {
+            false
+        }
+    }
+
+    /// Changes the pixel at `(x, y)`, if it's within the buffer.
+    pub fn set_pixel(&mut self, x: i32, y: i32, mode: Mode) {
+        self.span(y, x, x, mode)
+    }
+
+    /// Changes the pixels of row `y` from `x0` to `x1` inclusive. The
+    /// endpoints may be given in either order.
+    pub fn span(&mut self, y: i32, x0: i32, x1: i32, mode: Mode) {
+        if y < 0 || y as usize >= self.height() {
+            return;
+        }
+        let left = max(min(x0, x1), 0);
+        let right = min(max(x0, x1) as i64, self.width() as i64 - 1);
+        if i64::from(left) > right {
+            return;
+        }
+        let (left, right) = (left as usize, right as usize);
+
+        let row = &mut self.mem[y as usize * self.stride..][..self.stride];
+        let (first, last) = (left / 32, right / 32);
+        let first_mask = !0 << (left % 32);
+        let last_mask = !0 >> (31 - right % 32);
+        if first == last {
+            mode.apply(&mut row[first], first_mask & last_mask);
+        } else {
+            mode.apply(&mut row[first], first_mask);
+            for word in &mut row[first + 1..last] {
+                mode.apply(word, !0);
+            }
+            mode.apply(&mut row[last], last_mask);
+        }
+    }
+
+    /// Fills the rectangle with opposite corners `(x0, y0)` and `(x1, y1)`.
+    pub fn fill_rect(
+        &mut self,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+        mode: Mode,
+    ) {
+        let (top, bottom) = self.clip_rows(min(y0, y1), max(y0, y1));
+        for y in top..=bottom {
+            self.span(y, x0, x1, mode)
+        }
+    }
+
+    /// Outlines the rectangle with opposite corners `(x0, y0)` and `(x1, y1)`.
+    pub fn rect(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, mode: Mode) {
+        let (top, bottom) = (min(y0, y1), max(y0, y1));
+        let span = (min(x0, x1), max(x0, x1));
+        self.outline_rows(top, bottom, |_| Some(span), mode)
+    }
+
+    /// Fills the axis-aligned ellipse centered on `(cx, cy)` with horizontal
+    /// radius `rx` and vertical radius `ry`.
+    pub fn fill_ellipse(
+        &mut self,
+        cx: i32,
+        cy: i32,
+        rx: u16,
+        ry: u16,
+        mode: Mode,
+    ) {
+        ellipse_half_widths(rx, ry, |y, w| {
+            let (left, right) = (cx.saturating_sub(w), cx.saturating_add(w));
+            self.span(cy.saturating_sub(y), left, right, mode);
+            if y != 0 {
+                self.span(cy.saturating_add(y), left, right, mode);
+            }
+        })
+    }
+
+    /// Outlines the axis-aligned ellipse centered on `(cx, cy)` with
+    /// horizontal radius `rx` and vertical radius `ry`.
+    pub fn ellipse(&mut self, cx: i32, cy: i32, rx: u16, ry: u16, mode: Mode) {
+        // Half-widths are generated from the top row inward, but outlining a
+        // row requires its neighbors on both sides. So we hold each row until
+        // we've seen the next one.
+        let mut outer = None;
+        let mut pending = None;
+        ellipse_half_widths(rx, ry, |y, w| {
+            if let Some((py, pw)) = pending {
+                self.ellipse_row(cx, cy, (py, pw), outer, Some(w), mode);
+                outer = Some(pw);
+            }
+            pending = Some((y, w));
+        });
+        // The last row is the center row, whose inner neighbor is its outer
+        // neighbor's mirror image.
+        if let Some(row) = pending {
+            self.ellipse_row(cx, cy, row, outer, outer, mode);
+        }
+    }
+
+    /// Fills the circle centered on `(cx, cy)` with radius `r`.
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, r: u16, mode: Mode) {
+        self.fill_ellipse(cx, cy, r, r, mode)
+    }
+
+    /// Outlines the circle centered on `(cx, cy)` with radius `r`.
+    pub fn circle(&mut self, cx: i32, cy: i32, r: u16, mode: Mode) {
+        self.ellipse(cx, cy, r, r, mode)
+    }
+
+    /// Fills the triangle with vertices `p0`, `p1`, and `p2`, given as `(x, y)`
+    /// pairs. See `fill_polygon`.
+    pub fn fill_triangle(
+        &mut self,
+        p0: (i32, i32),
+        p1: (i32, i32),
+        p2: (i32, i32),
+        mode: Mode,
+    ) {
+        self.fill_polygon(&[p0, p1, p2], mode)
+    }
+
+    /// Outlines the triangle with vertices `p0`, `p1`, and `p2`, given as
+    /// `(x, y)` pairs. See `polygon`.
+    pub fn triangle(
+        &mut self,
+        p0: (i32, i32),
+        p1: (i32, i32),
+        p2: (i32, i32),
+        mode: Mode,
+    ) {
+        self.polygon(&[p0, p1, p2], mode)
+    }
+
+    /// Fills a convex polygon, given its vertices as `(x, y)` pairs in either
+    /// winding order.
+    ///
+    /// The edges of the filled area are exactly the pixels that `draw_line`
+    /// would draw for the polygon's edges, so a wireframe drawn over the fill
+    /// lines up with it.
+    ///
+    /// Each row is filled from its leftmost edge pixel to its rightmost. For a
+    /// concave polygon, this fills in the concavities.
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], mode: Mode) {
+        if let Some((top, bottom)) = polygon_rows(points) {
+            let (top, bottom) = self.clip_rows(top, bottom);
+            for y in top..=bottom {
+                if let Some((left, right)) = polygon_span(points, y) {
+                    self.span(y, left, right, mode)
+                }
+            }
+        }
+    }
+
+    /// Outlines a convex polygon, given its vertices as `(x, y)` pairs in
+    /// either winding order.
+    ///
+    /// Like the other outlines, this is the edge of the corresponding fill --
+    /// see `fill_polygon`. It's similar to drawing the edges with `draw_line`,
+    /// except that it may include extra pixels to keep the outline 4-connected,
+    /// and never draws a pixel twice.
+    pub fn polygon(&mut self, points: &[(i32, i32)], mode: Mode) {
+        if let Some((top, bottom)) = polygon_rows(points) {
+            self.outline_rows(top, bottom, |y| polygon_span(points, y), mode)
+        }
+    }
+
+    /// Flood-fills the 4-connected region containing `(x, y)`.
+    ///
+    /// The region consists of pixels with the same value as the one at
+    /// `(x, y)`, and is filled according to `mode`:
+    ///
+    /// - `Mode::Set` fills a region of 0 pixels with 1s.
+    /// - `Mode::Clear` fills a region of 1 pixels with 0s.
+    /// - `Mode::Xor` inverts the region, whatever its value.
+    ///
+    /// If the starting pixel is outside the buffer, or already has the value
+    /// `mode` would give it, nothing happens.
+    ///
+    /// The fill works a span at a time, and needs somewhere to keep track of
+    /// spans it hasn't explored yet. The caller provides this as `stack`. The
+    /// amount needed depends on the complexity of the region; a few dozen
+    /// entries is enough for simple shapes. If the stack runs out, the fill
+    /// completes as much of the region as it can, and returns `StackFull`.
+    pub fn flood_fill(
+        &mut self,
+        x: i32,
+        y: i32,
+        mode: Mode,
+        stack: &mut [FillSpan],
+    ) -> Result<(), StackFull> {
+        if !self.contains(x, y) {
+            return Ok(());
+        }
+        let target = self.get_pixel(x, y);
+        match (mode, target) {
+            (Mode::Set, true) | (Mode::Clear, false) => return Ok(()),
+            _ => (),
+        }
+
+        // Every pixel we fill stops matching `target`, so it serves as its own
+        // "visited" mark.
+        let mut stack = SpanStack {
+            spans: stack,
+            len: 0,
+            overflowed: false,
+        };
+        // The seed row is explored downward; the row above it is explored
+        // upward as though we'd come from the seed.
+        for &(y, dy) in &[(y - 1, -1), (y, 1)] {
+            stack.push(FillSpan {
+                left: x,
+                right: x,
+                y,
+                dy,
+            });
+        }
+
+        while let Some(FillSpan { left, right, y, dy }) = stack.pop() {
+            if y < 0 || y as usize >= self.height() {
+                continue;
+            }
+            // Fill each run of matching pixels that overlaps `left..=right`,
+            // extending it as far as it goes in both directions.
+            let mut x = left;
+            while x <= right {
+                if self.get_pixel(x, y) != target {
+                    x += 1;
+                    continue;
+                }
+                let mut run_left = x;
+                while run_left > 0 && self.get_pixel(run_left - 1, y) == target
+                {
+                    run_left -= 1;
+                }
+                let mut run_right = x;
+                while self.contains(run_right + 1, y)
+                    && self.get_pixel(run_right + 1, y) == target
+                {
+                    run_right += 1;
+                }
+                self.span(y, run_left, run_right, mode);
+
+                // Keep going in the same direction. We only need to look back
+                // where the run overhangs the span we came from.
+                stack.push(FillSpan {
+                    left: run_left,
+                    right: run_right,
+                    y: y + dy,
+                    dy,
+                });
+                if run_left < left {
+                    stack.push(FillSpan {
+                        left: run_left,
+                        right: left - 1,
+                        y: y - dy,
+                        dy: -dy,
+                    });
+                }
+                if run_right > right {
+                    stack.push(FillSpan {
+                        left: right + 1,
+                        right: run_right,
+                        y: y - dy,
+                        dy: -dy,
+                    });
+                }
+                // The pixel after the run doesn't match, so skip it too.
+                x = run_right + 2;
+            }
+        }
+
+        if stack.overflowed {
+            Err(StackFull)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks whether `(x, y)` is within the buffer.
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width()
+            && (y as usize) < self.height()
+    }
+
+    /// Clips an inclusive range of rows to the buffer. The result is empty
+    /// (`top > bottom`) if none of the rows are in the buffer.
+    fn clip_rows(&self, top: i32, bottom: i32) -> (i32, i32) {
+        let last = min(self.height() as i64 - 1, i64::from(i32::MAX)) as i32;
+        (max(top, 0), min(bottom, last))
+    }
+
+    /// Outlines a shape occupying rows `top..=bottom`, whose spans are given by
+    /// `row`.
+    fn outline_rows(
+        &mut self,
+        top: i32,
+        bottom: i32,
+        row: impl Fn(i32) -> Option<Span>,
+        mode: Mode,
+    ) {
+        let (first, last) = self.clip_rows(top, bottom);
+        for y in first..=last {
+            if let Some(span) = row(y) {
+                let above = if y > top { row(y - 1) } else { None };
+                let below = if y < bottom { row(y + 1) } else { None };
+                self.outline_row(y, span, above, below, mode)
+            }
+        }
+    }
+
+    /// Draws the outline pixels of `span` in row `y`: those that don't have
+    /// neighbors on all four sides, given the spans of the neighboring rows.
+    fn outline_row(
+        &mut self,
+        y: i32,
+        span: Span,
+        above: Option<Span>,
+        below: Option<Span>,
+        mode: Mode,
+    ) {
+        let interior = match (above, below) {
+            (Some(a), Some(b)) => {
+                let left = max(span.0.saturating_add(1), max(a.0, b.0));
+                let right = min(span.1.saturating_sub(1), min(a.1, b.1));
+                if left <= right {
+                    Some((left, right))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        match interior {
+            None => self.span(y, span.0, span.1, mode),
+            Some((left, right)) => {
+                self.span(y, span.0, left - 1, mode);
+                self.span(y, right + 1, span.1, mode);
+            }
+        }
+    }
+
+    /// Outlines the two rows of an ellipse that are `y` rows from its center
+    /// and have half-width `w`. `outer` and `inner` are the half-widths of the
+    /// neighboring rows farther from and closer to the center, respectively.
+    fn ellipse_row(
+        &mut self,
+        cx: i32,
+        cy: i32,
+        (y, w): (i32, i32),
+        outer: Option<i32>,
+        inner: Option<i32>,
+        mode: Mode,
+    ) {
+        let span = |w: i32| (cx.saturating_sub(w), cx.saturating_add(w));
+        let (outer, inner) = (outer.map(span), inner.map(span));
+        self.outline_row(cy.saturating_sub(y), span(w), outer, inner, mode);
+        if y != 0 {
+            self.outline_row(cy.saturating_add(y), span(w), inner, outer, mode);
+        }
+    }
+}
+
+/// Generates the half-widths of the rows of an ellipse using the midpoint
+/// algorithm, calling `row(y, w)` to indicate that the row `y` rows from the
+/// center spans `w` pixels either side of it. Rows are generated once each,
+/// from `y = ry` down to zero.
+fn ellipse_half_widths(rx: u16, ry: u16, mut row: impl FnMut(i32, i32)) {
+    // Work in i64 with all terms scaled by 4, so that the half-pixel offsets of
+    // the midpoints are integers.
+    let (rx2, ry2) = (i64::from(rx).pow(2), i64::from(ry).pow(2));
+    let (mut x, mut y) = (0i64, i64::from(ry));
+    // Partial derivatives of the ellipse equation (divided by 2) at (x, y).
+    let (mut dx, mut dy) = (0, 2 * rx2 * y);
+
+    // Region 1: the slope is shallower than -1, so we step along x, and row y
+    // ends when we step down.
+    let mut d = 4 * ry2 - 4 * rx2 * y + rx2;
+    while dx < dy {
+        x += 1;
+        dx += 2 * ry2;
+        if d < 0 {
+            d += 4 * (dx + ry2);
+        } else {
+            row(y as i32, (x - 1) as i32);
+            y -= 1;
+            dy -= 2 * rx2;
+            d += 4 * (dx - dy + ry2);
+        }
+    }
+
+    // Region 2: the slope is steeper, so we step along y, and each row is a
+    // single step. The decision variable is recomputed at the new midpoint;
+    // its initial terms can exceed 64 bits, though the sum doesn't.
+    let mut d = (i128::from(ry2) * i128::from(2 * x + 1).pow(2)
+        + 4 * i128::from(rx2) * i128::from(y - 1).pow(2)
+        - 4 * i128::from(rx2) * i128::from(ry2)) as i64;
+    loop {
+        if y == 0 {
+            // For very flat ellipses, region 1 can reach the center row long
+            // before the end of it. We know where it ends, though.
+            row(0, i32::from(rx));
+            break;
+        }
+        row(y as i32, x as i32);
+        y -= 1;
+        dy -= 2 * rx2;
+        if d > 0 {
+            d += 4 * (rx2 - dy);
+        } else {
+            x += 1;
+            dx += 2 * ry2;
+            d += 4 * (dx - dy + rx2);
+        }
+    }
+}
+
+/// Finds the range of rows occupied by a polygon, or `None` if it has no
+/// vertices.
+fn polygon_rows(points: &[(i32, i32)]) -> Option<Span> {
+    let top = points.iter().map(|p| p.1).min()?;
+    let bottom = points.iter().map(|p| p.1).max()?;
+    Some((top, bottom))
+}
+
+/// Finds the span of a convex polygon in row `y`: from the leftmost pixel of
+/// any of its edges in that row, to the rightmost.
+fn polygon_span(points: &[(i32, i32)], y: i32) -> Option<Span> {
+    let edges = points.iter().zip(points.iter().cycle().skip(1));
+    edges.filter_map(|(&p0, &p1)| edge_span(p0, p1, y)).fold(
+        None,
+        |acc, (left, right)| match acc {
+            None => Some((left, right)),
+            Some((l, r)) => Some((min(l, left), max(r, right))),
+        },
+    )
+}
+
+/// Finds the pixels in row `y` that `draw_line` would draw for the line from
+/// `p0` to `p1`, if any.
+fn edge_span(p0: (i32, i32), p1: (i32, i32), y: i32) -> Option<Span> {
+    // Normalize the line as `draw_line` does: top to bottom.
+    let (p0, p1) = if p0.1 > p1.1 { (p1, p0) } else { (p0, p1) };
+    let (x0, y0) = (i64::from(p0.0), i64::from(p0.1));
+    let (x1, y1) = (i64::from(p1.0), i64::from(p1.1));
+    let t = i64::from(y) - y0;
+
+    let dx = x1 - x0;
+    let dy = y1 - y0;
+    if t < 0 || t > dy {
+        return None;
+    }
+    let x_sign = if dx > 0 { 1 } else { -1 };
+
+    // See `clip_steps_in` for the derivation: the pixel at step `k` along the
+    // major axis is displaced along the minor axis by
+    //
+    //     minor(k) = floor((2 * dminor * k + dmajor) / (2 * dmajor))
+    let (k_lo, k_hi) = if dx.abs() > dy {
+        // Mostly horizontal, so this row may contain several steps: those with
+        // minor(k) == t.
+        let (k_lo, k_hi, _, _) =
+            super::clip_steps(dx.abs(), dy, (0, dx.abs()), (t, t))?;
+        (k_lo, k_hi)
+    } else {
+        // Mostly vertical, so this row contains exactly one step, `t`. We
+        // express its displacement along x as a single-step range.
+        let (_, _, minor, _) =
+            super::clip_steps(dy, dx.abs(), (t, t), (0, dx.abs()))?;
+        (minor, minor)
+    };
+
+    let (a, b) = (x0 + x_sign * k_lo, x0 + x_sign * k_hi);
+    Some((min(a, b) as i32, max(a, b) as i32))
+}
+
+/// Bounded stack of `FillSpan`s for `flood_fill`, which remembers whether it
+/// has had to drop anything.
+struct SpanStack<'s> {
+    spans: &'s mut [FillSpan],
+    len: usize,
+    overflowed: bool,
+}
+
+impl<'s> SpanStack<'s> {
+    fn push(&mut self, span: FillSpan) {
+        if let Some(slot) = self.spans.get_mut(self.len) {
+            *slot = span;
+            self.len += 1;
+        } else {
+            self.overflowed = true;
+        }
+    }
+
+    fn pop(&mut self) -> Option<FillSpan> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            Some(self.spans[self.len])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Lcg;
+
+    const MODES: [Mode; 3] = [Mode::Set, Mode::Clear, Mode::Xor];
+
+    /// A test image: a `width`-by-`height` buffer of pixels, stored packed.
+    struct Image {
+        mem: Vec<u32>,
+        stride: usize,
+    }
+
+    impl Image {
+        fn new(words: usize, height: usize) -> Self {
+            Image {
+                mem: vec![0; words * height],
+                stride: words,
+            }
+        }
+
+        /// Fills the image with a repeatable jumble of pixels.
+        fn scramble(mut self) -> Self {
+            let mut rng = Lcg(1);
+            for word in &mut self.mem {
+                *word = rng.step();
+            }
+            self
+        }
+
+        fn buf(&mut self) -> PackedBitBuffer<'_> {
+            PackedBitBuffer::new(&mut self.mem, self.stride)
+        }
+
+        fn width(&self) -> i32 {
+            self.stride as i32 * 32
+        }
+
+        fn height(&self) -> i32 {
+            (self.mem.len() / self.stride) as i32
+        }
+
+        fn get(&self, x: i32, y: i32) -> bool {
+            let word = self.mem[y as usize * self.stride + x as usize / 32];
+            word & (1 << (x % 32)) != 0
+        }
+
+        /// Lists the coordinates of the set pixels.
+        fn pixels(&self) -> Vec<(i32, i32)> {
+            let mut out = vec![];
+            for y in 0..self.height() {
+                for x in 0..self.width() {
+                    if self.get(x, y) {
+                        out.push((x, y))
+                    }
+                }
+            }
+            out
+        }
+    }
+
+    /// Something that can be drawn on a buffer.
+    type Shape = dyn Fn(&mut PackedBitBuffer, Mode);
+
+    /// Draws `shape` in `mode` on a blank image.
+    fn draw(shape: &Shape, mode: Mode) -> Image {
+        let mut image = Image::new(3, 48);
+        shape(&mut image.buf(), mode);
+        image
+    }
+
+    /// A selection of shapes, as (fill, outline) pairs, all of which fit
+    /// entirely within a 96x48 image.
+    fn shapes() -> Vec<(Box<Shape>, Box<Shape>)> {
+        let mut shapes: Vec<(Box<Shape>, Box<Shape>)> = vec![
+            (
+                Box::new(|b, m| b.fill_rect(70, 40, 3, 5, m)),
+                Box::new(|b, m| b.rect(70, 40, 3, 5, m)),
+            ),
+            (
+                Box::new(|b, m| b.fill_rect(10, 10, 10, 20, m)),
+                Box::new(|b, m| b.rect(10, 10, 10, 20, m)),
+            ),
+            (
+                Box::new(|b, m| b.fill_triangle((5, 3), (90, 20), (40, 45), m)),
+                Box::new(|b, m| b.triangle((5, 3), (90, 20), (40, 45), m)),
+            ),
+            (
+                Box::new(|b, m| {
+                    b.fill_polygon(&[(30, 2), (60, 8), (62, 30), (20, 44)], m)
+                }),
+                Box::new(|b, m| {
+                    b.polygon(&[(30, 2), (60, 8), (62, 30), (20, 44)], m)
+                }),
+            ),
+        ];
+        for &(rx, ry) in &[(0, 0), (0, 5), (7, 0), (1, 1), (20, 20), (40, 9)] {
+            shapes.push((
+                Box::new(move |b, m| b.fill_ellipse(48, 23, rx, ry, m)),
+                Box::new(move |b, m| b.ellipse(48, 23, rx, ry, m)),
+            ));
+        }
+        for &r in &[2, 3, 10, 23] {
+            shapes.push((
+                Box::new(move |b, m| b.fill_circle(48, 24, r, m)),
+                Box::new(move |b, m| b.circle(48, 24, r, m)),
+            ));
+        }
+        shapes
+    }
+
+    #[test]
+    fn span_matches_reference() {
+        for &mode in &MODES {
+            for x0 in -3..67 {
+                for x1 in -3..67 {
+                    let mut image = Image::new(2, 3).scramble();
+                    let before = Image {
+                        mem: image.mem.clone(),
+                        stride: 2,
+                    };
+                    image.buf().span(1, x0, x1, mode);
+                    for y in 0..3 {
+                        for x in 0..64 {
+                            let inside =
+                                y == 1 && x >= min(x0, x1) && x <= max(x0, x1);
+                            let old = before.get(x, y);
+                            let expected = match (inside, mode) {
+                                (false, _) => old,
+                                (true, Mode::Set) => true,
+                                (true, Mode::Clear) => false,
+                                (true, Mode::Xor) => !old,
+                            };
+                            assert_eq!(
+                                image.get(x, y),
+                                expected,
+                                "{:?} {}..={}: pixel ({}, {})",
+                                mode,
+                                x0,
+                                x1,
+                                x,
+                                y
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixels() {
+        let mut image = Image::new(2, 4);
+        let mut buf = image.buf();
+        assert_eq!((buf.width(), buf.height()), (64, 4));
+        buf.set_pixel(33, 2, Mode::Set);
+        buf.set_pixel(64, 2, Mode::Set);
+        buf.set_pixel(-1, 2, Mode::Set);
+        assert!(buf.get_pixel(33, 2));
+        assert!(!buf.get_pixel(32, 2));
+        assert!(!buf.get_pixel(64, 2));
+        buf.set_pixel(33, 2, Mode::Xor);
+        assert_eq!(image.pixels(), vec![]);
+    }
+
+    #[test]
+    fn rects_clip() {
+        let mut image = Image::new(2, 8);
+        image.buf().fill_rect(10, 3, -5, -5, Mode::Set);
+        let expected: Vec<_> = (0..=3)
+            .flat_map(|y| (0..=10).map(move |x| (x, y)))
+            .collect();
+        assert_eq!(image.pixels(), expected);
+
+        let mut image = Image::new(2, 8);
+        image.buf().rect(-1, 2, 100, 100, Mode::Set);
+        let expected: Vec<_> = (0..64).map(|x| (x, 2)).collect();
+        assert_eq!(image.pixels(), expected);
+
+        let mut image = Image::new(2, 8);
+        image.buf().fill_rect(
+            i32::MIN,
+            i32::MIN,
+            i32::MAX,
+            i32::MAX,
+            Mode::Set,
+        );
+        assert!(image.mem.iter().all(|&w| w == !0));
+        image
+            .buf()
+            .rect(i32::MIN, i32::MIN, i32::MAX, i32::MAX, Mode::Xor);
+        assert!(image.mem.iter().all(|&w| w == !0));
+    }
+
+    #[test]
+    fn xor_draws_each_pixel_once() {
+        for (fill, outline) in shapes() {
+            for shape in &[&fill, &outline] {
+                let set = draw(&***shape, Mode::Set);
+                let xor = draw(&***shape, Mode::Xor);
+                assert_eq!(set.mem, xor.mem);
+
+                let mut image = Image::new(3, 48).scramble();
+                let before = image.mem.clone();
+                shape(&mut image.buf(), Mode::Xor);
+                shape(&mut image.buf(), Mode::Xor);
+                assert_eq!(image.mem, before);
+
+                let mut image = set;
+                shape(&mut image.buf(), Mode::Clear);
+                assert_eq!(image.pixels(), vec![]);
+            }
+        }
+    }
+
+    #[test]
+    fn outline_is_edge_of_fill() {
+        for (fill, outline) in shapes() {
+            let fill = draw(&*fill, Mode::Set);
+            let outline = draw(&*outline, Mode::Set);
+            let filled = |x, y| {
+                x >= 0
+                    && y >= 0
+                    && x < fill.width()
+                    && y < fill.height()
+                    && fill.get(x, y)
+            };
+            let expected: Vec<_> = fill
+                .pixels()
+                .into_iter()
+                .filter(|&(x, y)| {
+                    !(filled(x - 1, y)
+                        && filled(x + 1, y)
+                        && filled(x, y - 1)
+                        && filled(x, y + 1))
+                })
+                .collect();
+            assert_eq!(outline.pixels(), expected);
+        }
+    }
+
+    /// Collects the half-widths of an ellipse, indexed by distance from the
+    /// center row.
+    fn half_widths(rx: u16, ry: u16) -> Vec<i32> {
+        let mut widths = vec![-1; usize::from(ry) + 1];
+        let mut next = i32::from(ry);
+        ellipse_half_widths(rx, ry, |y, w| {
+            assert_eq!(y, next, "rows out of order");
+            next -= 1;
+            widths[y as usize] = w;
+        });
+        assert_eq!(next, -1, "rows missing");
+        widths
+    }
+
+    #[test]
+    fn small_circles() {
+        assert_eq!(half_widths(0, 0), [0]);
+        assert_eq!(half_widths(1, 1), [1, 0]);
+        assert_eq!(half_widths(2, 2), [2, 2, 1]);
+        assert_eq!(half_widths(3, 3), [3, 3, 2, 1]);
+        assert_eq!(half_widths(5, 0), [5]);
+        assert_eq!(half_widths(0, 3), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ellipses_are_plausible() {
+        for &(rx, ry) in &[(1, 9), (9, 1), (13, 13), (100, 7), (7, 100)] {
+            let widths = half_widths(rx, ry);
+            assert_eq!(widths[0], i32::from(rx));
+            let (a, b) = (f64::from(rx), f64::from(ry));
+            for (y, pair) in widths.windows(2).enumerate() {
+                assert!(pair[1] <= pair[0], "{:?} not convex", (rx, ry));
+                // The outermost pixel of each row should be within a pixel of
+                // the ideal curve.
+                let (x, y) = (f64::from(pair[0]), y as f64);
+                let nearest = (0..=360 * 8)
+                    .map(|i| {
+                        let t = f64::from(i) / 8.0f64.to_degrees();
+                        (a * t.cos() - x).hypot(b * t.sin() - y)
+                    })
+                    .fold(f64::INFINITY, f64::min);
+                assert!(nearest < 1.0, "{:?} row {}", (rx, ry), y);
+            }
+        }
+    }
+
+    #[test]
+    fn extreme_ellipses() {
+        let mut image = Image::new(2, 8);
+        image.buf().fill_ellipse(32, 4, u16::MAX, 0, Mode::Set);
+        assert_eq!(image.mem[8..10], [!0, !0]);
+        // Entirely off the top of the buffer.
+        image
+            .buf()
+            .ellipse(i32::MAX, i32::MIN, u16::MAX, u16::MAX, Mode::Set);
+        // The buffer is deep inside this one, so the outline misses it.
+        image.buf().ellipse(0, 0, u16::MAX, u16::MAX, Mode::Set);
+        assert_eq!(image.pixels().len(), 64);
+        image
+            .buf()
+            .fill_ellipse(0, 0, u16::MAX, u16::MAX, Mode::Xor);
+        for (i, &word) in image.mem.iter().enumerate() {
+            assert_eq!(word, if i / 2 == 4 { 0 } else { !0 });
+        }
+    }
+
+    #[test]
+    fn right_triangle() {
+        let mut image = Image::new(1, 16);
+        image
+            .buf()
+            .fill_triangle((0, 0), (10, 0), (0, 10), Mode::Set);
+        let expected: Vec<_> = (0..=10)
+            .flat_map(|y| (0..=10 - y).map(move |x| (x, y)))
+            .collect();
+        assert_eq!(image.pixels(), expected);
+    }
+
+    #[test]
+    fn degenerate_polygons() {
+        let mut image = Image::new(1, 4);
+        image.buf().fill_polygon(&[], Mode::Set);
+        image.buf().polygon(&[], Mode::Set);
+        assert_eq!(image.pixels(), vec![]);
+        image.buf().polygon(&[(3, 2)], Mode::Set);
+        assert_eq!(image.pixels(), vec![(3, 2)]);
+        image.buf().fill_polygon(&[(3, 2), (-10, 2)], Mode::Xor);
+        assert_eq!(image.pixels(), vec![(0, 2), (1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn polygons_match_lines() {
+        // The fill in each row should run from the leftmost pixel drawn by
+        // `draw_line` for any edge, to the rightmost, for arbitrary (often
+        // clipped) triangles.
+        let mut rng = Lcg(7);
+        let mut coord = || rng.coord(-30, 90);
+        for _ in 0..500 {
+            let points =
+                [(coord(), coord()), (coord(), coord()), (coord(), coord())];
+            let mut fill = Image::new(2, 40);
+            fill.buf().fill_polygon(&points, Mode::Set);
+            // Draw the lines unclipped, in a larger image offset by
+            // (`DX`, `DY`).
+            const DX: i32 = 32;
+            const DY: i32 = 40;
+            let mut lines = Image::new(4, 136);
+            for i in 0..3 {
+                let (p0, p1) = (points[i], points[(i + 1) % 3]);
+                lines.buf().draw_line(
+                    p0.0 + DX,
+                    p0.1 + DY,
+                    p1.0 + DX,
+                    p1.1 + DY,
+                );
+            }
+            for y in 0..40 {
+                let xs = (0..64).filter(|&x| fill.get(x, y));
+                let actual = (xs.clone().min(), xs.max());
+
+                let xs = (0..128).filter(|&x| lines.get(x, y + DY));
+                let expected = match (xs.clone().min(), xs.max()) {
+                    (Some(l), Some(r)) if r - DX >= 0 && l - DX < 64 => {
+                        (Some(max(l - DX, 0)), Some(min(r - DX, 63)))
+                    }
+                    _ => (None, None),
+                };
+                assert_eq!(actual, expected, "{:?} row {}", points, y);
+            }
+        }
+    }
+
+    #[test]
+    fn flood_fill_regions() {
+        let mut stack = [FillSpan::default(); 16];
+
+        let mut image = Image::new(2, 16);
+        image.buf().rect(5, 2, 40, 10, Mode::Set);
+        image
+            .buf()
+            .flood_fill(10, 5, Mode::Set, &mut stack)
+            .unwrap();
+        let mut expected = Image::new(2, 16);
+        expected.buf().fill_rect(5, 2, 40, 10, Mode::Set);
+        assert_eq!(image.mem, expected.mem);
+
+        // Already set: nothing to do.
+        image
+            .buf()
+            .flood_fill(10, 5, Mode::Set, &mut stack)
+            .unwrap();
+        assert_eq!(image.mem, expected.mem);
+
+        image.buf().flood_fill(0, 0, Mode::Xor, &mut stack).unwrap();
+        assert!(image.mem.iter().all(|&w| w == !0));
+        image
+            .buf()
+            .flood_fill(63, 15, Mode::Clear, &mut stack)
+            .unwrap();
+        assert_eq!(image.pixels(), vec![]);
+
+        // Outside the buffer: nothing to do.
+        image
+            .buf()
+            .flood_fill(64, 0, Mode::Set, &mut stack)
+            .unwrap();
+        assert_eq!(image.pixels(), vec![]);
+    }
+
+    /// Reference flood fill, for a Set-mode fill starting on a 0 pixel.
+    fn reference_fill(image: &mut Image, x: i32, y: i32) {
+        let mut pending = vec![(x, y)];
+        while let Some((x, y)) = pending.pop() {
+            if x >= 0
+                && y >= 0
+                && x < image.width()
+                && y < image.height()
+                && !image.get(x, y)
+            {
+                image.buf().set_pixel(x, y, Mode::Set);
+                pending.extend(&[
+                    (x - 1, y),
+                    (x + 1, y),
+                    (x, y - 1),
+                    (x, y + 1),
+                ]);
+            }
+        }
+    }
+
+    #[test]
+    fn flood_fill_mazes() {
+        // Sparse random walls make for complicated, but usually connected,
+        // regions.
+        let mut rng = Lcg(3);
+        for _ in 0..20 {
+            let mut image = Image::new(2, 24);
+            for word in &mut image.mem {
+                for _ in 0..3 {
+                    let r = rng.step();
+                    *word |= r >> 16 & r >> 8 & r;
+                }
+            }
+            image.buf().set_pixel(1, 1, Mode::Clear);
+            let mut expected = Image {
+                mem: image.mem.clone(),
+                stride: 2,
+            };
+            reference_fill(&mut expected, 1, 1);
+
+            let mut partial = Image {
+                mem: image.mem.clone(),
+                stride: 2,
+            };
+            let result = partial.buf().flood_fill(
+                1,
+                1,
+                Mode::Set,
+                &mut [FillSpan::default(); 2],
+            );
+            assert_eq!(result, Err(StackFull));
+            // Whatever was filled should have been in the region.
+            for (p, e) in partial.mem.iter().zip(&expected.mem) {
+                assert_eq!(p & !e, 0);
+            }
+
+            let mut stack = vec![FillSpan::default(); 1000];
+            image.buf().flood_fill(1, 1, Mode::Set, &mut stack).unwrap();
+            assert_eq!(image.mem, expected.mem);
+        }
+    }
+}
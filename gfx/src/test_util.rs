@@ -0,0 +1,31 @@
+//! Helpers shared by the tests in this crate.
+
+/// Repeatable source of pseudorandom numbers, for tests that check many
+/// arbitrary cases against a reference.
+pub struct Lcg(pub u32);
+
+impl Lcg {
+    /// Advances the generator and returns its new state. The low bits cycle
+    /// quickly, so prefer the other methods for anything but filler.
+    pub fn step(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        (self.step() >> 8) % n
+    }
+
+    /// Returns a coordinate in `lo..=hi`.
+    pub fn coord(&mut self, lo: i32, hi: i32) -> i32 {
+        (self.step() >> 16) as i32 % (hi - lo + 1) + lo
+    }
+
+    /// Returns `n` words of random pixels.
+    pub fn words(&mut self, n: usize) -> Vec<u32> {
+        (0..n)
+            .map(|_| (self.step() >> 8) ^ (self.step() >> 8 << 16))
+            .collect()
+    }
+}
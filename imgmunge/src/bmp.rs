@@ -0,0 +1,104 @@
+//! Windows BMP reader.
+//!
+//! Only uncompressed (`BI_RGB`) images are supported, at 1, 4, 8, 24, or 32
+//! bits per pixel. That covers what most editors write when asked for a BMP
+//! without RLE. Both bottom-up and top-down row orders are accepted.
+
+use std::io::{self, Cursor};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::{invalid, Image, Rgb};
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: u32 = 40;
+const BI_RGB: u32 = 0;
+
+/// Parses a BMP image. `data` must start with the `BM` magic.
+pub fn read(data: &[u8]) -> io::Result<Image> {
+    if !data.starts_with(b"BM") {
+        return Err(invalid("not a BMP image"));
+    }
+    let mut input = Cursor::new(data);
+    input.set_position(10);
+    let data_offset = input.read_u32::<LittleEndian>()? as usize;
+
+    let header_size = input.read_u32::<LittleEndian>()?;
+    if header_size < INFO_HEADER_SIZE {
+        // OS/2 core headers use 16-bit dimensions. Nothing writes them
+        // anymore.
+        return Err(invalid("unsupported BMP header"));
+    }
+    let width = input.read_i32::<LittleEndian>()?;
+    let height = input.read_i32::<LittleEndian>()?;
+    let _planes = input.read_u16::<LittleEndian>()?;
+    let bpp = input.read_u16::<LittleEndian>()?;
+    let compression = input.read_u32::<LittleEndian>()?;
+    let _image_size = input.read_u32::<LittleEndian>()?;
+    let _x_ppm = input.read_i32::<LittleEndian>()?;
+    let _y_ppm = input.read_i32::<LittleEndian>()?;
+    let colors_used = input.read_u32::<LittleEndian>()? as usize;
+
+    if compression != BI_RGB {
+        return Err(invalid("compressed BMP images are not supported"));
+    }
+    // A negative height means rows are stored top to bottom.
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+    if width == 0 || height == 0 || width > 0xFFFF || height > 0xFFFF {
+        return Err(invalid("image dimensions out of range"));
+    }
+
+    let palette = match bpp {
+        1 | 4 | 8 => {
+            let count = if colors_used == 0 {
+                1 << bpp
+            } else {
+                colors_used.min(1 << bpp)
+            };
+            let start = FILE_HEADER_SIZE + header_size as usize;
+            let table = data
+                .get(start..start + count * 4)
+                .ok_or_else(|| invalid("BMP palette truncated"))?;
+            // Entries are stored as BGRX.
+            table.chunks(4).map(|e| [e[2], e[1], e[0]]).collect()
+        }
+        24 | 32 => Vec::new(),
+        _ => return Err(invalid("unsupported BMP bit depth")),
+    };
+
+    // Rows are padded out to a multiple of four bytes.
+    let row_bytes = (width * usize::from(bpp)).div_ceil(32) * 4;
+    let body = data
+        .get(data_offset..)
+        .filter(|b| b.len() >= row_bytes * height)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "BMP data truncated")
+        })?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let stored = if top_down { y } else { height - 1 - y };
+        let row = &body[stored * row_bytes..(stored + 1) * row_bytes];
+        for x in 0..width {
+            let px: Rgb = match bpp {
+                24 | 32 => {
+                    let o = x * usize::from(bpp / 8);
+                    [row[o + 2], row[o + 1], row[o]]
+                }
+                _ => {
+                    let bit = x * usize::from(bpp);
+                    let shift = 8 - usize::from(bpp) - bit % 8;
+                    let mask = (1 << bpp) - 1;
+                    let index = usize::from(row[bit / 8] >> shift) & mask;
+                    *palette
+                        .get(index)
+                        .ok_or_else(|| invalid("BMP index out of palette"))?
+                }
+            };
+            pixels.push(px);
+        }
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
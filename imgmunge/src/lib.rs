@@ -0,0 +1,734 @@
+//! Raster image converter for build-time assets.
+//!
+//! This library reads small images in simple formats -- Netpbm (PBM, PGM,
+//! PPM) and uncompressed BMP -- and produces Rust source declaring statics in
+//! the layouts expected by the `m4vga` rasterizers. It's intended to be called
+//! from a `build.rs`, with the output written into `OUT_DIR` and pulled in
+//! with `include!`.
+//!
+//! Each generator prefixes its declarations with a caller-chosen name, so
+//! several assets can share a module. Generators report the flash and RAM the
+//! asset will occupy, both as a comment in the output and as a `Cost` return
+//! value, so a build script can keep a running total.
+//!
+//! Colors are reduced to the driver's `0bBB_GG_RR` format using
+//! `m4vga::color`, so assets are rounded and dithered exactly as colors
+//! computed at runtime are.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use m4vga::color::{self, Color};
+
+mod bmp;
+mod pnm;
+
+/// An 8-bit-per-channel color, as `[r, g, b]`.
+pub type Rgb = [u8; 3];
+
+/// A decoded image, stored as rows of `Rgb` pixels from top to bottom.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    /// Wraps a row-major pixel vector. Panics if its length doesn't match the
+    /// dimensions.
+    pub fn new(width: usize, height: usize, pixels: Vec<Rgb>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Gets the pixel at `(x, y)`. Panics if out of bounds.
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        assert!(x < self.width);
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Reads an image from `input`, detecting its format from the first few
+/// bytes.
+pub fn read_image(mut input: impl Read) -> io::Result<Image> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    match data.get(..2) {
+        Some(b"BM") => bmp::read(&data),
+        Some([b'P', b'1'..=b'6']) => pnm::read(&data),
+        _ => Err(invalid("unrecognized image format")),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// How to reduce 8-bit channels to the driver's 2-bit levels.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dither {
+    /// Round each channel to the nearest level. Best for pixel art that was
+    /// drawn in the target palette.
+    None,
+    /// Apply a 4x4 ordered dither, using `m4vga::color::dither_level`.
+    /// Best for photos and gradients.
+    Bayer,
+}
+
+/// Where an asset should live at runtime.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Placement {
+    /// Leave the data in flash. Costs no RAM, but the rasterizers read flash
+    /// more slowly than SRAM, and compete with instruction fetch to do it.
+    Flash,
+    /// Copy the data into RAM at startup. It still occupies flash, to hold
+    /// the initial image.
+    Ram,
+}
+
+/// Memory occupied by a generated asset, in bytes.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cost {
+    pub flash: usize,
+    pub ram: usize,
+}
+
+impl Cost {
+    fn of(bytes: usize, placement: Placement) -> Self {
+        Cost {
+            flash: bytes,
+            ram: match placement {
+                Placement::Flash => 0,
+                Placement::Ram => bytes,
+            },
+        }
+    }
+}
+
+impl std::ops::Add for Cost {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Cost {
+            flash: self.flash + other.flash,
+            ram: self.ram + other.ram,
+        }
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "flash {} bytes, RAM {} bytes", self.flash, self.ram)
+    }
+}
+
+/// Converts a color to a pixel for position `(x, y)`.
+fn quantize(c: Rgb, x: usize, y: usize, dither: Dither) -> u8 {
+    match dither {
+        Dither::None => Color::rgb(c[0], c[1], c[2]),
+        Dither::Bayer => Color::dithered(c[0], c[1], c[2], x, y),
+    }
+    .to_pixel()
+}
+
+/// Approximate perceived brightness, 0-255.
+fn luma(c: Rgb) -> u8 {
+    ((u32::from(c[0]) * 299 + u32::from(c[1]) * 587 + u32::from(c[2]) * 114)
+        / 1000) as u8
+}
+
+/// Brightness of a pixel in driver format, 0-255.
+fn pixel_luma(p: u8) -> u8 {
+    let (r, g, b) = Color::from_pixel(p).to_rgb8();
+    luma([r, g, b])
+}
+
+/// Packs `bits`-wide values into words, least significant bits leftmost, as
+/// the rasterizers scan them out. Each row starts on a fresh word. Returns
+/// the stride in words and the packed data.
+fn pack_rows(
+    image: &Image,
+    bits: usize,
+    mut value: impl FnMut(usize, usize) -> u32,
+) -> (usize, Vec<u32>) {
+    let per_word = 32 / bits;
+    let stride = image.width.div_ceil(per_word);
+    let mut words = vec![0; stride * image.height];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let v = value(x, y);
+            debug_assert!(v >> bits == 0);
+            words[y * stride + x / per_word] |= v << (x % per_word * bits);
+        }
+    }
+    (stride, words)
+}
+
+/// Checks that `name` can prefix a Rust item name.
+fn check_name(name: &str) -> io::Result<()> {
+    let ok = name
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if ok {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not a valid identifier", name),
+        ))
+    }
+}
+
+/// Writes the comment and attribute lines that precede every asset static.
+fn write_preamble(
+    output: &mut impl Write,
+    name: &str,
+    description: &str,
+    cost: Cost,
+    placement: Placement,
+) -> io::Result<()> {
+    writeln!(output, "// {}: {}", name, description)?;
+    writeln!(output, "// {}", cost)?;
+    if placement == Placement::Ram {
+        writeln!(output, "#[link_section = \".data\"]")?;
+    }
+    Ok(())
+}
+
+fn write_words(output: &mut impl Write, words: &[u32]) -> io::Result<()> {
+    for chunk in words.chunks(8) {
+        write!(output, "   ")?;
+        for w in chunk {
+            write!(output, " 0x{:08x},", w)?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+fn write_dimensions(
+    output: &mut impl Write,
+    name: &str,
+    image: &Image,
+    stride: usize,
+) -> io::Result<()> {
+    writeln!(output, "pub const {}_WIDTH: usize = {};", name, image.width)?;
+    writeln!(
+        output,
+        "pub const {}_HEIGHT: usize = {};",
+        name, image.height
+    )?;
+    writeln!(output, "pub const {}_STRIDE: usize = {};", name, stride)?;
+    Ok(())
+}
+
+/// Converts `image` into a 1bpp bitmap for `m4vga::rast::bitmap_1`.
+///
+/// Emits `NAME` as a `[u32]` of packed rows, `NAME_WIDTH`, `NAME_HEIGHT`, and
+/// `NAME_STRIDE` (in words), and `NAME_CLUT`, a color lookup table word for
+/// the rasterizer.
+///
+/// If the image uses at most two colors (after rounding to the driver's
+/// palette), those become the CLUT, with the darker one as the background.
+/// Otherwise, pixels are thresholded on brightness and drawn in white on
+/// black.
+pub fn generate_bitmap_1(
+    image: &Image,
+    name: &str,
+    placement: Placement,
+    mut output: impl Write,
+) -> io::Result<Cost> {
+    check_name(name)?;
+
+    let mut colors = Vec::new();
+    for &c in &image.pixels {
+        let p = quantize(c, 0, 0, Dither::None);
+        if !colors.contains(&p) {
+            colors.push(p);
+            if colors.len() > 2 {
+                break;
+            }
+        }
+    }
+
+    let (clut, words, stride);
+    if colors.len() <= 2 {
+        colors.sort_by_key(|&p| pixel_luma(p));
+        let background = colors[0];
+        let foreground = *colors.last().unwrap();
+        clut = color::bitmap_1_clut(
+            Color::from_pixel(background),
+            Color::from_pixel(foreground),
+        );
+        let (s, w) = pack_rows(image, 1, |x, y| {
+            let p = quantize(image.get(x, y), 0, 0, Dither::None);
+            (p != background) as u32
+        });
+        stride = s;
+        words = w;
+    } else {
+        eprintln!("{}: more than two colors; thresholding on brightness", name);
+        clut = color::bitmap_1_clut(Color::BLACK, Color::WHITE);
+        let (s, w) =
+            pack_rows(image, 1, |x, y| (luma(image.get(x, y)) >= 128) as u32);
+        stride = s;
+        words = w;
+    }
+
+    let cost = Cost::of(words.len() * 4, placement);
+    eprintln!(
+        "{}: {}x{} bitmap_1, {}",
+        name, image.width, image.height, cost
+    );
+
+    write_dimensions(&mut output, name, image, stride)?;
+    writeln!(output, "pub const {}_CLUT: usize = 0x{:04x};", name, clut)?;
+    write_preamble(&mut output, name, "bitmap_1", cost, placement)?;
+    writeln!(output, "pub static {}: [u32; {}] = [", name, words.len())?;
+    write_words(&mut output, &words)?;
+    writeln!(output, "];")?;
+
+    Ok(cost)
+}
+
+/// Converts `image` into 8bpp pixels for `m4vga::rast::direct`.
+///
+/// Emits `NAME` as a `[u32]` holding four pixels per word, leftmost pixel in
+/// the least significant byte, plus `NAME_WIDTH`, `NAME_HEIGHT`, and
+/// `NAME_STRIDE` (in words). Rows are padded with black to a whole word.
+pub fn generate_direct(
+    image: &Image,
+    name: &str,
+    dither: Dither,
+    placement: Placement,
+    mut output: impl Write,
+) -> io::Result<Cost> {
+    check_name(name)?;
+
+    let (stride, words) = pack_rows(image, 8, |x, y| {
+        u32::from(quantize(image.get(x, y), x, y, dither))
+    });
+
+    let cost = Cost::of(words.len() * 4, placement);
+    eprintln!(
+        "{}: {}x{} direct, {}",
+        name, image.width, image.height, cost
+    );
+
+    write_dimensions(&mut output, name, image, stride)?;
+    write_preamble(&mut output, name, "direct 8bpp", cost, placement)?;
+    writeln!(output, "pub static {}: [u32; {}] = [", name, words.len())?;
+    write_words(&mut output, &words)?;
+    writeln!(output, "];")?;
+
+    Ok(cost)
+}
+
+/// Converts `image` into packed palette indices of `bits` bits each (1, 2,
+/// 4, or 8), for palettized rasterizers.
+///
+/// Emits `NAME_PALETTE`, a `[u8]` of pixels in order of first appearance;
+/// `NAME` as a `[u32]` of packed indices, leftmost in the least significant
+/// bits; and `NAME_WIDTH`, `NAME_HEIGHT`, and `NAME_STRIDE` (in words).
+///
+/// Fails if the image, after dithering, uses more colors than `bits` can
+/// index.
+pub fn generate_palettized(
+    image: &Image,
+    name: &str,
+    bits: usize,
+    dither: Dither,
+    placement: Placement,
+    mut output: impl Write,
+) -> io::Result<Cost> {
+    check_name(name)?;
+    if ![1, 2, 4, 8].contains(&bits) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "bits per index must be 1, 2, 4, or 8",
+        ));
+    }
+
+    let mut palette = Vec::new();
+    let mut lookup = HashMap::new();
+    let mut indices = Vec::with_capacity(image.pixels.len());
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let p = quantize(image.get(x, y), x, y, dither);
+            let index = *lookup.entry(p).or_insert_with(|| {
+                palette.push(p);
+                palette.len() - 1
+            });
+            indices.push(index as u32);
+        }
+    }
+    if palette.len() > 1 << bits {
+        return Err(invalid(&format!(
+            "{} colors will not fit in {}-bit indices",
+            palette.len(),
+            bits
+        )));
+    }
+
+    let (stride, words) =
+        pack_rows(image, bits, |x, y| indices[y * image.width + x]);
+
+    let cost = Cost::of(palette.len() + words.len() * 4, placement);
+    eprintln!(
+        "{}: {}x{} palettized, {} colors, {}",
+        name,
+        image.width,
+        image.height,
+        palette.len(),
+        cost
+    );
+
+    write_dimensions(&mut output, name, image, stride)?;
+    let description = format!("{}-bit palettized", bits);
+    write_preamble(&mut output, name, &description, cost, placement)?;
+    write!(
+        output,
+        "pub static {}_PALETTE: [u8; {}] = [",
+        name,
+        palette.len()
+    )?;
+    for p in &palette {
+        write!(output, "0x{:02x}, ", p)?;
+    }
+    writeln!(output, "];")?;
+    if placement == Placement::Ram {
+        writeln!(output, "#[link_section = \".data\"]")?;
+    }
+    writeln!(output, "pub static {}: [u32; {}] = [", name, words.len())?;
+    write_words(&mut output, &words)?;
+    writeln!(output, "];")?;
+
+    Ok(cost)
+}
+
+/// Produces a screen of `m4vga::rast::text_10x16::AChar` cells.
+///
+/// `background` gives one pixel per cell, so its dimensions set the number of
+/// columns and rows. Characters come from the lines of `text`; cells past the
+/// end of a line, or below the last line, are blank. Characters outside
+/// printable ASCII are replaced with `?`.
+///
+/// Foreground colors come from `foreground` if given, which must match the
+/// dimensions of `background`. Otherwise each cell gets black or white,
+/// whichever contrasts with its background.
+///
+/// Emits `NAME` as an `[AChar]`, plus `NAME_COLS` and `NAME_ROWS`.
+pub fn generate_text_screen(
+    background: &Image,
+    foreground: Option<&Image>,
+    text: &str,
+    name: &str,
+    placement: Placement,
+    mut output: impl Write,
+) -> io::Result<Cost> {
+    check_name(name)?;
+    if let Some(fg) = foreground {
+        if fg.width != background.width || fg.height != background.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "foreground and background images differ in size",
+            ));
+        }
+    }
+
+    let (cols, rows) = (background.width, background.height);
+    let lines: Vec<&[u8]> = text.lines().map(str::as_bytes).collect();
+
+    let cost = Cost::of(cols * rows * 4, placement);
+    eprintln!("{}: {}x{} text screen, {}", name, cols, rows, cost);
+    if lines.len() > rows || lines.iter().any(|l| l.len() > cols) {
+        eprintln!("{}: text clipped to {}x{}", name, cols, rows);
+    }
+
+    writeln!(output, "pub const {}_COLS: usize = {};", name, cols)?;
+    writeln!(output, "pub const {}_ROWS: usize = {};", name, rows)?;
+    write_preamble(&mut output, name, "text_10x16", cost, placement)?;
+    writeln!(
+        output,
+        "pub static {}: [m4vga::rast::text_10x16::AChar; {}] = [",
+        name,
+        cols * rows
+    )?;
+    for y in 0..rows {
+        for x in 0..cols {
+            let c = lines
+                .get(y)
+                .and_then(|l| l.get(x))
+                .map(|&c| {
+                    if c == b' ' || c.is_ascii_graphic() {
+                        c
+                    } else {
+                        b'?'
+                    }
+                })
+                .unwrap_or(b' ');
+            let bg = quantize(background.get(x, y), x, y, Dither::None);
+            let fg = match foreground {
+                Some(image) => quantize(image.get(x, y), x, y, Dither::None),
+                None if pixel_luma(bg) < 128 => Color::WHITE.to_pixel(),
+                None => Color::BLACK.to_pixel(),
+            };
+            writeln!(
+                output,
+                "    m4vga::rast::text_10x16::AChar::from_ascii_char(0x{:02x})\
+                 .with_foreground(0x{:02x}).with_background(0x{:02x}),",
+                c, fg, bg
+            )?;
+        }
+    }
+    writeln!(output, "];")?;
+
+    Ok(cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(data: &[u8]) -> Image {
+        read_image(data).unwrap()
+    }
+
+    const BLACK: Rgb = [0, 0, 0];
+    const WHITE: Rgb = [255, 255, 255];
+
+    #[test]
+    fn ascii_netpbm() {
+        let pbm = read(b"P1\n# comment\n3 2\n010\n1 1 0\n");
+        assert_eq!(
+            pbm,
+            Image::new(3, 2, vec![WHITE, BLACK, WHITE, BLACK, BLACK, WHITE])
+        );
+
+        let pgm = read(b"P2 2 1 4 0 4");
+        assert_eq!(pgm, Image::new(2, 1, vec![BLACK, WHITE]));
+
+        let ppm = read(b"P3 2 1 255 255 0 0 0 0 255");
+        assert_eq!(ppm, Image::new(2, 1, vec![[255, 0, 0], [0, 0, 255]]));
+    }
+
+    #[test]
+    fn binary_netpbm() {
+        let pbm = read(b"P4 10 1\n\x80\x40");
+        let mut expected = vec![WHITE; 10];
+        expected[0] = BLACK;
+        expected[9] = BLACK;
+        assert_eq!(pbm, Image::new(10, 1, expected));
+
+        let pgm = read(b"P5 2 1 65535\n\xff\xff\x00\x00");
+        assert_eq!(pgm, Image::new(2, 1, vec![WHITE, BLACK]));
+
+        let ppm = read(b"P6 1 1 255\n\x01\x02\x03");
+        assert_eq!(ppm, Image::new(1, 1, vec![[1, 2, 3]]));
+
+        assert!(read_image(&b"P6 2 1 255\n\x01\x02\x03"[..]).is_err());
+    }
+
+    #[test]
+    fn netpbm_dimensions_checked_before_allocating() {
+        let kind = |data: &[u8]| read_image(data).unwrap_err().kind();
+        assert_eq!(kind(b"P2 65536 1 255 0"), io::ErrorKind::InvalidData);
+        // Plausible dimensions, but nowhere near enough data for them.
+        assert_eq!(kind(b"P3 65535 65535 255 0"), io::ErrorKind::UnexpectedEof);
+        assert_eq!(kind(b"P4 65535 65535\n\0"), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            kind(b"P6 65535 65535 65535\n\0"),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    /// Builds a BMP file around an info header and pixel data.
+    fn bmp(
+        width: i32,
+        height: i32,
+        bpp: u16,
+        palette: &[u8],
+        body: &[u8],
+    ) -> Vec<u8> {
+        let offset = 14 + 40 + palette.len() as u32;
+        let mut f = Vec::new();
+        f.extend_from_slice(b"BM");
+        f.extend_from_slice(&(offset + body.len() as u32).to_le_bytes());
+        f.extend_from_slice(&[0; 4]);
+        f.extend_from_slice(&offset.to_le_bytes());
+        f.extend_from_slice(&40u32.to_le_bytes());
+        f.extend_from_slice(&width.to_le_bytes());
+        f.extend_from_slice(&height.to_le_bytes());
+        f.extend_from_slice(&1u16.to_le_bytes());
+        f.extend_from_slice(&bpp.to_le_bytes());
+        f.extend_from_slice(&[0; 24]);
+        f.extend_from_slice(palette);
+        f.extend_from_slice(body);
+        f
+    }
+
+    #[test]
+    fn bmp_24bit_bottom_up() {
+        #[rustfmt::skip]
+        let body = [
+            // Bottom row: blue, green, padding.
+            255, 0, 0, 0, 255, 0, 0, 0,
+            // Top row: red, white, padding.
+            0, 0, 255, 255, 255, 255, 0, 0,
+        ];
+        let image = read(&bmp(2, 2, 24, &[], &body));
+        assert_eq!(
+            image,
+            Image::new(
+                2,
+                2,
+                vec![[255, 0, 0], WHITE, [0, 0, 255], [0, 255, 0]]
+            )
+        );
+    }
+
+    #[test]
+    fn bmp_4bit_top_down() {
+        let palette = [0, 0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 0];
+        let body = [0x12, 0x00, 0, 0, 0x21, 0x00, 0, 0];
+        let mut palette16 = palette.to_vec();
+        palette16.resize(64, 0);
+        let image = read(&bmp(3, -2, 4, &palette16, &body));
+        let red = [255, 0, 0];
+        assert_eq!(
+            image,
+            Image::new(3, 2, vec![WHITE, red, BLACK, red, WHITE, BLACK])
+        );
+    }
+
+    #[test]
+    fn dither_matches_rounding_for_exact_levels() {
+        for &v in &[0, 85, 170, 255] {
+            let c = [v, 255 - v, v];
+            for y in 0..4 {
+                for x in 0..4 {
+                    assert_eq!(
+                        quantize(c, x, y, Dither::Bayer),
+                        quantize(c, x, y, Dither::None)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitmap_1_output() {
+        let red = [255, 0, 0];
+        let mut pixels = vec![BLACK; 40];
+        pixels[0] = red;
+        pixels[33] = red;
+        pixels[20 + 1] = red;
+        let image = Image::new(20, 2, pixels);
+
+        let mut out = Vec::new();
+        let cost =
+            generate_bitmap_1(&image, "LOGO", Placement::Flash, &mut out)
+                .unwrap();
+        assert_eq!(cost, Cost { flash: 8, ram: 0 });
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("LOGO_STRIDE: usize = 1;"));
+        assert!(out.contains("LOGO_CLUT: usize = 0x0300;"));
+        assert!(out.contains("0x00000001, 0x00002002,"));
+    }
+
+    #[test]
+    fn direct_output_in_ram() {
+        let image = Image::new(
+            5,
+            1,
+            vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], WHITE, WHITE],
+        );
+        let mut out = Vec::new();
+        let cost = generate_direct(
+            &image,
+            "PIC",
+            Dither::None,
+            Placement::Ram,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(cost, Cost { flash: 8, ram: 8 });
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("#[link_section = \".data\"]"));
+        assert!(out.contains("0x3f300c03, 0x0000003f,"));
+    }
+
+    #[test]
+    fn palettized_limits() {
+        let image = Image::new(3, 1, vec![BLACK, WHITE, [255, 0, 0]]);
+        let mut out = Vec::new();
+        assert!(generate_palettized(
+            &image,
+            "P",
+            1,
+            Dither::None,
+            Placement::Flash,
+            &mut out
+        )
+        .is_err());
+        let cost = generate_palettized(
+            &image,
+            "P",
+            2,
+            Dither::None,
+            Placement::Flash,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            cost,
+            Cost {
+                flash: 3 + 4,
+                ram: 0
+            }
+        );
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("P_PALETTE: [u8; 3] = [0x00, 0x3f, 0x03, ];"));
+        assert!(out.contains("0x00000024,"));
+    }
+
+    #[test]
+    fn text_screen_output() {
+        let background = Image::new(2, 1, vec![BLACK, WHITE]);
+        let mut out = Vec::new();
+        generate_text_screen(
+            &background,
+            None,
+            "Hi\u{e9}",
+            "SCREEN",
+            Placement::Flash,
+            &mut out,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "from_ascii_char(0x48).with_foreground(0x3f).with_background(0x00)"
+        ));
+        assert!(out.contains(
+            "from_ascii_char(0x69).with_foreground(0x00).with_background(0x3f)"
+        ));
+        assert!(check_name("9LIVES").is_err());
+    }
+}
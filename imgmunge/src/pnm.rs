@@ -0,0 +1,186 @@
+//! Netpbm (PBM/PGM/PPM) reader, in both ASCII and binary flavors.
+
+use std::io;
+
+use crate::{invalid, Image, Rgb};
+
+/// Parses a Netpbm image. `data` must start with the `P1`-`P6` magic.
+pub fn read(data: &[u8]) -> io::Result<Image> {
+    let kind = match data {
+        [b'P', k @ b'1'..=b'6', ..] => k - b'0',
+        _ => return Err(invalid("not a Netpbm image")),
+    };
+    let mut header = Header { data, pos: 2 };
+
+    let width = header.number()?;
+    let height = header.number()?;
+    // Bitmaps have no maxval; their samples are single bits.
+    let maxval = if kind == 1 || kind == 4 {
+        1
+    } else {
+        header.number()?
+    };
+    if width == 0 || height == 0 || width > 0xFFFF || height > 0xFFFF {
+        return Err(invalid("image dimensions out of range"));
+    }
+    if maxval == 0 || maxval > 0xFFFF {
+        return Err(invalid("maxval out of range"));
+    }
+
+    let channels = if kind == 3 || kind == 6 { 3 } else { 1 };
+    // With the dimensions limited as above, these can only overflow on 32-bit
+    // hosts -- but there, they can.
+    let count = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .ok_or_else(|| invalid("image too large"))?;
+
+    // Don't trust the header's dimensions with an allocation until the data
+    // bears them out.
+    let mut samples = Vec::new();
+    if kind <= 3 {
+        // ASCII formats: whitespace-separated decimal samples, except that
+        // PBM allows the digits to be packed together with no separator. Each
+        // takes at least one byte.
+        samples.reserve(count.min(data.len()));
+        for _ in 0..count {
+            let v = if kind == 1 {
+                header.bit()?
+            } else {
+                header.number()?
+            };
+            if v > maxval {
+                return Err(invalid("sample exceeds maxval"));
+            }
+            samples.push(v);
+        }
+    } else {
+        // Binary formats: exactly one whitespace byte ends the header.
+        header.pos += 1;
+        let body = &data[header.pos.min(data.len())..];
+        if kind == 4 {
+            // Rows are padded to a byte boundary, MSB first.
+            let row_bytes = width.div_ceil(8);
+            if body.len() / row_bytes < height {
+                return Err(truncated());
+            }
+            samples.reserve(count);
+            for row in body.chunks(row_bytes).take(height) {
+                for x in 0..width {
+                    samples.push(usize::from(row[x / 8] >> (7 - x % 8)) & 1);
+                }
+            }
+        } else {
+            let wide = maxval > 0xFF;
+            let size = if wide { 2 } else { 1 };
+            if body.len() / size < count {
+                return Err(truncated());
+            }
+            samples.reserve(count);
+            for s in body.chunks(size).take(count) {
+                let v = if wide {
+                    usize::from(s[0]) << 8 | usize::from(s[1])
+                } else {
+                    usize::from(s[0])
+                };
+                if v > maxval {
+                    return Err(invalid("sample exceeds maxval"));
+                }
+                samples.push(v);
+            }
+        }
+    }
+
+    // In PBM, 1 is ink (black), so invert while scaling.
+    let scale = |v: usize| -> u8 {
+        let v = if kind == 1 || kind == 4 { 1 - v } else { v };
+        ((v * 255 + maxval / 2) / maxval) as u8
+    };
+
+    let pixels: Vec<Rgb> = if channels == 3 {
+        samples
+            .chunks(3)
+            .map(|s| [scale(s[0]), scale(s[1]), scale(s[2])])
+            .collect()
+    } else {
+        samples
+            .into_iter()
+            .map(|s| {
+                let v = scale(s);
+                [v, v, v]
+            })
+            .collect()
+    };
+
+    Ok(Image::new(width, height, pixels))
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "image data truncated")
+}
+
+/// Cursor over the text portion of a Netpbm file.
+struct Header<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Header<'a> {
+    /// Skips whitespace and `#` comments, which may appear between any two
+    /// tokens.
+    fn skip_space(&mut self) {
+        while let Some(&c) = self.data.get(self.pos) {
+            if c == b'#' {
+                while let Some(&c) = self.data.get(self.pos) {
+                    if c == b'\n' || c == b'\r' {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            } else if c.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        self.skip_space();
+        let start = self.pos;
+        let mut n: usize = 0;
+        while let Some(&c) = self.data.get(self.pos) {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            n = n
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(usize::from(c - b'0')))
+                .ok_or_else(|| invalid("number too large"))?;
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(match self.data.get(self.pos) {
+                None => truncated(),
+                Some(_) => invalid("expected a number"),
+            });
+        }
+        Ok(n)
+    }
+
+    fn bit(&mut self) -> io::Result<usize> {
+        self.skip_space();
+        match self.data.get(self.pos) {
+            Some(b'0') => {
+                self.pos += 1;
+                Ok(0)
+            }
+            Some(b'1') => {
+                self.pos += 1;
+                Ok(1)
+            }
+            Some(_) => Err(invalid("expected 0 or 1")),
+            None => Err(truncated()),
+        }
+    }
+}
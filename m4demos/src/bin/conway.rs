@@ -11,7 +11,9 @@ extern crate panic_itm;
 use stm32f4;
 use stm32f4::stm32f407::interrupt;
 
+use m4vga::color::Color;
 use m4vga::priority;
+use m4vga::util::arena::{Arena, Ccm, Sram1};
 use m4vga_fx_common::{Demo, Raster, Render};
 use m4vga_fx_conway as fx;
 
@@ -22,28 +24,23 @@ const BUF_SIZE: usize = 800 * 600 / 32;
 #[allow(unused_parens)] // TODO bug in cortex_m_rt
 #[cortex_m_rt::entry]
 fn main() -> ! {
-    // Safety: as long as these are the *only* mutable references we produce to
-    // those statics, we're good.
+    // The two buffers don't fit in the same RAM, so split them between SRAM1
+    // and CCM. The rasterizer reads them with the CPU, so either will do.
     let mut state = fx::State::new(
         // Foreground
-        {
-            static mut BUF0: [u32; BUF_SIZE] = [0; BUF_SIZE];
-            // Safety: because of scoping this is clearly the only mutable
-            // reference we generate to this static.
-            unsafe { &mut BUF0 as &mut [_] }
-        },
+        Arena::<Sram1>::take()
+            .unwrap()
+            .alloc_slice(BUF_SIZE, 0)
+            .expect("fg buffer"),
         // Background
-        {
-            #[link_section = ".local_bss"]
-            static mut BUF1: [u32; BUF_SIZE] = [0; BUF_SIZE];
-            // Safety: because of scoping this is clearly the only mutable
-            // reference we generate to this static.
-            unsafe { &mut BUF1 as &mut [_] }
-        },
+        Arena::<Ccm>::take()
+            .unwrap()
+            .alloc_slice(BUF_SIZE, 0)
+            .expect("bg buffer"),
         // Foreground color
-        0b11_11_11,
+        Color::WHITE,
         // Background color
-        0b00_00_00,
+        Color::BLACK,
     );
     let (mut raster, mut render) = state.split();
 
@@ -42,17 +42,15 @@ use stm32f4;
 use stm32f4::stm32f407::interrupt;
 
 use font_10x16;
+use m4vga::color::Color;
 use m4vga::rast::text_10x16::{self, AChar};
 use m4vga::util::spin_lock::SpinLock;
 
 const COLS: usize = 80;
 const ROWS: usize = 37;
 
-const WHITE: u8 = 0b11_11_11;
-const BLACK: u8 = 0b00_00_00;
-const DK_GRAY: u8 = 0b01_01_01;
-const RED: u8 = 0b00_00_11;
-const BLUE: u8 = 0b11_00_00;
+/// Background of the body text.
+const DK_BLUE: Color = Color::from_levels(0, 0, 2);
 
 static TEXT_BUF: SpinLock<[AChar; COLS * ROWS]> =
     SpinLock::new([AChar::from_ascii_char(0); COLS * ROWS]);
@@ -66,20 +64,20 @@ fn main() -> ! {
         // Type some stuff into the buffer.
         let mut c = TEXT_BUF.try_lock().unwrap();
         let mut c = Cursor::new(&mut *c);
-        c.fg = WHITE;
-        c.bg = DK_GRAY;
+        c.fg = Color::WHITE;
+        c.bg = Color::DARK_GRAY;
         c.puts(b"800x600 Attributed Text Demo\n");
-        c.bg = BLACK;
+        c.bg = Color::BLACK;
         c.puts(b"10x16 point characters in an 80x37 grid, with ");
-        c.fg = RED;
+        c.fg = Color::RED;
         c.puts(b"foreground");
-        c.fg = WHITE;
+        c.fg = Color::WHITE;
         c.puts(b" and ");
-        c.bg = BLUE;
+        c.bg = Color::BLUE;
         c.puts(b"background");
-        c.bg = BLACK;
+        c.bg = Color::BLACK;
         c.puts(b" colors.\n");
-        c.bg = 0b10_00_00;
+        c.bg = DK_BLUE;
         c.puts(
             br#"
        Lorem ipsum dolor sit amet, consectetur adipiscing elit. Nam ut
@@ -154,8 +152,8 @@ fn main() -> ! {
                     let mut buf = TEXT_BUF.try_lock().expect("app buf access");
                     let mut c = Cursor::new(&mut *buf);
                     c.goto(36, 0);
-                    c.bg = 0;
-                    c.fg = 0b00_11_00;
+                    c.bg = Color::BLACK;
+                    c.fg = Color::GREEN;
                     write!(&mut c, "Welcome to frame {}", frame_no).unwrap();
                     frame_no += 1;
                 }
@@ -168,8 +166,8 @@ struct Cursor<'a> {
     buf: &'a mut [AChar; COLS * ROWS],
     row: usize,
     col: usize,
-    fg: m4vga::Pixel,
-    bg: m4vga::Pixel,
+    fg: Color,
+    bg: Color,
 }
 
 impl<'a> Cursor<'a> {
@@ -178,8 +176,8 @@ impl<'a> Cursor<'a> {
             buf,
             row: 0,
             col: 0,
-            fg: 0xFF,
-            bg: 0b100000,
+            fg: Color::WHITE,
+            bg: DK_BLUE,
         }
     }
 
@@ -192,8 +190,8 @@ impl<'a> Cursor<'a> {
                 let end_of_line = (pos + (COLS - 1)) / COLS * COLS;
                 for p in &mut self.buf[pos..end_of_line] {
                     *p = AChar::from_ascii_char(b' ')
-                        .with_foreground(self.fg)
-                        .with_background(self.bg)
+                        .with_foreground(self.fg.into())
+                        .with_background(self.bg.into())
                 }
                 self.col = 0;
                 self.row += 1;
@@ -201,8 +199,8 @@ impl<'a> Cursor<'a> {
             _ => {
                 self.buf[self.row * COLS + self.col] =
                     AChar::from_ascii_char(c)
-                        .with_foreground(self.fg)
-                        .with_background(self.bg);
+                        .with_foreground(self.fg.into())
+                        .with_background(self.bg.into());
                 self.col += 1;
                 if self.col == COLS {
                     self.col = 0;
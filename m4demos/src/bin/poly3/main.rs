@@ -11,15 +11,17 @@ use stm32f4;
 use stm32f4::stm32f407::interrupt;
 
 use math::{
-    Augment, HomoTransform, Mat4f, Project, Vec3, Vec3f, Vec3i, Vector,
+    clip, Augment, HomoTransform, Mat4f, Project, Vec3, Vec3f, Vec4, Vec4f,
+    Vector,
 };
 
-use m4vga::util::rw_lock::ReadWriteLock;
+use m4vga::color::Color;
+use m4vga::priority::{self, I0};
+use m4vga::util::priority_cell::PriorityCell;
 
-mod model;
-mod render;
+use polyrast::{Clip, Raster, Tri};
 
-use render::Raster;
+mod model;
 
 extern "C" {
     fn fast_fill(start: *mut u8, end: *const u8, value: u8);
@@ -39,23 +41,51 @@ fn main() -> ! {
     entry()
 }
 
-static RASTER: ReadWriteLock<Raster> = ReadWriteLock::new(Raster::new());
+/// Number of trapezoids the rasterizer can hold. Each triangle needs one or
+/// two.
+const MAX_STATES: usize = 90;
+
+static RASTER: PriorityCell<Raster<MAX_STATES>, I0> =
+    PriorityCell::new(Raster::new(Clip::new(800, 600)));
+
+/// Room for vertices made by clipping triangles, beyond those in the model.
+const EXTRA_VERTICES: usize = 48;
+
+/// Number of triangles, from the model or made by clipping, that can be
+/// handed to the rasterizer in one frame.
+const MAX_TRIS: usize = 128;
 
 const LIGHT: Vec3f = Vec3(-0.577, 0.577, 0.577);
 
 fn entry() -> ! {
-    let transformed = singleton!(: [Vec3i; model::VERTEX_COUNT] =
-                     [Vec3(0,0,0); model::VERTEX_COUNT])
+    let clipspace = singleton!(: [Vec4f; model::VERTEX_COUNT] =
+                     [Vec4(0.,0.,0.,0.); model::VERTEX_COUNT])
+    .unwrap();
+
+    let outcodes = singleton!(: [u8; model::VERTEX_COUNT] =
+                     [0; model::VERTEX_COUNT])
+    .unwrap();
+
+    let transformed = singleton!(
+        : [Vec3f; model::VERTEX_COUNT + EXTRA_VERTICES] =
+        [Vec3(0.,0.,0.); model::VERTEX_COUNT + EXTRA_VERTICES])
+    .unwrap();
+
+    let tris = singleton!(: [Tri; MAX_TRIS] = [Tri {
+        vertex_indices: [0; 3],
+        normal_index: 0,
+        color: 0,
+    }; MAX_TRIS])
     .unwrap();
 
     let transformed_n = singleton!(: [Vec3f; model::NORMAL_COUNT] =
                      [Vec3(0.,0.,0.); model::NORMAL_COUNT])
     .unwrap();
 
-    let projection = Mat4f::translate((400., 300., 0.).into())
-        * Mat4f::scale((300., 300., 300.).into())
-        * Mat4f::perspective(-10., -10., 10., 10., 20., 100.)
+    let camera = Mat4f::perspective(-10., -10., 10., 10., 20., 100.)
         * Mat4f::translate((0., 0., -70.).into());
+    let viewport = Mat4f::translate((400., 300., 0.).into())
+        * Mat4f::scale((300., 300., 300.).into());
 
     let mut frame = 0;
 
@@ -66,20 +96,19 @@ fn entry() -> ! {
         // ... and provide a raster callback.
         .with_raster(
             #[link_section = ".ramcode"]
-            |ln, tgt, ctx, _| {
+            |ln, tgt, ctx, p| {
                 m4vga::util::measurement::sig_d_set();
                 let mut left_margin = 800;
                 let mut right_margin = 0;
-                RASTER
-                    .try_lock_mut()
-                    .expect("rast access")
-                    .step(ln, |span, _color, normal| {
-                        let color =
-                            ((normal.dot(LIGHT) + 1.) * 1.7) as u8 * 0b010101;
+                RASTER.with(&p, |r| {
+                    r.step(ln, |span, _color, normal| {
+                        let level = ((normal.dot(LIGHT) + 1.) * 1.7) as u8;
+                        let color = Color::gray(level).to_pixel();
                         left_margin = left_margin.min(span.start);
                         right_margin = right_margin.max(span.end);
                         fill(&mut tgt[span.clone()], color);
-                    });
+                    })
+                });
                 fill(&mut tgt[..left_margin], 0);
                 if right_margin > left_margin {
                     fill(&mut tgt[right_margin..800], 0);
@@ -88,35 +117,113 @@ fn entry() -> ! {
                 m4vga::util::measurement::sig_d_clear();
                 ctx.target_range = 0..800; // 800 pixels now valid
             },
-            |vga| loop {
-                vga.sync_to_vblank();
-                let model = Mat4f::rotate_y(frame as f32 * 0.05)
-                    * Mat4f::rotate_z(frame as f32 * 0.025);
-                let modelview = projection * model;
-
-                // Project vertices into screen space.
-                for (t, s) in
-                    transformed.iter_mut().zip(model::VERTICES.iter())
-                {
-                    let Vec3(x, y, z) = (modelview * s.augment()).project();
-                    *t = Vec3(x as i32, y as i32, z as i32);
-                }
-
-                // Project normals into model space.
-                for (t, n) in
-                    transformed_n.iter_mut().zip(model::NORMALS.iter())
-                {
-                    *t = (model * n.augment()).project();
+            |vga| {
+                let thread = priority::Thread::new_checked().unwrap();
+                loop {
+                    vga.sync_to_vblank();
+                    let model = Mat4f::rotate_y(frame as f32 * 0.05)
+                        * Mat4f::rotate_z(frame as f32 * 0.025);
+                    let modelview = camera * model;
+                    // The model matrix is a pure rotation, so this can't
+                    // fail.
+                    let normal = model.normal_matrix().unwrap();
+
+                    // Transform vertices into clip space, and project those
+                    // that can be into screen space.
+                    for (((c, code), t), s) in clipspace
+                        .iter_mut()
+                        .zip(outcodes.iter_mut())
+                        .zip(transformed.iter_mut())
+                        .zip(model::VERTICES.iter())
+                    {
+                        *c = modelview * s.augment();
+                        *code = clip::outcode(*c);
+                        if *code & (clip::NEAR | clip::FAR) == 0 {
+                            *t = (viewport * *c).project();
+                        }
+                    }
+                    let tri_count = clip_tris(
+                        clipspace, outcodes, &viewport, transformed, tris,
+                    );
+
+                    // Project normals into model space.
+                    for (t, n) in
+                        transformed_n.iter_mut().zip(model::NORMALS.iter())
+                    {
+                        *t = normal * *n;
+                    }
+
+                    // If the model ever outgrows the rasterizer, draw what
+                    // fits rather than stopping.
+                    RASTER.lock(&thread, |r| {
+                        r.reset(&tris[..tri_count], transformed, transformed_n)
+                            .ok()
+                    });
+                    vga.video_on();
+                    frame += 1;
                 }
-
-                RASTER.lock_mut()
-                    .reset(&model::TRIS, transformed, transformed_n);
-                vga.video_on();
-                frame += 1;
             },
         )
 }
 
+/// Collects the model's triangles into `tris` for rasterizing, and returns how
+/// many there are.
+///
+/// Triangles entirely outside the view frustum are dropped. Those that cross
+/// the near or far plane can't be projected as they are, so they're clipped,
+/// and the pieces are added with new vertices appended to `transformed`.
+/// Crossing the other planes is fine, since the rasterizer clips to the
+/// screen.
+///
+/// If either buffer runs out of room, the remaining pieces are dropped.
+fn clip_tris(
+    clipspace: &[Vec4f; model::VERTEX_COUNT],
+    outcodes: &[u8; model::VERTEX_COUNT],
+    viewport: &Mat4f,
+    transformed: &mut [Vec3f],
+    tris: &mut [Tri],
+) -> usize {
+    let mut tri_count = 0;
+    let mut vertex_count = model::VERTEX_COUNT;
+    for tri in model::TRIS.iter() {
+        let [a, b, c] = tri.vertex_indices;
+        let codes = [outcodes[a], outcodes[b], outcodes[c]];
+        if codes[0] & codes[1] & codes[2] != 0 {
+            continue;
+        }
+        if (codes[0] | codes[1] | codes[2]) & (clip::NEAR | clip::FAR) == 0 {
+            if tri_count == tris.len() {
+                break;
+            }
+            tris[tri_count] = *tri;
+            tri_count += 1;
+            continue;
+        }
+
+        let poly =
+            clip::clip_triangle([clipspace[a], clipspace[b], clipspace[c]]);
+        for piece in poly.triangles() {
+            if tri_count == tris.len() || vertex_count + 3 > transformed.len() {
+                return tri_count;
+            }
+            for (t, v) in transformed[vertex_count..].iter_mut().zip(&piece) {
+                *t = (*viewport * *v).project();
+            }
+            tris[tri_count] = Tri {
+                vertex_indices: [
+                    vertex_count,
+                    vertex_count + 1,
+                    vertex_count + 2,
+                ],
+                ..*tri
+            };
+            tri_count += 1;
+            vertex_count += 3;
+        }
+    }
+    tri_count
+}
+
 /// Wires up the PendSV handler expected by the driver.
 #[cortex_m_rt::exception]
 #[link_section = ".ramcode"]
@@ -21,64 +21,86 @@ use stm32f4;
 use stm32f4::stm32f407::interrupt;
 
 use gfx;
-use math::{Augment, HomoTransform, Mat4f, Project, Vec2, Vec2i, Vec3f};
+use math::{
+    clip, Augment, HomoTransform, Mat4f, Project, Quatf, Vec2, Vec2i, Vec3f,
+    Vec4f,
+};
 
+use m4vga::color::{self, Color};
+use m4vga::priority::{self, I0};
 use m4vga::rast::text_10x16::AChar;
-use m4vga::util::rw_lock::ReadWriteLock;
-
-// We can't fit a second buffer in bitband-accessible RAM, so the `front` buffer
-// goes in CCM. This means we can't render into it directly using bitbanding,
-// and we'll have to *copy* pixels from `back` to `front` during vsync.
-//
-// Because `front` is shared between the render loop and the rasterizer, we wrap
-// it in a lock.
-#[link_section = ".local_bss"]
-static FRONT_BUF: ReadWriteLock<[u32; 800 * 600 / 32]> =
-    ReadWriteLock::new([0; 800 * 600 / 32]);
+use m4vga::util::arena::{Arena, Sram1};
+use m4vga::util::flip_buf::FlipBuffer;
+use m4vga::util::priority_cell::PriorityCell;
+
+/// Words per line of the bitmap.
+const STRIDE: usize = 800 / 32;
+/// First display line showing the bitmap.
+const BITMAP_TOP: usize = 100;
+/// Number of lines in the bitmap. Only these are stored, which is what lets us
+/// fit two framebuffers in bitband-accessible RAM.
+const BITMAP_LINES: usize = 400;
 
 // We need somewhere to store the text that we scroll across the screen. We will
 // update it in-place, so it needs to be mutable. It's shared between the render
-// loop and the text rasterizer, so it needs to be in a lock.
-static MESSAGE: ReadWriteLock<[AChar; 81]> =
-    ReadWriteLock::new([AChar::from_ascii_char(b' '); 81]);
+// loop and the text rasterizer, so it needs to be in a cell too.
+static MESSAGE: PriorityCell<[AChar; 81], I0> =
+    PriorityCell::new([AChar::from_ascii_char(b' '); 81]);
 
 /// Demo entry point and main loop. This is factored out of `main` because the
 /// `cortex_m_rt` `entry` attribute mis-reports all error locations, making code
 /// hard to debug.
 fn entry() -> ! {
-    // We need two framebuffers, which we'll call `front` and `back`.
-
-    // The `back` buffer needs to live in the bitband target region of the
-    // address space, so that our line-drawing code can get to it. An array
-    // without any section attribute goes there by default per our linker
-    // script.
-    let back =
-        singleton!(: [u32; 800 * 600 / 32] = [0; 800 * 600 / 32]).unwrap();
-    let mut back = gfx::PackedBitBuffer::new(sram112_alias(back), 800 / 32);
+    // We need two framebuffers: one on display, and one to draw the next frame
+    // into, swapped at the start of each frame. Both need to live in the
+    // bitband target region of the address space, so that our line-drawing
+    // code can get to them, and be reachable by DMA, which clears them. The
+    // SRAM1 arena can provide that.
+    let mut sram1 = Arena::<Sram1>::take().unwrap();
+    let mut alloc = || {
+        sram1
+            .alloc_dma_bit_band(STRIDE * BITMAP_LINES, 0)
+            .expect("framebuffer")
+    };
+    let mut buffers = FlipBuffer::new([alloc(), alloc()]);
+    let (mut front, mut back) = buffers.split();
 
     // Each vertex in the model is shared by multiple triangles. It would
     // therefore be wasteful to transform each triangle separately. Instead, we
     // save time by transforming all the unique vertices and writing their
-    // screen-space projected versions into `vertex_buf` each frame.
+    // screen-space projected versions into `vertex_buf` each frame. Their
+    // clip-space outcodes go into `outcode_buf`, so that edges needing
+    // clipping can be found without transforming them again.
     let vertex_buf = singleton!(
         : [Vec2i; model::VERTEX_COUNT] = [Vec2(0, 0); model::VERTEX_COUNT])
     .unwrap();
+    let outcode_buf = singleton!(
+        : [u8; model::VERTEX_COUNT] = [0; model::VERTEX_COUNT])
+    .unwrap();
 
     // Foreground and background colors for the bitmap display:
-    let clut = AtomicUsize::new(0xFF_00);
+    let clut =
+        AtomicUsize::new(color::bitmap_1_clut(Color::BLACK, Color::WHITE));
 
-    // Base projection matrix, will be updated to animate.
-    let mut projection = Mat4f::translate((800. / 2., 600. / 2., 0.).into())
-        * Mat4f::scale((600. / 2., 600. / 2., 1.).into())
-        * Mat4f::perspective(-10., -10., 10., 10., 20., 100.)
+    // Camera and projection into clip space, fixed.
+    let camera = Mat4f::perspective(-10., -10., 10., 10., 20., 100.)
         * Mat4f::translate((0., 0., -75.).into());
-
-    // Base model matrix, will be updated to animate.
-    let mut model = Mat4f::rotate_y(3.1415926 / 2.);
+    // Mapping from clip space to the bitmap, applied after clipping. This
+    // centers the model on the screen, not the bitmap.
+    let viewport =
+        Mat4f::translate((800. / 2., 600. / 2. - BITMAP_TOP as f32, 0.).into())
+            * Mat4f::scale((600. / 2., 600. / 2., 1.).into());
+
+    // Model orientation, as a tilt around Z applied after a spin around Y.
+    // Both are updated to animate. Keeping them as quaternions lets us
+    // renormalize each frame, so the model stays rigid however long the demo
+    // runs.
+    let mut tilt = Quatf::identity();
+    let mut spin = Quatf::rotate_y(3.1415926 / 2.);
 
     // Rotation steps around each animated axis.
-    let rot_step_z = Mat4f::rotate_z(0.01);
-    let rot_step_y = Mat4f::rotate_y(0.02);
+    let rot_step_z = Quatf::rotate_z(0.01);
+    let rot_step_y = Quatf::rotate_y(0.02);
 
     // This scans the static `EDGES` array at startup, doing all bounds checking
     // in advance, and returns a slice of `CheckedEdge`s that are cheaper to
@@ -87,7 +109,8 @@ fn entry() -> ! {
 
     // Text time!
 
-    fill_message(&mut *MESSAGE.lock_mut());
+    let thread = priority::Thread::new_checked().unwrap();
+    MESSAGE.lock(&thread, fill_message);
 
     // Values between 1 and 9 slide rendered text by that many pixels to the
     // left. This is how we achieve smooth scrolling: we increment this to 9,
@@ -95,30 +118,30 @@ fn entry() -> ! {
     // this to zero.
     let fine_scroll = AtomicUsize::new(0);
 
+    let flipper = front.flipper();
+
     // Give the driver its hardware resources...
     m4vga::take_hardware()
         // ...select a display timing...
         .configure_timing(&m4vga::timing::SVGA_800_600)
         // ... and provide a raster callback.
         .with_raster(
-            |ln, tgt, ctx, _| {
+            |ln, tgt, ctx, p| {
                 if ln == 0 || ln == 516 {
                     // The top and bottom of the screen use the cheapest
                     // rasterizer to draw empty space, to save CPU.
                     m4vga::rast::solid_color_fill(tgt, ctx, 800, 0);
-                    ctx.repeat_lines = 99;
+                    ctx.repeat_lines = BITMAP_TOP - 1;
                     return;
                 }
 
-                if ln < 500 {
+                if ln < BITMAP_TOP + BITMAP_LINES {
                     // Bitmapped wireframe display.
                     m4vga::util::measurement::sig_d_set();
 
-                    let front = FRONT_BUF.try_lock().expect("front unavail");
-
-                    let offset = ln * (800 / 32);
+                    let offset = (ln - BITMAP_TOP) * STRIDE;
                     m4vga::rast::bitmap_1::unpack(
-                        &front[offset..offset + (800 / 32)],
+                        &front.front(ln, &p)[offset..offset + STRIDE],
                         &clut,
                         &mut tgt[0..800],
                     );
@@ -130,59 +153,71 @@ fn entry() -> ! {
                     // the display to the left. We do this by adjusting our
                     // `tgt` slice.
                     let fs = fine_scroll.load(Ordering::Relaxed);
-                    m4vga::rast::text_10x16::unpack(
-                        &*MESSAGE.try_lock().expect("message unavail"),
-                        font_10x16::FONT.as_glyph_slices(),
-                        &mut tgt[16 - fs..],
-                        ln - 500,
-                        81,
-                    );
+                    MESSAGE.with(&p, |message| {
+                        m4vga::rast::text_10x16::unpack(
+                            message,
+                            font_10x16::FONT.as_glyph_slices(),
+                            &mut tgt[16 - fs..],
+                            ln - (BITMAP_TOP + BITMAP_LINES),
+                            81,
+                        )
+                    });
                     ctx.target_range = 16..816;
                 }
             },
-            // This closure contains the main loop of the program.
-            |vga| loop {
-                vga.sync_to_vblank();
-
-                // Update the fine-scrolling state machine.  This multi-step
-                // check-and-store sequence is okay because we're the only
-                // writer.
-                let fs = fine_scroll.load(Ordering::Acquire);
-                if fs == 9 {
-                    fine_scroll.store(0, Ordering::Release);
-                    MESSAGE.lock_mut().rotate_left(1);
-                } else {
-                    fine_scroll.store(fs + 1, Ordering::Release);
+            // This closure contains the main loop of the program. It has the
+            // driver swap buffers as soon as active video ends, so a frame
+            // presented by then frees up its back buffer by vblank.
+            |vga| vga.with_flip(flipper, |vga| {
+                let mut copier = vga.take_dma_copier().unwrap();
+                loop {
+                    vga.sync_to_vblank();
+
+                    // Update the fine-scrolling state machine.  This multi-step
+                    // check-and-store sequence is okay because we're the only
+                    // writer.
+                    let fs = fine_scroll.load(Ordering::Acquire);
+                    if fs == 9 {
+                        fine_scroll.store(0, Ordering::Release);
+                        MESSAGE.lock(&thread, |m| m.rotate_left(1));
+                    } else {
+                        fine_scroll.store(fs + 1, Ordering::Release);
+                    }
+
+                    // Get the buffer that isn't on display. It was last shown
+                    // two frames ago, so it needs clearing. Dropping `guard`
+                    // presents it, to be swapped in during a later vblank, so
+                    // drawing doesn't need to finish within this one.
+                    let mut guard = back.back(&thread);
+
+                    // Clear the buffer by DMA, while the CPU transforms the
+                    // model's vertices.
+                    let view = Viewing {
+                        clip: camera * (tilt * spin).to_mat4(),
+                        viewport,
+                    };
+                    copier.fill_while(&mut guard, 0, || {
+                        transform_vertices(
+                            &view,
+                            &model::VERTICES,
+                            vertex_buf,
+                            outcode_buf,
+                        )
+                    });
+
+                    m4vga::util::measurement::sig_c_set();
+                    let mut buf = gfx::PackedBitBuffer::new(&mut guard, STRIDE);
+                    draw_edges(&mut buf, edges, vertex_buf, outcode_buf, &view);
+                    m4vga::util::measurement::sig_c_clear();
+                    drop(guard);
+
+                    // Animate:
+                    spin = (spin * rot_step_y).normalize();
+                    tilt = (tilt * rot_step_z).normalize();
+
+                    vga.video_on();
                 }
-
-                m4vga::util::measurement::sig_c_set();
-                // Copy draw buffer to front buffer. We are racing here: we must
-                // release the lock on front before scanout starts, or we'll
-                // panic.
-                m4vga::util::copy_words::copy_words(
-                    back.as_word_slice(),
-                    &mut *FRONT_BUF.lock_mut(),
-                );
-                m4vga::util::measurement::sig_c_clear();
-
-                back.clear();
-
-                transform_vertices(
-                    projection * model,
-                    &model::VERTICES,
-                    vertex_buf,
-                );
-
-                m4vga::util::measurement::sig_c_set();
-                draw_edges(&mut back, edges, vertex_buf);
-                m4vga::util::measurement::sig_c_clear();
-
-                // Animate:
-                model = model * rot_step_y.clone();
-                projection = projection * rot_step_z.clone();
-
-                vga.video_on();
-            },
+            }),
         )
 }
 
@@ -192,70 +227,95 @@ fn fill_message(message: &mut [AChar; 81]) {
     let text = b"3450 triangles made from 4700 segments, \
              drawn at 60Hz at 800x600, mixed mode -- ";
     for (dst, &b) in message.iter_mut().zip(text as &[_]) {
-        *dst = AChar::from_ascii_char(b).with_foreground(0b101010);
+        *dst =
+            AChar::from_ascii_char(b).with_foreground(Color::LIGHT_GRAY.into());
     }
     for c in &mut message[0..15] {
-        *c = c.with_foreground(0b111111);
+        *c = c.with_foreground(Color::WHITE.into());
     }
     for c in &mut message[25..38] {
-        *c = c.with_foreground(0b111111).with_background(0b110000);
+        *c = c
+            .with_foreground(Color::WHITE.into())
+            .with_background(Color::BLUE.into());
     }
     for c in &mut message[49..53] {
-        *c = c.with_foreground(0b000011);
+        *c = c.with_foreground(Color::RED.into());
     }
 }
 
-/// Transforms a vertex slice and projects to 2D.
-fn transform_vertices(m: Mat4f, vertices: &[Vec3f], out: &mut [Vec2i]) {
-    for (dst, src) in out.iter_mut().zip(vertices) {
-        let v = (m * src.augment()).project();
-        *dst = (v.0 as i32, v.1 as i32).into();
+/// The transforms for one frame.
+struct Viewing {
+    /// From model space to clip space.
+    clip: Mat4f,
+    /// From clip space to the screen.
+    viewport: Mat4f,
+}
+
+impl Viewing {
+    /// Maps a clipped point to the screen.
+    fn to_screen(&self, v: Vec4f) -> Vec2i {
+        let v = (self.viewport * v).project();
+        (v.0 as i32, v.1 as i32).into()
+    }
+}
+
+/// Transforms a vertex slice into clip space, recording outcodes, and projects
+/// to 2D.
+///
+/// Vertices outside the near or far plane can't be projected meaningfully, and
+/// their entries in `out` are left alone; edges that touch them are handled
+/// by `draw_edges`.
+fn transform_vertices(
+    view: &Viewing,
+    vertices: &[Vec3f],
+    out: &mut [Vec2i],
+    outcodes: &mut [u8],
+) {
+    for ((dst, code), src) in out.iter_mut().zip(outcodes).zip(vertices) {
+        let v = view.clip * src.augment();
+        *code = clip::outcode(v);
+        if *code & (clip::NEAR | clip::FAR) == 0 {
+            *dst = view.to_screen(v);
+        }
     }
 }
 
 /// Draws wireframe edges into `buf`.
 ///
 /// This takes a pre-transformed point cloud, `vertex_table`, and draws edges
-/// connecting points in the cloud as specified in `edge_table`.
+/// connecting points in the cloud as specified in `edge_table`. Edges that
+/// cross the near or far plane are transformed again from the model and
+/// clipped in 3D first.
 fn draw_edges(
     buf: &mut gfx::PackedBitBuffer,
     edge_table: &[(checked::VertexIndex, checked::VertexIndex)],
     vertex_table: &[Vec2i; model::VERTEX_COUNT],
+    outcode_table: &[u8; model::VERTEX_COUNT],
+    view: &Viewing,
 ) {
-    let mut bits = buf.as_bits();
     for (start, end) in edge_table {
-        let p0 = start.lookup(vertex_table);
-        let p1 = end.lookup(vertex_table);
-
-        bits.draw_line_unclipped(
-            p0.0 as usize,
-            p0.1 as usize,
-            p1.0 as usize,
-            p1.1 as usize,
-        )
-    }
-}
+        let c0 = *start.lookup(outcode_table);
+        let c1 = *end.lookup(outcode_table);
+        if c0 & c1 != 0 {
+            // Entirely off one side of the frustum.
+            continue;
+        }
 
-/// Maps an address from the zero-based alias of SRAM112 into its native space
-/// starting at `0x2000_0000`. This is required to use bitbanding.
-///
-/// Since the input slice is mutably borrowed, the alias is safe.
-fn sram112_alias<T>(slice: &mut [T]) -> &mut [T] {
-    const SRAM_SIZE: usize = 112 * 1024;
-
-    let addr = slice.as_ptr() as usize;
-    assert!(addr < SRAM_SIZE);
-    match slice.last() {
-        None => &mut [],
-        Some(r) => {
-            assert!((r as *const _ as usize) < SRAM_SIZE);
-            unsafe {
-                core::slice::from_raw_parts_mut(
-                    (addr | 0x20000000) as *mut T,
-                    slice.len(),
-                )
+        let (p0, p1) = if (c0 | c1) & (clip::NEAR | clip::FAR) == 0 {
+            (*start.lookup(vertex_table), *end.lookup(vertex_table))
+        } else {
+            let v0 = view.clip * start.lookup(&model::VERTICES).augment();
+            let v1 = view.clip * end.lookup(&model::VERTICES).augment();
+            match clip::clip_line(v0, v1) {
+                Some((v0, v1)) => (view.to_screen(v0), view.to_screen(v1)),
+                None => continue,
             }
-        }
+        };
+
+        // Clipped, so that the model can safely extend past the edges of the
+        // screen. This uses bit-banding on the hardware, but also works
+        // elsewhere.
+        buf.draw_line(p0.0, p0.1, p1.0, p1.1)
     }
 }
 
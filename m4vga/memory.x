@@ -48,6 +48,14 @@ SECTIONS {
 
   _sram16_bss_start = ADDR(.sram16);
   _sram16_bss_end = ADDR(.sram16) + SIZEOF(.sram16);
+
+  .arena_sram16 (NOLOAD) : {
+    . = ALIGN(4);
+    _arena_sram16_start = .;
+    /* exhaust the rest of this SRAM */
+    . = ORIGIN(SRAM16) + LENGTH(SRAM16);
+    _arena_sram16_end = .;
+  } > SRAM16
 } INSERT AFTER .bss;
 
 SECTIONS {
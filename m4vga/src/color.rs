@@ -0,0 +1,136 @@
+//! Colors in the driver's `0bBB_GG_RR` pixel format.
+//!
+//! The reference hardware has a resistor DAC with two bits per channel, for a
+//! total of 64 colors. `Color` wraps a pixel in this format, and provides ways
+//! to build one without memorizing the bit layout.
+//!
+//! With only four levels per channel, smooth gradients band badly. The
+//! dithering functions here trade some spatial resolution for more apparent
+//! levels, using an ordered (Bayer) dither: each pixel is rounded up or down
+//! depending on its position in a 4x4 tile, so that the average over the tile
+//! approximates the requested color. Because the pattern depends only on
+//! position, it's cheap to compute in a rasterizer, and stays still from frame
+//! to frame.
+
+use crate::Pixel;
+
+/// A color in the driver's pixel format.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Default)]
+#[repr(transparent)]
+pub struct Color(Pixel);
+
+impl Color {
+    pub const BLACK: Self = Self::from_levels(0, 0, 0);
+    pub const DARK_GRAY: Self = Self::gray(1);
+    pub const LIGHT_GRAY: Self = Self::gray(2);
+    pub const WHITE: Self = Self::from_levels(3, 3, 3);
+
+    pub const RED: Self = Self::from_levels(3, 0, 0);
+    pub const GREEN: Self = Self::from_levels(0, 3, 0);
+    pub const BLUE: Self = Self::from_levels(0, 0, 3);
+    pub const CYAN: Self = Self::from_levels(0, 3, 3);
+    pub const MAGENTA: Self = Self::from_levels(3, 0, 3);
+    pub const YELLOW: Self = Self::from_levels(3, 3, 0);
+
+    /// Interprets a pixel value as a color. Bits above the bottom six are
+    /// ignored.
+    pub const fn from_pixel(pixel: Pixel) -> Self {
+        Color(pixel & 0b11_11_11)
+    }
+
+    /// Makes a color from 2-bit levels (0-3) for each channel. Higher bits are
+    /// ignored.
+    pub const fn from_levels(r: u8, g: u8, b: u8) -> Self {
+        Color((r & 0b11) | (g & 0b11) << 2 | (b & 0b11) << 4)
+    }
+
+    /// Makes a color from 8-bit channels, rounding each to the nearest
+    /// available level.
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::from_levels(to_level(r), to_level(g), to_level(b))
+    }
+
+    /// Makes a gray from a 2-bit level (0-3).
+    pub const fn gray(level: u8) -> Self {
+        Self::from_levels(level, level, level)
+    }
+
+    /// Red level, 0-3.
+    pub const fn red(self) -> u8 {
+        self.0 & 0b11
+    }
+
+    /// Green level, 0-3.
+    pub const fn green(self) -> u8 {
+        (self.0 >> 2) & 0b11
+    }
+
+    /// Blue level, 0-3.
+    pub const fn blue(self) -> u8 {
+        (self.0 >> 4) & 0b11
+    }
+
+    pub const fn to_pixel(self) -> Pixel {
+        self.0
+    }
+
+    /// Expands the color to 8-bit channels, as `(r, g, b)`. Level 3 becomes
+    /// 255, so white is white.
+    pub const fn to_rgb8(self) -> (u8, u8, u8) {
+        (self.red() * 0x55, self.green() * 0x55, self.blue() * 0x55)
+    }
+
+    /// Makes a color from 8-bit channels, dithered for a pixel at `(x, y)`.
+    /// See `dither_level`.
+    pub fn dithered(r: u8, g: u8, b: u8, x: usize, y: usize) -> Self {
+        Self::from_levels(
+            dither_level(r, x, y),
+            dither_level(g, x, y),
+            dither_level(b, x, y),
+        )
+    }
+}
+
+impl From<Color> for Pixel {
+    fn from(c: Color) -> Pixel {
+        c.to_pixel()
+    }
+}
+
+/// Packs background and foreground colors into the color lookup table word
+/// used by `rast::bitmap_1`, where 0 bits are drawn in `background` and 1 bits
+/// in `foreground`.
+pub const fn bitmap_1_clut(background: Color, foreground: Color) -> usize {
+    background.0 as usize | (foreground.0 as usize) << 8
+}
+
+/// Rounds an 8-bit channel value to the nearest 2-bit level.
+const fn to_level(value: u8) -> u8 {
+    ((value as u16 * 3 + 127) / 255) as u8
+}
+
+/// The 4x4 Bayer matrix, giving the order in which pixels of a tile switch to
+/// the next level up as the input value increases.
+#[rustfmt::skip]
+pub const BAYER_4X4: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// Converts an 8-bit channel value to a 2-bit level (0-3), dithered for a
+/// pixel at `(x, y)`.
+///
+/// Averaged over any aligned 4x4 tile, the result is within 1/16 of a level of
+/// `value * 3 / 255`. Values that are exactly representable (0, 85, 170, 255)
+/// produce a solid level.
+pub fn dither_level(value: u8, x: usize, y: usize) -> u8 {
+    // Compare the fractional part of the level, in sixteenths, against the
+    // threshold for this pixel. Thresholds are offset by a half step, so that
+    // representable values don't pick up stray pixels.
+    let threshold = u32::from(BAYER_4X4[y % 4][x % 4]) * 2 + 1;
+    let scaled = u32::from(value) * 3 * 32;
+    let level = (scaled + threshold * 255) / (255 * 32);
+    level as u8
+}
@@ -1,11 +1,17 @@
 #[cfg(target_os = "none")]
 mod isr;
+mod dma_copy;
+#[cfg(feature = "panic-vga")]
+mod panic_vga;
 
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use scopeguard::defer;
+
 use crate::{priority, util, rast};
 use crate::rast::{RasterCtx, TargetBuffer};
 use crate::timing::{self, Polarity};
+use crate::util::flip_buf::Flipper;
 use crate::util::spin_lock::{SpinLock, SpinLockGuard};
 
 use cortex_m::peripheral as cm;
@@ -20,6 +26,19 @@ pub use self::isr::bg_rast::maintain_raster_isr as pendsv_raster_isr;
 pub use self::isr::hstate::hstate_isr as tim4_horiz_isr;
 pub use self::isr::shock::shock_absorber_isr as tim3_shock_isr;
 
+pub use self::dma_copy::{DmaCopier, DmaTransfer};
+
+/// Returns a histogram of raster callback costs since the last call, and
+/// starts a new one.
+///
+/// This is available only with the `profile` feature. Call it once per frame
+/// (e.g. after `sync_to_vblank`) to get per-frame results.
+#[cfg(feature = "profile")]
+pub fn take_raster_profile() -> util::profile::LineHistogram {
+    let mut profile = self::isr::bg_rast::RASTER_PROFILE.lock();
+    let budget = profile.budget();
+    core::mem::replace(&mut *profile, util::profile::LineHistogram::new(budget))
+}
 
 /// Driver handle.
 ///
@@ -94,6 +113,12 @@ impl SyncOn for Live {}
 
 /// Operations valid in any driver state.
 impl<T> Vga<T> {
+    /// Gets exclusive access to the spare DMA stream used for background
+    /// copies. This succeeds only once; later calls return `None`.
+    pub fn take_dma_copier(&self) -> Option<DmaCopier> {
+        DmaCopier::take()
+    }
+
     /// Disables video output. This is not synchronized and can happen in the
     /// middle of the frame; if that bothers you, synchronize with vblank.
     pub fn video_off(&self) {
@@ -328,6 +353,30 @@ impl Vga<Sync> {
 }
 
 impl Vga<Live> {
+    /// Has the driver swap in the buffer most recently presented to `flipper`'s
+    /// `FlipBuffer` at the end of each frame's active video, and executes
+    /// `scope`. When `scope` returns, `flipper` is revoked.
+    ///
+    /// This lets a renderer that waits with `sync_to_vblank` get its next back
+    /// buffer immediately, rather than when the rasterizer gets around to
+    /// reading the front buffer. The rasterizer must still read it through the
+    /// matching `FlipReader`.
+    pub fn with_flip<R>(
+        &mut self,
+        flipper: Flipper<'_>,
+        scope: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        // Safety: we revoke the flipper before returning, so PendSV can't use
+        // it beyond its real lifetime.
+        let flipper: Flipper<'static> =
+            unsafe { core::mem::transmute(flipper) };
+        *FLIPPER.try_lock().expect("flipper lock") = Some(flipper);
+        defer! {{
+            *FLIPPER.try_lock().expect("flipper lock") = None;
+        }}
+        scope(self)
+    }
+
     /// Enables video output. This is not synchronized and can happen in the
     /// middle of the frame; if that bothers you, synchronize with vblank.
     pub fn video_on(&mut self) {
@@ -444,6 +493,8 @@ pub fn init(
 ) -> Vga<Idle> {
     unsafe {
         util::measurement::init();
+        #[cfg(feature = "profile")]
+        util::profile::init();
     }
 
     let previous_instance = DRIVER_INIT_FLAG.swap(true, Ordering::SeqCst);
@@ -595,6 +646,10 @@ static TIMING: SpinLock<Option<timing::Timing>> = SpinLock::new(None);
 /// by hstate.
 static RASTER: rast::IRef = rast::IRef::new();
 
+/// Page flip to perform at the end of active video, if any. Loaded from thread
+/// mode by `with_flip`, used by PendSV during vblank.
+static FLIPPER: SpinLock<Option<Flipper<'static>>> = SpinLock::new(None);
+
 /// Turns off sync outputs. This used to be public API, but I never use it, so.
 fn sync_off(gpiob: &device::GPIOB) {
     gpiob
@@ -0,0 +1,254 @@
+//! Background memory-to-memory copies using a spare DMA stream.
+//!
+//! The driver owns DMA2, but only uses stream 5 (for scanout). This module
+//! uses stream 0 to copy or fill buffers while the CPU does something else,
+//! like render the next frame.
+//!
+//! `copy_words` is still faster in raw terms -- but it occupies the CPU for
+//! the whole copy, which can be a big chunk of vblank for a large framebuffer.
+//!
+//! # Arbitration with scanout
+//!
+//! Both streams share DMA2 and the AHB bus matrix, so copies must not delay
+//! scanout, which would distort the display. Copies run at the lowest DMA
+//! priority, while scanout runs at the highest, so the DMA controller's
+//! arbiter always services scanout requests first. Copies also move one word
+//! at a time (no bursts), so that a scanout request never waits for more than a
+//! single word of copy traffic.
+//!
+//! Copies still consume bus bandwidth, slowing down CPU access to the same
+//! RAMs. For best results, run them during vblank, or keep rasterizers that
+//! run during a copy out of the RAMs involved.
+//!
+//! # Memory restrictions
+//!
+//! DMA can't reach Core-Coupled Memory, so buffers are passed as
+//! `util::arena::DmaBuffer`s, which can only be allocated in RAMs that DMA can
+//! reach.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use stm32f4::stm32f407 as device;
+
+use crate::util::arena::{DmaBuffer, DmaCapable, Sram1};
+
+/// Most words we can move in one transfer, limited by the width of NDTR.
+const MAX_WORDS: usize = 0xFFFF;
+
+static COPIER_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Source word for `DmaCopier::fill_while`. Like all statics without a
+/// `link_section`, this lives in SRAM1 -- unlike the stack, which is in CCM,
+/// out of DMA's reach.
+static FILL_VALUE: AtomicU32 = AtomicU32::new(0);
+
+/// Exclusive access to DMA2 stream 0 for memory-to-memory copies.
+///
+/// Get one using `Vga::take_dma_copier`.
+pub struct DmaCopier(());
+
+/// An in-progress copy, holding the source and destination buffers until it
+/// completes.
+///
+/// Dropping a `DmaTransfer` before it completes leaks the DMA stream: the copy
+/// continues in the background, but the buffers and the `DmaCopier` are lost,
+/// and since `Vga::take_dma_copier` only succeeds once, no more copies can be
+/// made until reset. Use `wait` or `poll` to get them back.
+pub struct DmaTransfer<S: DmaCapable, D: DmaCapable> {
+    copier: DmaCopier,
+    source: DmaBuffer<S>,
+    dest: DmaBuffer<D>,
+}
+
+impl DmaCopier {
+    /// Creates the copier, if it hasn't been created already.
+    pub(super) fn take() -> Option<Self> {
+        if COPIER_TAKEN.swap(true, Ordering::AcqRel) {
+            None
+        } else {
+            Some(DmaCopier(()))
+        }
+    }
+
+    /// Starts copying `source` into `dest`, returning a `DmaTransfer` that can
+    /// be used to check for completion and get the buffers back.
+    ///
+    /// # Panics
+    ///
+    /// If the buffers are not the same length, or if they are longer than
+    /// 65,535 words.
+    pub fn start<S: DmaCapable, D: DmaCapable>(
+        self,
+        source: DmaBuffer<S>,
+        dest: DmaBuffer<D>,
+    ) -> DmaTransfer<S, D> {
+        assert!(source.len() == dest.len());
+        begin(source.dma_address(), true, dest.dma_address(), dest.len());
+        DmaTransfer {
+            copier: self,
+            source,
+            dest,
+        }
+    }
+
+    /// Fills `dest` with copies of `value`, while running `f` on the CPU.
+    /// Returns the result of `f` once both are done.
+    ///
+    /// Unlike `start`, this only borrows `dest`, because it doesn't return
+    /// until the DMA controller is done with it -- even if `f` panics. So this
+    /// works on buffers that are themselves borrowed, like the back buffer of a
+    /// `FlipBuffer`.
+    ///
+    /// # Panics
+    ///
+    /// If `dest` is longer than 65,535 words.
+    pub fn fill_while<D: DmaCapable, T>(
+        &mut self,
+        dest: &mut DmaBuffer<D>,
+        value: u32,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        /// Waits for the fill when dropped, including during a panic.
+        struct Finish;
+
+        impl Drop for Finish {
+            fn drop(&mut self) {
+                while !is_complete() {
+                    // busy wait
+                }
+                // Ensure the CPU doesn't read stale data.
+                cortex_m::asm::dmb();
+            }
+        }
+
+        if dest.is_empty() {
+            // There'd be no transfer to wait for.
+            return f();
+        }
+        FILL_VALUE.store(value, Ordering::Relaxed);
+        let source = Sram1::dma_address(&FILL_VALUE as *const _ as usize);
+        begin(source, false, dest.dma_address(), dest.len());
+        let _finish = Finish;
+        f()
+    }
+}
+
+impl<S: DmaCapable, D: DmaCapable> DmaTransfer<S, D> {
+    /// Checks whether the copy has finished.
+    ///
+    /// # Panics
+    ///
+    /// If the DMA controller reported a transfer error. This shouldn't be
+    /// possible for the addresses `start` accepts.
+    pub fn is_complete(&self) -> bool {
+        self.source.is_empty() || is_complete()
+    }
+
+    /// Returns the copier and buffers if the copy has finished, or gives back
+    /// the transfer otherwise.
+    pub fn poll(self) -> Result<(DmaCopier, DmaBuffer<S>, DmaBuffer<D>), Self> {
+        if self.is_complete() {
+            Ok(self.finish())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Busy-waits for the copy to finish, and returns the copier and buffers.
+    pub fn wait(self) -> (DmaCopier, DmaBuffer<S>, DmaBuffer<D>) {
+        while !self.is_complete() {
+            // busy wait
+        }
+        self.finish()
+    }
+
+    fn finish(self) -> (DmaCopier, DmaBuffer<S>, DmaBuffer<D>) {
+        // Ensure the CPU doesn't read stale data through `dest`.
+        cortex_m::asm::dmb();
+        (self.copier, self.source, self.dest)
+    }
+}
+
+/// Starts stream 0 moving `len` words from `source` to `dest` (both DMA
+/// addresses). If `source_increments` is false, the same source word is
+/// copied repeatedly.
+///
+/// # Panics
+///
+/// If `len` is more than 65,535 words.
+fn begin(source: usize, source_increments: bool, dest: usize, len: usize) {
+    assert!(len <= MAX_WORDS, "DMA copy too long");
+    if len == 0 {
+        return;
+    }
+    let dma = dma2();
+
+    // Clear any stale flags from a previous transfer, or the stream will
+    // refuse to start.
+    dma.lifcr.write(|w| {
+        w.ctcif0()
+            .set_bit()
+            .chtif0()
+            .set_bit()
+            .cteif0()
+            .set_bit()
+            .cdmeif0()
+            .set_bit()
+            .cfeif0()
+            .set_bit()
+    });
+
+    // Memory-to-memory transfers must use the FIFO. The "peripheral" side is
+    // our source.
+    dma.s0fcr.write(|w| w.dmdis().set_bit().fth().half());
+    dma.s0par.write(|w| w.pa().bits(source as u32));
+    dma.s0m0ar.write(|w| w.m0a().bits(dest as u32));
+    dma.s0ndtr.write(|w| w.ndt().bits(len as u16));
+
+    // Ensure the buffers' contents are visible to the DMA controller before it
+    // starts.
+    cortex_m::asm::dmb();
+
+    dma.s0cr.write(|w| {
+        w.chsel()
+            .bits(0)
+            .pl()
+            .low()
+            .mburst()
+            .single()
+            .pburst()
+            .single()
+            .msize()
+            .word()
+            .psize()
+            .word()
+            .minc()
+            .set_bit()
+            .pinc()
+            .bit(source_increments)
+            .dir()
+            .memory_to_memory()
+            .en()
+            .set_bit()
+    });
+}
+
+/// Checks whether the most recent transfer on stream 0 has finished.
+///
+/// # Panics
+///
+/// If the DMA controller reported a transfer error.
+fn is_complete() -> bool {
+    let lisr = dma2().lisr.read();
+    assert!(!lisr.teif0().bit_is_set(), "DMA copy transfer error");
+    lisr.tcif0().bit_is_set()
+}
+
+/// Gets the DMA2 register block.
+fn dma2() -> &'static device::dma2::RegisterBlock {
+    // Safety: the driver owns DMA2, but its ISRs only touch stream 5 and the
+    // high interrupt status/clear registers. Stream 0 and the low registers
+    // are ours, and the clear register is write-one-to-clear, so we can't
+    // disturb the other streams' flags.
+    unsafe { &*device::DMA2::ptr() }
+}
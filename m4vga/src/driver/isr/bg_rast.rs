@@ -10,9 +10,12 @@ use crate::priority;
 use crate::rast::{RasterCtx, TargetBuffer};
 use crate::timing::{Timing, MIN_CYCLES_PER_PIXEL};
 use crate::util::measurement;
+#[cfg(feature = "profile")]
+use crate::util::profile::LineHistogram;
 use crate::util::spin_lock::SpinLock;
 use super::super::{
-    acquire_hw, vert_state, NextTransfer, VState, HPSHARE, LINE, RASTER, TIMING,
+    acquire_hw, vert_state, NextTransfer, VState, FLIPPER, HPSHARE, LINE,
+    RASTER, TIMING,
 };
 
 /// Equivalent of `rast::TargetBuffer`, but as words to ensure alignment for
@@ -78,6 +81,17 @@ static mut RASTER_STATE: SpinLock<RasterState> = SpinLock::new(RasterState {
 #[link_section = ".scanout_bss"]
 static mut GLOBAL_SCANOUT_BUFFER: WorkingBuffer = [0; WORKING_BUFFER_SIZE];
 
+/// Releases the lock on `RASTER_STATE`, which may still be held by an
+/// invocation of the raster ISR that was interrupted by a panic.
+///
+/// # Safety
+///
+/// The code holding the lock, if any, must never resume.
+#[cfg(feature = "panic-vga")]
+pub(crate) unsafe fn force_unlock_raster_state() {
+    (*core::ptr::addr_of!(RASTER_STATE)).force_unlock()
+}
+
 /// Raster ISR: call this from `PendSV`.
 ///
 /// This is one of three ISRs you must wire up for the driver to work. In the
@@ -91,6 +105,17 @@ static mut GLOBAL_SCANOUT_BUFFER: WorkingBuffer = [0; WORKING_BUFFER_SIZE];
 /// }
 /// ```
 pub fn maintain_raster_isr() {
+    maintain_raster(|ln, target, ctx, priority| {
+        // Ignore errors if the rasterizer is not there yet.
+        let _ = RASTER.observe(|r| r(ln, target, ctx, priority));
+    })
+}
+
+/// Body of the raster ISR, parameterized on the raster callback. The panic
+/// handler uses this to substitute its own callback for the application's.
+pub(crate) fn maintain_raster(
+    rast: impl FnOnce(usize, &mut TargetBuffer, &mut RasterCtx, priority::I0),
+) {
     // Safety: RASTER_STATE is mut only because it captures a &mut to
     // GLOBAL_WORKING_BUFFER in its initializer, and rustc is picky about that
     // pattern. This access is safe because we don't circumvent the spinlock.
@@ -144,6 +169,19 @@ pub fn maintain_raster_isr() {
         }
     }
 
+    // Once active video is over, the front buffer of a `FlipBuffer` is free to
+    // change, so swap in any newly presented frame now. The renderer can then
+    // get its next back buffer as soon as it sees vblank.
+    if vs == VState::Blank {
+        if let Ok(flipper) = FLIPPER.try_lock() {
+            if let Some(flipper) = *flipper {
+                // Safety: the rasterizer only runs in rendered states, from
+                // this ISR, so it can't be using the front buffer.
+                unsafe { flipper.flip() }
+            }
+        }
+    }
+
     // Allow the application to do additional work during what's left of hblank.
     //vga_hblank_interrupt(); TODO implement this someday, Glitch needs it
 
@@ -171,18 +209,63 @@ pub fn maintain_raster_isr() {
             ..
         } = *TIMING.try_lock().expect("pendsv timing").as_mut().unwrap();
 
+        // Collect what we need to profile the rasterizer. The line number has
+        // to be read now, because it will advance at EAV, which may happen
+        // while the rasterizer runs.
+        #[cfg(feature = "profile")]
+        let (line, line_budget, start) = {
+            let timing = TIMING.try_lock().expect("pendsv timing");
+            let timing = timing.as_ref().unwrap();
+            let budget = crate::util::profile::line_budget(
+                timing.line_pixels,
+                timing.cycles_per_pixel(),
+            );
+            let line = LINE.load(Ordering::Relaxed) + 1 - video_start_line;
+            (line, budget, crate::util::profile::cycles())
+        };
+
         // Run the rasterizer.
         state.update_scan_buffer = rasterize_next_line(
             add_cycles_per_pixel + MIN_CYCLES_PER_PIXEL,
             video_start_line,
             &mut state.raster_ctx,
             &mut state.working_buffer,
+            rast,
         );
 
+        #[cfg(feature = "profile")]
+        {
+            // Only lines where the rasterizer actually ran are interesting.
+            if state.update_scan_buffer {
+                let cycles = crate::util::profile::cycles().wrapping_sub(start);
+                record_raster_profile(line, cycles, line_budget);
+            }
+        }
+
         measurement::sig_b_clear(); // signal rasterizer exit
     }
 }
 
+/// Histogram of raster callback costs, maintained if the `profile` feature is
+/// enabled.
+#[cfg(feature = "profile")]
+pub static RASTER_PROFILE: SpinLock<LineHistogram> =
+    SpinLock::new(LineHistogram::new(0));
+
+/// Adds a sample to `RASTER_PROFILE`, resetting it if the display timing (and
+/// thus the budget) has changed.
+#[cfg(feature = "profile")]
+fn record_raster_profile(line: usize, cycles: u32, budget: u32) {
+    // If thread code is reading the profile, drop this sample rather than
+    // wait.
+    if let Ok(mut profile) = RASTER_PROFILE.try_lock() {
+        if profile.budget() != budget {
+            profile.reset(budget)
+        }
+        profile.record(line, cycles)
+    }
+}
+
 /// Copy the first `len_bytes` of `working` into the global scanout buffer for
 /// DMA.
 fn update_scan_buffer(target_range: Range<usize>, working: &mut WorkingBuffer) {
@@ -379,6 +462,7 @@ fn rasterize_next_line(
     video_start_line: usize,
     ctx: &mut RasterCtx,
     working: &mut WorkingBuffer,
+    rast: impl FnOnce(usize, &mut TargetBuffer, &mut RasterCtx, priority::I0),
 ) -> bool {
     let current_line = LINE.load(Ordering::Relaxed);
     let next_line = current_line + 1;
@@ -396,10 +480,8 @@ fn rasterize_next_line(
             repeat_lines: 0,
             target_range: 0..0,
         };
-        // Invoke the rasterizer. Ignore errors if it's not there yet.
-        let _ = RASTER.observe(|r| {
-            r(visible_line, working_buffer_as_u8(working), ctx, priority)
-        });
+        // Invoke the rasterizer.
+        rast(visible_line, working_buffer_as_u8(working), ctx, priority);
         true
     } else {
         // repeat_lines > 0
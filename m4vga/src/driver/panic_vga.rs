@@ -0,0 +1,270 @@
+//! Blue-screen panic handler, enabled by the `panic-vga` feature.
+//!
+//! When a demo panics, the usual handlers either halt (leaving a frozen or
+//! black display) or report the panic over ITM, which is only useful with a
+//! debugger attached. This handler instead takes over the driver and displays
+//! the panic message, location, and state of the raster callback on the
+//! monitor, in white 10x16 text on a blue background.
+//!
+//! To use it, build with the `panic-vga` feature and *without* any other panic
+//! handler crate -- for the demos, that means `--no-default-features
+//! --features panic-vga`.
+//!
+//! # Theory of operation
+//!
+//! The panic may have happened anywhere: in thread mode, in the raster
+//! callback, or in one of the driver ISRs. In the latter cases the interrupted
+//! code may be holding locks, and the ISR will never return, so the hardware
+//! won't generate any more interrupts at its priority level.
+//!
+//! So, rather than relying on interrupts, the handler masks them all and runs
+//! the driver's ISRs itself, by polling for pending interrupts. This works
+//! from any context. Any locks held by the abandoned code are forcibly
+//! released first; since the panic handler never returns, the abandoned code
+//! can't notice.
+//!
+//! Polling adds a bit of latency to the start of each line, compared to the
+//! real ISRs, so the display may be slightly wobbly. It's good enough to
+//! photograph.
+//!
+//! If the panic happens before timing is configured, there's nothing to take
+//! over, and the handler simply halts.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{self, AtomicBool, Ordering};
+
+use stm32f4::stm32f407 as device;
+
+use crate::color::Color;
+use crate::rast::text_10x16::{self, AChar, GLYPH_COLS, GLYPH_ROWS};
+use crate::rast::{self, RasterCtx, TargetBuffer};
+use crate::util::armv7m::clear_pending_irq;
+use crate::util::spin_lock::SpinLock;
+use crate::Pixel;
+
+use super::isr::{bg_rast, hstate, shock};
+use super::{Live, Vga, HPSHARE, RASTER, TIMING};
+
+/// Number of text columns in the panic screen. Narrower video modes show only
+/// a prefix of each row.
+const COLS: usize = 80;
+/// Number of text rows in the panic screen, which fills 592 lines of an
+/// 800x600 display.
+const ROWS: usize = 37;
+
+const FOREGROUND: Pixel = Color::WHITE.to_pixel();
+const BACKGROUND: Pixel = Color::from_levels(0, 0, 2).to_pixel();
+
+const BLANK: AChar = AChar::from_ascii_char(b' ')
+    .with_foreground(FOREGROUND)
+    .with_background(BACKGROUND);
+
+/// The text of the panic screen.
+static TEXT: SpinLock<[AChar; COLS * ROWS]> =
+    SpinLock::new([BLANK; COLS * ROWS]);
+
+/// Set on entry to the panic handler, so that we can detect a panic within the
+/// handler itself.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        // We panicked while trying to display a panic. Give up.
+        halt()
+    }
+
+    if let Ok(mut text) = TEXT.try_lock() {
+        // Writes to the screen can't fail -- excess text is dropped -- so we
+        // ignore the results below.
+        let _ = write_report(&mut Screen::new(&mut text), info);
+    }
+
+    // Safety: the code holding these locks, if any, was interrupted by the
+    // panic and will never resume.
+    unsafe {
+        HPSHARE.force_unlock();
+        TIMING.force_unlock();
+        shock::SHOCK_TIMER.force_unlock();
+        bg_rast::force_unlock_raster_state();
+    }
+
+    let configured = HPSHARE.try_lock().map(|s| s.is_some()).unwrap_or(false);
+    let shape = TIMING.try_lock().ok().and_then(|timing| {
+        timing.as_ref().map(|t| {
+            let cols = usize::min(COLS, t.video_pixels / GLYPH_COLS);
+            let lines = t.video_end_line - t.video_start_line;
+            (cols, lines)
+        })
+    });
+
+    match (configured, shape) {
+        (true, Some((cols, lines))) => scan_forever(cols, lines),
+        _ => halt(),
+    }
+}
+
+/// Fills in the panic screen.
+fn write_report(screen: &mut Screen, info: &PanicInfo) -> fmt::Result {
+    screen.inverse = true;
+    write!(screen, "{:<1$}", " m4vga: panic", COLS)?;
+    screen.inverse = false;
+    // End the heading row, and leave a blank one after it.
+    writeln!(screen)?;
+    writeln!(screen)?;
+
+    if let Some(location) = info.location() {
+        writeln!(
+            screen,
+            "panicked at {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        )?;
+    } else {
+        writeln!(screen, "panicked at unknown location")?;
+    }
+    writeln!(screen)?;
+
+    writeln!(screen, "{}", info.message())?;
+    writeln!(screen)?;
+
+    writeln!(screen, "raster callback: {}", RASTER.describe())
+}
+
+/// Runs the driver's ISRs by polling, with our own raster callback, until the
+/// end of time.
+fn scan_forever(cols: usize, lines: usize) -> ! {
+    // Enable video output, in case the panic happened before the application
+    // got around to it.
+    //
+    // Safety: we're stealing the driver's hardware, but the driver is never
+    // going to run again, except under our control.
+    unsafe {
+        let p = device::Peripherals::steal();
+        let cp = cortex_m::Peripherals::steal();
+        Vga {
+            rcc: p.RCC,
+            flash: p.FLASH,
+            gpioe: p.GPIOE,
+            nvic: cp.NVIC,
+            mode_state: Live(()),
+        }
+        .video_on();
+    }
+
+    loop {
+        // Interrupts are masked, but WFI still wakes when one becomes pending.
+        cortex_m::asm::wfi();
+
+        // Service the interrupts in the order their timing requires: the shock
+        // absorber idles until start-of-active-video, which hstate handles,
+        // and hstate pends the rasterizer at end-of-active-video.
+        if cortex_m::peripheral::NVIC::is_pending(device::Interrupt::TIM3) {
+            shock::shock_absorber_isr();
+            clear_pending_irq(device::Interrupt::TIM3);
+        }
+        if cortex_m::peripheral::NVIC::is_pending(device::Interrupt::TIM4) {
+            hstate::hstate_isr();
+            clear_pending_irq(device::Interrupt::TIM4);
+        }
+        if cortex_m::peripheral::SCB::is_pendsv_pending() {
+            cortex_m::peripheral::SCB::clear_pendsv();
+            bg_rast::maintain_raster(|ln, target, ctx, _| {
+                panic_raster(ln, target, ctx, cols, lines)
+            });
+        }
+    }
+}
+
+/// Raster callback for the panic screen.
+fn panic_raster(
+    ln: usize,
+    target: &mut TargetBuffer,
+    ctx: &mut RasterCtx,
+    cols: usize,
+    lines: usize,
+) {
+    let text_lines = usize::min(ROWS * GLYPH_ROWS, lines);
+    if ln < text_lines {
+        let text = TEXT.try_lock().expect("panic text");
+        let row = ln / GLYPH_ROWS;
+        let row = &text[row * COLS..row * COLS + cols];
+        text_10x16::unpack_raw(
+            row,
+            &font_10x16::FONT.as_glyph_slices()[ln % GLYPH_ROWS],
+            &mut target[..cols * GLYPH_COLS],
+        );
+        ctx.target_range = 0..cols * GLYPH_COLS;
+    } else {
+        // Fill out any partial row at the bottom.
+        rast::solid_color_fill(target, ctx, cols * GLYPH_COLS, BACKGROUND);
+        ctx.repeat_lines = lines - ln - 1;
+    }
+}
+
+fn halt() -> ! {
+    loop {
+        atomic::compiler_fence(Ordering::SeqCst)
+    }
+}
+
+/// Cursor for writing text into the panic screen. Text that doesn't fit is
+/// wrapped, and anything past the last row is dropped.
+struct Screen<'a> {
+    text: &'a mut [AChar; COLS * ROWS],
+    row: usize,
+    col: usize,
+    /// Swaps foreground and background colors, for headings.
+    inverse: bool,
+}
+
+impl<'a> Screen<'a> {
+    fn new(text: &'a mut [AChar; COLS * ROWS]) -> Self {
+        Screen {
+            text,
+            row: 0,
+            col: 0,
+            inverse: false,
+        }
+    }
+
+    fn newline(&mut self) {
+        self.row += 1;
+        self.col = 0;
+    }
+
+    fn putc(&mut self, c: u8) {
+        if self.col == COLS {
+            self.newline()
+        }
+        if self.row < ROWS {
+            let (fg, bg) = if self.inverse {
+                (BACKGROUND, FOREGROUND)
+            } else {
+                (FOREGROUND, BACKGROUND)
+            };
+            self.text[self.row * COLS + self.col] = AChar::from_ascii_char(c)
+                .with_foreground(fg)
+                .with_background(bg);
+            self.col += 1;
+        }
+    }
+}
+
+impl<'a> fmt::Write for Screen<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match c {
+                '\n' => self.newline(),
+                // The font only covers ASCII reliably.
+                c if c.is_ascii() => self.putc(c as u8),
+                _ => self.putc(b'?'),
+            }
+        }
+        Ok(())
+    }
+}
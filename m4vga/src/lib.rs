@@ -1,5 +1,10 @@
 #![no_std]
 
+// The host simulation keeps per-thread state; see `priority`.
+#[cfg(not(target_os = "none"))]
+extern crate std;
+
+pub mod color;
 pub mod rast;
 pub mod util;
 
@@ -4,6 +4,19 @@
 //! interrupt hook, it will pull an appropriate priority token out of thin air
 //! and hand it to the hook. This gives the hook the ability to take certain
 //! actions that would otherwise be off-limits.
+//!
+//! Code can also check its priority at runtime using `Thread::new_checked` and
+//! `I0::new_checked`, which produce a token only when called from the matching
+//! context.
+//!
+//! # Simulation
+//!
+//! There are no interrupts when the driver is simulated on a hosted target.
+//! Instead, the simulator tracks which priority it's pretending to run at, and
+//! invokes interrupt-priority code (e.g. raster callbacks) using
+//! `simulate_i0`. The `new_checked` functions consult this tracker, so code
+//! that tries to get a `Thread` token from a raster callback fails the same
+//! way in simulation as on hardware.
 
 use core::marker::PhantomData;
 
@@ -26,6 +39,33 @@ impl I0 {
     }
 }
 
+#[cfg(target_os = "none")]
+impl I0 {
+    /// Returns an `I0` token only if called from the `I0` interrupt handler.
+    pub fn new_checked() -> Option<Self> {
+        // Safety: reads of the ICSR are safe.
+        let icsr = unsafe { &(*cortex_m::peripheral::SCB::ptr()).icsr }.read();
+        if icsr & 0xFF == PENDSV_VECTOR {
+            Some(unsafe { Self::new() })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "none"))]
+impl I0 {
+    /// Returns an `I0` token only if called from a simulated `I0` interrupt;
+    /// see `simulate_i0`.
+    pub fn new_checked() -> Option<Self> {
+        if sim::active() == sim::I0 {
+            Some(unsafe { Self::new() })
+        } else {
+            None
+        }
+    }
+}
+
 /*
 TODO: re-enable when we start supporting hblank hooks
 impl I1 {
@@ -61,14 +101,140 @@ impl Thread {
 
 #[cfg(not(target_os = "none"))]
 impl Thread {
-    /// Returns a `Thread` token only if called from thread priority.
+    /// Returns a `Thread` token only if called from thread priority, i.e. not
+    /// from within `simulate_i0`.
     pub fn new_checked() -> Option<Self> {
-        Some(unsafe { Self::new() })
+        if sim::active() == sim::THREAD {
+            Some(unsafe { Self::new() })
+        } else {
+            None
+        }
     }
 }
 
+/// Exception number of PendSV, which the driver uses for `I0`, as reported in
+/// the ICSR's VECTACTIVE field.
+#[cfg(target_os = "none")]
+const PENDSV_VECTOR: u32 = 14;
+
 /// Indicates that a type represents an interrupt priority level.
-pub trait InterruptPriority {}
+pub trait InterruptPriority {
+    /// Runs `body` with interrupts at this priority level, and all lower
+    /// levels, masked. Higher-priority interrupts can still preempt `body`.
+    ///
+    /// This is the basis of `util::priority_cell`.
+    fn mask<R>(body: impl FnOnce() -> R) -> R;
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "none")] {
+        impl InterruptPriority for I0 {
+            fn mask<R>(body: impl FnOnce() -> R) -> R {
+                use cortex_m::register::{basepri, basepri_max};
 
-impl InterruptPriority for I0 {}
-impl InterruptPriority for I1 {}
+                // I0 is PendSV, which the driver sets to the lowest possible
+                // priority, 0xFF. Setting BASEPRI to the same value masks it
+                // without affecting the timing-critical interrupts.
+                let previous = basepri::read();
+                basepri_max::write(0xFF);
+                let result = body();
+                // Safety: we're restoring the mask that was in effect when we
+                // were called, which can't unmask anything our caller expected
+                // to stay masked.
+                unsafe { basepri::write(previous) }
+                result
+            }
+        }
+
+        impl InterruptPriority for I1 {
+            fn mask<R>(body: impl FnOnce() -> R) -> R {
+                // I1 is TIM4, at priority 0, which BASEPRI cannot mask. Mask
+                // everything instead.
+                cortex_m::interrupt::free(|_| body())
+            }
+        }
+    } else {
+        // There are no interrupts in simulation, but we record masking so
+        // that `simulate_i0` can catch interrupts the hardware would defer.
+
+        impl InterruptPriority for I0 {
+            fn mask<R>(body: impl FnOnce() -> R) -> R {
+                sim::mask(sim::I0, body)
+            }
+        }
+
+        impl InterruptPriority for I1 {
+            fn mask<R>(body: impl FnOnce() -> R) -> R {
+                sim::mask(sim::I1, body)
+            }
+        }
+
+        /// Runs `body` as though it were the `I0` interrupt handler, passing it
+        /// an `I0` token. For use by simulators, e.g. to invoke raster
+        /// callbacks.
+        ///
+        /// # Panics
+        ///
+        /// If called from a simulated interrupt, or while `I0` is masked. On
+        /// hardware the interrupt would wait, but the simulator has no way to
+        /// defer it, so this indicates a bug in the simulator.
+        pub fn simulate_i0<R>(body: impl FnOnce(I0) -> R) -> R {
+            sim::interrupt(sim::I0, || body(unsafe { I0::new() }))
+        }
+
+        /// Priority context tracking for simulation.
+        ///
+        /// Each host thread simulates its own processor, so the trackers are
+        /// thread-local. This keeps concurrently running tests from seeing
+        /// each other's interrupts.
+        mod sim {
+            use core::cell::Cell;
+
+            pub const THREAD: u8 = 0;
+            pub const I0: u8 = 1;
+            pub const I1: u8 = 2;
+
+            std::thread_local! {
+                /// Priority level currently being simulated.
+                static ACTIVE: Cell<u8> = const { Cell::new(THREAD) };
+                /// Highest priority level currently masked, or `THREAD` if
+                /// none.
+                static MASKED: Cell<u8> = const { Cell::new(THREAD) };
+            }
+
+            pub fn active() -> u8 {
+                ACTIVE.with(Cell::get)
+            }
+
+            pub fn mask<R>(level: u8, body: impl FnOnce() -> R) -> R {
+                let previous = MASKED.with(|m| m.replace(m.get().max(level)));
+                let _restore = Restore(&MASKED, previous);
+                body()
+            }
+
+            pub fn interrupt<R>(level: u8, body: impl FnOnce() -> R) -> R {
+                assert!(
+                    active() < level,
+                    "simulated interrupt preempting equal or higher priority"
+                );
+                assert!(
+                    MASKED.with(Cell::get) < level,
+                    "simulated interrupt while masked"
+                );
+                let previous = ACTIVE.with(|a| a.replace(level));
+                let _restore = Restore(&ACTIVE, previous);
+                body()
+            }
+
+            /// Restores a tracker to its previous value when dropped, even if
+            /// the body panics.
+            struct Restore(&'static std::thread::LocalKey<Cell<u8>>, u8);
+
+            impl Drop for Restore {
+                fn drop(&mut self) {
+                    self.0.with(|c| c.set(self.1))
+                }
+            }
+        }
+    }
+}
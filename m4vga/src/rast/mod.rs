@@ -265,4 +265,18 @@ impl IRef {
                 result
             })
     }
+
+    /// Describes the state of the `IRef`, for diagnostic purposes.
+    #[cfg(feature = "panic-vga")]
+    pub(crate) fn describe(&self) -> &'static str {
+        if self.poisoned.load(Ordering::Acquire) {
+            return "poisoned by an earlier panic in the raster callback";
+        }
+        match self.state.load(Ordering::Acquire) {
+            EMPTY => "empty (no raster callback provided)",
+            LOADING => "loading (raster callback being provided)",
+            LOADED => "loaded (raster callback idle)",
+            _ => "locked (panic was probably in the raster callback)",
+        }
+    }
 }
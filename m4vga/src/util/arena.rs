@@ -0,0 +1,380 @@
+//! Bump allocation from the RAM left over after statics are placed.
+//!
+//! The linker script reserves whatever is left of each RAM, after statics and
+//! stacks, as an *arena*. This module hands out `&'static mut` slices from
+//! those arenas.
+//!
+//! The RAMs on the STM32F407 are not interchangeable:
+//!
+//! - SRAM1 (112 kiB) is fast, DMA-capable, and can be bit-banded -- but only
+//!   through its native address, not the alias at zero where we normally use
+//!   it. See `Sram1`.
+//! - SRAM2 (16 kiB) is DMA-capable and bit-band-capable. It also holds the
+//!   scanout buffer, so heavy CPU traffic here can disturb the display.
+//! - CCM (64 kiB) is private to the CPU: neither DMA nor bit-banding can reach
+//!   it.
+//!
+//! Each arena is represented by a distinct type (`Arena<Sram1>`, etc.), and
+//! operations that only make sense in some RAMs -- like `alloc_bit_band` and
+//! `alloc_dma` -- are only available for those types. Code that needs a
+//! bit-band-capable buffer can thus ask for one, instead of hoping it ended up
+//! in the right place. Likewise, code that feeds buffers to DMA takes a
+//! `DmaBuffer`, which only comes from a RAM that DMA can reach.
+//!
+//! Each arena can be taken exactly once, much like the peripherals in
+//! `cortex_m` and `stm32f4`. On hosted targets there are no arenas to take, but
+//! `Arena::from_bounds` can manage other memory for testing.
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+#[cfg(target_os = "none")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(target_os = "none")]
+extern "C" {
+    static _arena_sram1_start: u32;
+    static _arena_sram1_end: u32;
+    static _arena_sram16_start: u32;
+    static _arena_sram16_end: u32;
+    static _arena_ccm_start: u32;
+    static _arena_ccm_end: u32;
+}
+
+/// Implementation details of `Region`, which is sealed: the set of RAMs is
+/// fixed by the hardware.
+mod sealed {
+    #[cfg(target_os = "none")]
+    use core::sync::atomic::AtomicBool;
+
+    pub trait Sealed {
+        /// Flag recording whether the arena has been taken.
+        #[cfg(target_os = "none")]
+        fn taken() -> &'static AtomicBool;
+        /// Address range of the arena, as `(start, end)`.
+        #[cfg(target_os = "none")]
+        fn bounds() -> (usize, usize);
+    }
+}
+
+/// A RAM with an arena.
+pub trait Region: sealed::Sealed {}
+
+/// A RAM that can be accessed through the bit-band alias region.
+pub trait BitBand: Region {
+    /// Translates an arena address into the bit-band target region, if it's
+    /// not already there.
+    fn bit_band_target(addr: usize) -> usize;
+}
+
+/// A RAM that the DMA controllers can read and write.
+pub trait DmaCapable: Region {
+    /// Translates an arena address into the address the DMA controllers use
+    /// for the same memory.
+    fn dma_address(addr: usize) -> usize;
+}
+
+/// SRAM1, the 112 kiB main SRAM.
+///
+/// This RAM is remapped at address zero during startup, and the arena hands
+/// out addresses in that alias. Bit-banding, however, is only available at its
+/// native address, `0x2000_0000`; use `Arena::alloc_bit_band` to get a slice
+/// there.
+pub enum Sram1 {}
+
+/// SRAM2, the 16 kiB auxiliary SRAM, which also holds the scanout buffer.
+pub enum Sram2 {}
+
+/// Core-Coupled Memory, which can only be accessed by the CPU.
+pub enum Ccm {}
+
+#[cfg(target_os = "none")]
+static SRAM1_TAKEN: AtomicBool = AtomicBool::new(false);
+#[cfg(target_os = "none")]
+static SRAM2_TAKEN: AtomicBool = AtomicBool::new(false);
+#[cfg(target_os = "none")]
+static CCM_TAKEN: AtomicBool = AtomicBool::new(false);
+
+impl sealed::Sealed for Sram1 {
+    #[cfg(target_os = "none")]
+    fn taken() -> &'static AtomicBool {
+        &SRAM1_TAKEN
+    }
+    #[cfg(target_os = "none")]
+    fn bounds() -> (usize, usize) {
+        unsafe {
+            (
+                &_arena_sram1_start as *const _ as usize,
+                &_arena_sram1_end as *const _ as usize,
+            )
+        }
+    }
+}
+
+impl sealed::Sealed for Sram2 {
+    #[cfg(target_os = "none")]
+    fn taken() -> &'static AtomicBool {
+        &SRAM2_TAKEN
+    }
+    #[cfg(target_os = "none")]
+    fn bounds() -> (usize, usize) {
+        unsafe {
+            (
+                &_arena_sram16_start as *const _ as usize,
+                &_arena_sram16_end as *const _ as usize,
+            )
+        }
+    }
+}
+
+impl sealed::Sealed for Ccm {
+    #[cfg(target_os = "none")]
+    fn taken() -> &'static AtomicBool {
+        &CCM_TAKEN
+    }
+    #[cfg(target_os = "none")]
+    fn bounds() -> (usize, usize) {
+        unsafe {
+            (
+                &_arena_ccm_start as *const _ as usize,
+                &_arena_ccm_end as *const _ as usize,
+            )
+        }
+    }
+}
+
+impl Region for Sram1 {}
+impl Region for Sram2 {}
+impl Region for Ccm {}
+
+impl BitBand for Sram1 {
+    fn bit_band_target(addr: usize) -> usize {
+        addr | 0x2000_0000
+    }
+}
+
+impl BitBand for Sram2 {
+    fn bit_band_target(addr: usize) -> usize {
+        addr
+    }
+}
+
+impl DmaCapable for Sram1 {
+    fn dma_address(addr: usize) -> usize {
+        // The remapping at address zero is only visible to the CPU.
+        addr | 0x2000_0000
+    }
+}
+
+impl DmaCapable for Sram2 {
+    fn dma_address(addr: usize) -> usize {
+        addr
+    }
+}
+
+/// The unallocated portion of the arena in RAM `R`.
+pub struct Arena<R: Region> {
+    next: usize,
+    end: usize,
+    _region: PhantomData<R>,
+}
+
+impl<R: Region> Arena<R> {
+    /// Takes the arena for `R`. This succeeds only once per region; later calls
+    /// return `None`.
+    #[cfg(target_os = "none")]
+    pub fn take() -> Option<Self> {
+        if R::taken().swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        let (start, end) = R::bounds();
+        // Safety: the linker reserved this memory for the arena, and we've
+        // just ensured that nobody else has it.
+        Some(unsafe { Self::from_bounds(start, end) })
+    }
+
+    /// Creates an arena that allocates from the addresses `start..end`, instead
+    /// of the one the linker reserved in `R`.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be valid for reads and writes forever, and not used by
+    /// anything but the arena. It must also be in `R`, or reachable through
+    /// the same aliases, since bit-band and DMA allocations translate
+    /// addresses as `R` requires.
+    pub unsafe fn from_bounds(start: usize, end: usize) -> Self {
+        assert!(start <= end, "arena ends before it starts");
+        Arena {
+            next: start,
+            end,
+            _region: PhantomData,
+        }
+    }
+
+    /// Number of bytes left in the arena, ignoring alignment.
+    pub fn remaining(&self) -> usize {
+        self.end - self.next
+    }
+
+    /// Allocates `count` elements of type `T`, each initialized to `value`,
+    /// aligned as `T` requires.
+    ///
+    /// Returns `None` if the arena doesn't have room.
+    pub fn alloc_slice<T: Copy>(
+        &mut self,
+        count: usize,
+        value: T,
+    ) -> Option<&'static mut [T]> {
+        self.alloc_slice_aligned(count, mem::align_of::<T>(), value)
+    }
+
+    /// Allocates `count` elements of type `T`, each initialized to `value`,
+    /// starting at an address that is a multiple of `align`. This is useful for
+    /// e.g. buffers used by DMA bursts.
+    ///
+    /// Returns `None` if the arena doesn't have room.
+    ///
+    /// # Panics
+    ///
+    /// If `align` is not a power of two, or is smaller than the alignment `T`
+    /// requires.
+    pub fn alloc_slice_aligned<T: Copy>(
+        &mut self,
+        count: usize,
+        align: usize,
+        value: T,
+    ) -> Option<&'static mut [T]> {
+        let addr = self.alloc_bytes(count, align, value)?;
+        // Safety: `alloc_bytes` has given us exclusive ownership of
+        // initialized, aligned memory for `count` `T`s, and we won't hand it
+        // out again.
+        Some(unsafe { core::slice::from_raw_parts_mut(addr as *mut T, count) })
+    }
+
+    /// Reserves and initializes space for `count` `T`s, returning its address.
+    fn alloc_bytes<T: Copy>(
+        &mut self,
+        count: usize,
+        align: usize,
+        value: T,
+    ) -> Option<usize> {
+        assert!(align.is_power_of_two(), "bad alignment: {}", align);
+        assert!(align >= mem::align_of::<T>(), "alignment too small for T");
+
+        let start = self.next.checked_add(align - 1)? & !(align - 1);
+        let size = mem::size_of::<T>().checked_mul(count)?;
+        let end = start.checked_add(size)?;
+        if end > self.end {
+            return None;
+        }
+        self.next = end;
+
+        let ptr = start as *mut T;
+        for i in 0..count {
+            // Safety: this memory is within the arena, and nobody else has a
+            // reference to it. We use `write` because it is uninitialized.
+            unsafe { ptr.add(i).write(value) }
+        }
+        Some(start)
+    }
+}
+
+impl<R: DmaCapable> Arena<R> {
+    /// Allocates a `DmaBuffer` of `count` words, each initialized to `value`.
+    ///
+    /// Returns `None` if the arena doesn't have room.
+    pub fn alloc_dma(
+        &mut self,
+        count: usize,
+        value: u32,
+    ) -> Option<DmaBuffer<R>> {
+        Some(DmaBuffer {
+            words: self.alloc_slice(count, value)?,
+            _region: PhantomData,
+        })
+    }
+}
+
+impl<R: BitBand + DmaCapable> Arena<R> {
+    /// Allocates a `DmaBuffer` of `count` words, each initialized to `value`,
+    /// in the bit-band target region, like `alloc_bit_band`.
+    ///
+    /// Returns `None` if the arena doesn't have room.
+    pub fn alloc_dma_bit_band(
+        &mut self,
+        count: usize,
+        value: u32,
+    ) -> Option<DmaBuffer<R>> {
+        Some(DmaBuffer {
+            words: self.alloc_bit_band(count, value)?,
+            _region: PhantomData,
+        })
+    }
+}
+
+impl<R: BitBand> Arena<R> {
+    /// Allocates `count` words, each initialized to `value`, in the bit-band
+    /// target region. The result can be passed to `gfx::bit::as_bits_mut` or
+    /// used for a `gfx::PackedBitBuffer`.
+    ///
+    /// This is only available for RAMs that support bit-banding, so it's not
+    /// possible to allocate a bit-band buffer in CCM by mistake.
+    ///
+    /// Returns `None` if the arena doesn't have room.
+    pub fn alloc_bit_band(
+        &mut self,
+        count: usize,
+        value: u32,
+    ) -> Option<&'static mut [u32]> {
+        let addr = self.alloc_bytes(count, mem::align_of::<u32>(), value)?;
+        let addr = R::bit_band_target(addr);
+        // Safety: as in `alloc_slice_aligned`. The bit-band target region
+        // address aliases the memory we just initialized, and no references
+        // exist through the other alias.
+        Some(unsafe {
+            core::slice::from_raw_parts_mut(addr as *mut u32, count)
+        })
+    }
+}
+
+/// A buffer of words allocated from the arena of a RAM that DMA can reach.
+///
+/// This derefs to `[u32]` for use by the CPU. APIs that point a DMA controller
+/// at memory, like `driver::DmaCopier`, take this instead of a plain slice, so
+/// that the compiler rules out buffers DMA can't reach.
+pub struct DmaBuffer<R: DmaCapable> {
+    words: &'static mut [u32],
+    _region: PhantomData<R>,
+}
+
+impl<R: DmaCapable> DmaBuffer<R> {
+    /// Gets the address of the buffer as seen by the DMA controllers.
+    pub fn dma_address(&self) -> usize {
+        R::dma_address(self.words.as_ptr() as usize)
+    }
+}
+
+impl<R: DmaCapable> Deref for DmaBuffer<R> {
+    type Target = [u32];
+    fn deref(&self) -> &[u32] {
+        self.words
+    }
+}
+
+impl<R: DmaCapable> DerefMut for DmaBuffer<R> {
+    fn deref_mut(&mut self) -> &mut [u32] {
+        self.words
+    }
+}
+
+impl<R: DmaCapable> AsRef<[u32]> for DmaBuffer<R> {
+    fn as_ref(&self) -> &[u32] {
+        self.words
+    }
+}
+
+impl<R: DmaCapable> AsMut<[u32]> for DmaBuffer<R> {
+    fn as_mut(&mut self) -> &mut [u32] {
+        self.words
+    }
+}
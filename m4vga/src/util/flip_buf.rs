@@ -0,0 +1,334 @@
+//! Page-flipping framebuffers.
+//!
+//! A `FlipBuffer` manages two or three complete framebuffers. At any given
+//! time, one of them is the *front* buffer, which the rasterizer reads during
+//! scanout. The renderer draws into a *back* buffer and then *presents* it.
+//! Between frames, the front buffer is swapped for the most recently presented
+//! one. No pixels are copied, and because the swap happens between frames,
+//! scanout never observes a partially drawn frame.
+//!
+//! The swap normally happens at the end of active video: hand the driver a
+//! `Flipper` (from `FlipReader::flipper`) using `Vga::with_flip`, and a frame
+//! presented before vblank is on display -- and its back buffer free -- by the
+//! time `sync_to_vblank` returns. Without that, the reader swaps when the
+//! rasterizer first asks for the front buffer in a frame, which may be well
+//! into the next frame's active video.
+//!
+//! With two buffers, the renderer must wait for each presented frame to reach
+//! the display before it can start on the next one. With three, the renderer
+//! can always get a back buffer immediately; if it presents several frames
+//! before the rasterizer swaps, only the most recent one is shown.
+
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::priority;
+
+/// Value of the `ready` field of the state word when no frame is waiting.
+const NONE: usize = 0b11;
+
+/// Extracts the index of the front buffer from a state word.
+fn front(state: usize) -> usize {
+    state & 0b11
+}
+
+/// Extracts the index of the presented-but-not-displayed buffer from a state
+/// word, if there is one.
+fn ready(state: usize) -> Option<usize> {
+    match (state >> 2) & 0b11 {
+        NONE => None,
+        i => Some(i),
+    }
+}
+
+/// Packs front and ready indices into a state word.
+fn pack(front: usize, ready: Option<usize>) -> usize {
+    front | (ready.unwrap_or(NONE) << 2)
+}
+
+/// A set of two or three framebuffers that can be swapped at the start of each
+/// frame.
+///
+/// # Parameters
+///
+/// `S` is the storage type, which must implement `AsMut<[B]>` and contain
+/// either two or three buffers. In contexts where storage is allocated
+/// statically, this is probably `[&'static mut [u32]; 2]`.
+///
+/// `B` is the type of each buffer.
+///
+/// # Use of priority tokens
+///
+/// Like `RaceBuffer`, users of a `FlipBuffer` must provide *priority tokens*:
+///
+/// - Back buffers can only be *obtained and presented* from thread mode.
+/// - The front buffer can only be *read* from interrupt handlers.
+///
+/// This ensures that the swap performed by the reader cannot be preempted by
+/// the writer.
+pub struct FlipBuffer<S, B> {
+    buffers: S,
+    state: AtomicUsize,
+    _marker: PhantomData<B>,
+}
+
+impl<S, B> FlipBuffer<S, B>
+where
+    S: AsMut<[B]>,
+{
+    /// Creates a `FlipBuffer` from a set of buffers. Initially, the first
+    /// buffer is the front buffer.
+    ///
+    /// # Panics
+    ///
+    /// If `buffers` does not contain exactly two or three buffers.
+    pub fn new(mut buffers: S) -> Self {
+        let count = buffers.as_mut().len();
+        assert!(
+            count == 2 || count == 3,
+            "FlipBuffer needs 2 or 3 buffers, not {}",
+            count
+        );
+        FlipBuffer {
+            buffers,
+            state: AtomicUsize::new(pack(0, None)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Generates a `FlipReader` and `FlipWriter` for this buffer, which can
+    /// then be distributed to the rasterizer and renderer.
+    pub fn split(&mut self) -> (FlipReader<'_, B>, FlipWriter<'_, B>) {
+        let buffers = self.buffers.as_mut();
+        let count = buffers.len();
+        // Safety: this comes from a slice, it cannot be null.
+        let base = unsafe { NonNull::new_unchecked(buffers.as_mut_ptr()) };
+        (
+            FlipReader {
+                base,
+                state: &self.state,
+                last_line: usize::MAX,
+            },
+            FlipWriter {
+                base,
+                count,
+                state: &self.state,
+                _not_sync: PhantomData,
+            },
+        )
+    }
+}
+
+/// Reads the front buffer of a `FlipBuffer`, swapping in newly presented
+/// buffers between frames.
+pub struct FlipReader<'a, B> {
+    base: NonNull<B>,
+    state: &'a AtomicUsize,
+    /// Line number passed to the most recent call to `front`, used to detect
+    /// the start of a new frame.
+    last_line: usize,
+}
+
+unsafe impl<'a, B: Sync> Send for FlipReader<'a, B> {}
+
+impl<'a, B> FlipReader<'a, B> {
+    /// Gets a reference to the front buffer, for use in rasterizing
+    /// `line_number`.
+    ///
+    /// The first call in each frame -- detected by `line_number` failing to
+    /// increase -- swaps in the most recently presented buffer, if any and if
+    /// a `Flipper` hasn't already done so. All later calls in the same frame
+    /// return the same buffer. This means the rasterizer need not call this on
+    /// every line, e.g. when it fills the top of the display with solid color.
+    ///
+    /// The caller is required to provide an interrupt priority token `P`,
+    /// proving that they are calling from interrupt context. This ensures that
+    /// the swap is atomic with respect to the `FlipWriter`, which must be used
+    /// *outside* an interrupt.
+    pub fn front<'r, P>(&'r mut self, line_number: usize, _: &'r P) -> &'r B
+    where
+        P: priority::InterruptPriority,
+    {
+        // `last_line` starts out as `usize::MAX`, so this also flips before
+        // the very first line.
+        if line_number <= self.last_line {
+            flip(self.state);
+        }
+        self.last_line = line_number;
+
+        let i = front(self.state.load(Ordering::Acquire));
+        // Safety: the writer will not hand out the front buffer for mutation,
+        // and the front buffer cannot change while the result borrows us:
+        // here we hold `&mut self`, and `Flipper::flip` can't run while the
+        // rasterizer does.
+        unsafe { &*self.base.as_ptr().add(i) }
+    }
+
+    /// Gets a `Flipper` that can swap this reader's front buffer outside the
+    /// rasterizer, e.g. from the driver at the end of active video.
+    pub fn flipper(&self) -> Flipper<'a> {
+        Flipper(self.state)
+    }
+}
+
+/// Makes the most recently presented buffer, if any, the front buffer.
+fn flip(state: &AtomicUsize) {
+    let mut current = state.load(Ordering::Acquire);
+    while let Some(r) = ready(current) {
+        match state.compare_exchange_weak(
+            current,
+            pack(r, None),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => break,
+            Err(s) => current = s,
+        }
+    }
+}
+
+/// Swaps in the most recently presented buffer on behalf of a `FlipReader`,
+/// without waiting for the rasterizer to start a frame.
+#[derive(Copy, Clone)]
+pub struct Flipper<'a>(&'a AtomicUsize);
+
+impl<'a> Flipper<'a> {
+    /// Makes the most recently presented buffer, if any, the front buffer.
+    ///
+    /// # Safety
+    ///
+    /// This must not run while a reference returned by the corresponding
+    /// `FlipReader::front` is in use, e.g. from anywhere that can preempt the
+    /// rasterizer. The driver only calls it from its raster interrupt during
+    /// vertical blank, when the rasterizer isn't running.
+    pub unsafe fn flip(self) {
+        flip(self.0)
+    }
+}
+
+/// Vends back buffers for rendering and presents them when they're complete.
+pub struct FlipWriter<'a, B> {
+    base: NonNull<B>,
+    count: usize,
+    state: &'a AtomicUsize,
+    /// Conservatively prevent this from being moved into an ISR, since it
+    /// relies on not preempting the reader.
+    _not_sync: PhantomData<*mut B>,
+}
+
+impl<'a, B> FlipWriter<'a, B> {
+    /// Attempts to get a back buffer for rendering.
+    ///
+    /// The buffer is returned as a `BackGuard` smart pointer. This works like a
+    /// `&mut`, and presents the buffer for display when it's dropped.
+    ///
+    /// If no back buffer is free, returns `None` without waiting. This happens
+    /// only with two buffers, when a presented frame has not yet reached the
+    /// display; the caller can use this to skip rendering.
+    ///
+    /// The caller is required to provide a `Thread` priority token,
+    /// demonstrating that they are *not* attempting to use this API from an
+    /// ISR.
+    pub fn try_back(
+        &mut self,
+        _: &priority::Thread,
+    ) -> Option<BackGuard<'_, B>> {
+        let (back, prev) = self.find_back()?;
+        Some(self.guard(back, prev))
+    }
+
+    /// Gets a back buffer for rendering, spinning until one is free.
+    ///
+    /// With two buffers, this can wait until the start of the next frame. See
+    /// `try_back` for details.
+    pub fn back(&mut self, _: &priority::Thread) -> BackGuard<'_, B> {
+        loop {
+            if let Some((back, prev)) = self.find_back() {
+                return self.guard(back, prev);
+            }
+        }
+    }
+
+    /// Chooses a back buffer, returning its index along with the index of the
+    /// most recently presented buffer.
+    fn find_back(&self) -> Option<(usize, usize)> {
+        let state = self.state.load(Ordering::Acquire);
+        let (f, r) = (front(state), ready(state));
+        // Any buffer that is neither on display nor waiting to be displayed is
+        // fair game. The reader only ever moves `ready` to `front`, so the
+        // buffer we choose can't be taken out from under us.
+        let back = (0..self.count).find(|&i| i != f && Some(i) != r)?;
+        Some((back, r.unwrap_or(f)))
+    }
+
+    fn guard(&mut self, back: usize, prev: usize) -> BackGuard<'_, B> {
+        // Safety: `back` is not visible to the reader until we present it, and
+        // `prev` is only ever accessed by shared reference until we hand it
+        // out as a back buffer -- which can't happen while the guard borrows
+        // us.
+        unsafe {
+            BackGuard {
+                state: self.state,
+                index: back,
+                data: &mut *self.base.as_ptr().add(back),
+                prev: &*self.base.as_ptr().add(prev),
+                _not_sync_send: PhantomData,
+            }
+        }
+    }
+}
+
+/// A back buffer borrowed from a `FlipWriter`. When dropped, the buffer is
+/// presented, and will become the front buffer at the next swap.
+pub struct BackGuard<'a, B> {
+    state: &'a AtomicUsize,
+    index: usize,
+    data: &'a mut B,
+    prev: &'a B,
+    /// Conservatively prevent this smart pointer from being moved into an ISR,
+    /// because I haven't thought through the implications of doing so.
+    _not_sync_send: PhantomData<*mut B>,
+}
+
+impl<'a, B> BackGuard<'a, B> {
+    /// Gets a reference to the most recently presented buffer. Renderers that
+    /// compute each frame from the last one (like Conway's Game of Life) can
+    /// read this while writing the back buffer.
+    ///
+    /// The result keeps the `FlipWriter` borrowed, so the buffer can't be
+    /// handed out for writing while the reference exists.
+    pub fn prev(&self) -> &'a B {
+        self.prev
+    }
+}
+
+impl<'a, B> Drop for BackGuard<'a, B> {
+    fn drop(&mut self) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match self.state.compare_exchange_weak(
+                state,
+                pack(front(state), Some(self.index)),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(s) => state = s,
+            }
+        }
+    }
+}
+
+impl<'a, B> core::ops::Deref for BackGuard<'a, B> {
+    type Target = B;
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+impl<'a, B> core::ops::DerefMut for BackGuard<'a, B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.data
+    }
+}
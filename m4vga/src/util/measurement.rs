@@ -14,8 +14,11 @@
 //!
 //! # Simulation
 //!
-//! Measurement signals are currently disabled in simulation, i.e. the
-//! `measurement` feature does nothing. This might change later.
+//! In simulation, the `measurement` feature records signal changes in memory
+//! instead of toggling pins. See the `trace` module for details.
+
+#[cfg(not(target_os = "none"))]
+pub mod trace;
 
 /// Sets up the measurement subsystem.
 ///
@@ -89,6 +92,8 @@ cfg_if::cfg_if! {
 pub fn sig_a_set() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.bs8().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::A, true);
 }
 
 /// Clear measurement signal A.
@@ -97,6 +102,8 @@ pub fn sig_a_set() {
 pub fn sig_a_clear() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.br8().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::A, false);
 }
 
 /// Set measurement signal B.
@@ -105,6 +112,8 @@ pub fn sig_a_clear() {
 pub fn sig_b_set() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.bs9().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::B, true);
 }
 
 /// Clear measurement signal B.
@@ -113,6 +122,8 @@ pub fn sig_b_set() {
 pub fn sig_b_clear() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.br9().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::B, false);
 }
 
 /// Set measurement signal C.
@@ -121,6 +132,8 @@ pub fn sig_b_clear() {
 pub fn sig_c_set() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.bs10().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::C, true);
 }
 
 /// Clear measurement signal C.
@@ -129,6 +142,8 @@ pub fn sig_c_set() {
 pub fn sig_c_clear() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.br10().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::C, false);
 }
 
 /// Set measurement signal D.
@@ -137,6 +152,8 @@ pub fn sig_c_clear() {
 pub fn sig_d_set() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.bs11().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::D, true);
 }
 
 /// Clear measurement signal D.
@@ -145,4 +162,6 @@ pub fn sig_d_set() {
 pub fn sig_d_clear() {
     #[cfg(all(target_os = "none", feature = "measurement"))]
     write_gpioc_bsrr(|w| w.br11().set_bit());
+    #[cfg(all(not(target_os = "none"), feature = "measurement"))]
+    trace::record(trace::Signal::D, false);
 }
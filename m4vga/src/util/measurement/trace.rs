@@ -0,0 +1,234 @@
+//! Recording of measurement signals in simulation.
+//!
+//! When the `measurement` feature is enabled on a hosted target, each change to
+//! a measurement signal is recorded in a ring buffer, along with a logical
+//! timestamp. There's no clock to read in simulation, so the timestamp is
+//! whatever the simulator last passed to `set_time` -- emulated cycles, pixels,
+//! scanlines, etc. Timestamps should not decrease, except across `reset`.
+//!
+//! The ring buffer holds the most recent `CAPACITY` events; older events are
+//! discarded. It can be exported as a Value Change Dump using `write_vcd`, for
+//! viewing in GTKWave or any other waveform viewer, just like a capture from a
+//! logic analyzer attached to the real pins.
+//!
+//! Without the `measurement` feature, the API is still available, but the ring
+//! buffer has no room, so no events are recorded and it costs no memory.
+
+use core::fmt;
+
+use crate::util::spin_lock::SpinLock;
+
+/// Number of events retained in the ring buffer.
+#[cfg(feature = "measurement")]
+pub const CAPACITY: usize = 4096;
+/// Number of events retained in the ring buffer: none, since nothing records
+/// them without the `measurement` feature.
+#[cfg(not(feature = "measurement"))]
+pub const CAPACITY: usize = 0;
+
+/// One of the four measurement signals.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Signal {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Signal {
+    const ALL: [Signal; 4] = [Signal::A, Signal::B, Signal::C, Signal::D];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Name used for the signal in VCD output.
+    fn name(self) -> &'static str {
+        match self {
+            Signal::A => "sig_a",
+            Signal::B => "sig_b",
+            Signal::C => "sig_c",
+            Signal::D => "sig_d",
+        }
+    }
+
+    /// Identifier code used for the signal in VCD output.
+    fn vcd_id(self) -> char {
+        (b'!' + self as u8) as char
+    }
+}
+
+/// A recorded change to a measurement signal.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// Logical time of the change, as set by `set_time`.
+    pub time: u64,
+    /// Signal affected.
+    pub signal: Signal,
+    /// New level: `true` for set, `false` for clear.
+    pub level: bool,
+}
+
+impl Event {
+    const EMPTY: Event = Event {
+        time: 0,
+        signal: Signal::A,
+        level: false,
+    };
+}
+
+struct Trace {
+    /// Current logical time.
+    now: u64,
+    events: [Event; CAPACITY],
+    /// Index where the next event will be written. When the buffer is full,
+    /// this is also the index of the oldest event.
+    next: usize,
+    /// Number of valid events in `events`.
+    len: usize,
+    /// Bitmask of signal levels before the oldest retained event. We need this
+    /// to produce correct initial values in VCD output once events have been
+    /// discarded.
+    initial: u8,
+    /// Number of events discarded since the last `reset`.
+    dropped: usize,
+}
+
+impl Trace {
+    const fn new() -> Self {
+        Trace {
+            now: 0,
+            events: [Event::EMPTY; CAPACITY],
+            next: 0,
+            len: 0,
+            initial: 0,
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, signal: Signal, level: bool) {
+        if self.len == CAPACITY {
+            // Fold the oldest event into the initial state before
+            // overwriting it.
+            let oldest = self.events[self.next];
+            self.initial = apply(self.initial, &oldest);
+            self.dropped += 1;
+        } else {
+            self.len += 1;
+        }
+        self.events[self.next] = Event {
+            time: self.now,
+            signal,
+            level,
+        };
+        self.next += 1;
+        if self.next == CAPACITY {
+            self.next = 0;
+        }
+    }
+
+    /// Iterates over retained events, oldest first.
+    fn iter(&self) -> impl Iterator<Item = &Event> {
+        // Until the buffer fills, events occupy `..next`, and `len == next`.
+        // After that, the oldest are at `next..` and the newest at `..next`.
+        let (newer, older) = self.events.split_at(self.next);
+        older[..self.len - self.next].iter().chain(newer)
+    }
+}
+
+/// Updates a bitmask of signal levels to reflect `event`.
+fn apply(levels: u8, event: &Event) -> u8 {
+    if event.level {
+        levels | event.signal.bit()
+    } else {
+        levels & !event.signal.bit()
+    }
+}
+
+static TRACE: SpinLock<Trace> = SpinLock::new(Trace::new());
+
+/// Records a change to `signal`. Called by the `sig_*` functions, but only if
+/// the `measurement` feature is enabled.
+#[cfg_attr(not(feature = "measurement"), allow(dead_code))]
+pub(super) fn record(signal: Signal, level: bool) {
+    TRACE.lock().push(signal, level)
+}
+
+/// Sets the logical time that will be attached to subsequent events.
+pub fn set_time(time: u64) {
+    TRACE.lock().now = time
+}
+
+/// Advances the logical time by `delta`.
+pub fn advance(delta: u64) {
+    TRACE.lock().now += delta
+}
+
+/// Gets the current logical time.
+pub fn time() -> u64 {
+    TRACE.lock().now
+}
+
+/// Discards all recorded events and resets the logical time to zero. All
+/// signals are considered clear afterwards.
+pub fn reset() {
+    *TRACE.lock() = Trace::new()
+}
+
+/// Number of events discarded, because the ring buffer was full, since the last
+/// `reset`.
+pub fn dropped() -> usize {
+    TRACE.lock().dropped
+}
+
+/// Calls `f` with each retained event, oldest first.
+///
+/// The trace is locked during this operation, so `f` must not change any
+/// measurement signals, or it will deadlock.
+pub fn for_each_event(mut f: impl FnMut(&Event)) {
+    for event in TRACE.lock().iter() {
+        f(event)
+    }
+}
+
+/// Writes the retained events to `out` as a Value Change Dump.
+///
+/// `timescale` gives the real-world meaning of one unit of logical time, in
+/// VCD syntax: a magnitude of 1, 10, or 100 followed by a unit from `s` down
+/// to `fs`, e.g. `"1 ns"`. If your logical time doesn't correspond to real
+/// time, any value will do; waveform viewers will just show misleading units.
+///
+/// As with `for_each_event`, `out` must not change any measurement signals.
+pub fn write_vcd<W: fmt::Write>(out: &mut W, timescale: &str) -> fmt::Result {
+    let trace = TRACE.lock();
+
+    writeln!(out, "$version m4vga measurement trace $end")?;
+    writeln!(out, "$timescale {} $end", timescale)?;
+    writeln!(out, "$scope module m4vga $end")?;
+    for &s in &Signal::ALL {
+        writeln!(out, "$var wire 1 {} {} $end", s.vcd_id(), s.name())?;
+    }
+    writeln!(out, "$upscope $end")?;
+    writeln!(out, "$enddefinitions $end")?;
+
+    // Initial values are given at the time of the oldest event, since we don't
+    // know anything about the time before it.
+    let start = trace.iter().next().map(|e| e.time).unwrap_or(0);
+    writeln!(out, "#{}", start)?;
+    writeln!(out, "$dumpvars")?;
+    for &s in &Signal::ALL {
+        let level = trace.initial & s.bit() != 0;
+        writeln!(out, "{}{}", level as u8, s.vcd_id())?;
+    }
+    writeln!(out, "$end")?;
+
+    let mut last_time = start;
+    for event in trace.iter() {
+        if event.time != last_time {
+            writeln!(out, "#{}", event.time)?;
+            last_time = event.time;
+        }
+        writeln!(out, "{}{}", event.level as u8, event.signal.vcd_id())?;
+    }
+    Ok(())
+}
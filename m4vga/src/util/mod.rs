@@ -8,8 +8,12 @@ cfg_if::cfg_if! {
     }
 }
 
+pub mod arena;
 pub mod copy_words;
+pub mod flip_buf;
 pub mod measurement;
+pub mod priority_cell;
+pub mod profile;
 pub mod race_buf;
 pub mod rw_lock;
 pub mod spin_lock;
@@ -0,0 +1,98 @@
+//! Shared state protected by priority ceilings.
+//!
+//! Locks like `SpinLock` and `ReadWriteLock` detect conflicting access at
+//! runtime, and the usual response in an interrupt handler -- which can't wait
+//! for the lock -- is to panic. Avoiding that relies on the application timing
+//! its accesses just right, e.g. releasing a lock before the next scanline
+//! begins.
+//!
+//! A `PriorityCell<T, P>` instead prevents conflicting access by construction,
+//! using the *priority ceiling* protocol. The cell is shared between thread
+//! mode and interrupts at priority `P`:
+//!
+//! - Code running at priority `P` accesses the contents using `with`, which
+//!   requires a `P` token. Thread mode code can't be running at the same time.
+//! - Thread mode code accesses the contents using `lock`, which requires a
+//!   `Thread` token, and masks interrupts at priority `P` for the duration.
+//!   The interrupt is simply deferred until the thread is done.
+//!
+//! Neither accessor can fail due to contention. The cost is that thread mode
+//! delays the interrupt while it holds the lock. For `PriorityCell<T, I0>`, a
+//! raster callback that is delayed too long will produce a display glitch --
+//! so, as with the locks it replaces, thread-mode access should happen during
+//! vblank, or be very brief.
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::priority::{InterruptPriority, Thread};
+
+/// A cell shared between thread mode and interrupts at priority `P`.
+pub struct PriorityCell<T, P> {
+    /// Set while someone has a reference to `value`. Since the priority ceiling
+    /// prevents concurrent access, this can only be set on entry if the same
+    /// context attempts to reenter the cell.
+    entered: AtomicBool,
+    value: UnsafeCell<T>,
+    _priority: PhantomData<fn() -> P>,
+}
+
+unsafe impl<T: Send, P> Sync for PriorityCell<T, P> {}
+
+impl<T, P> PriorityCell<T, P> {
+    pub const fn new(value: T) -> Self {
+        PriorityCell {
+            entered: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+            _priority: PhantomData,
+        }
+    }
+
+    /// Gets a reference to the contents. Since this requires `&mut self`, no
+    /// synchronization is needed.
+    pub fn get_mut(&mut self) -> &mut T {
+        // Safety: we have exclusive access to the cell.
+        unsafe { &mut *self.value.get() }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T, P> PriorityCell<T, P>
+where
+    P: InterruptPriority,
+{
+    /// Accesses the contents from an interrupt at priority `P`.
+    ///
+    /// # Panics
+    ///
+    /// If called from within `body`. The priority token makes all other
+    /// conflicting accesses impossible.
+    pub fn with<R>(&self, _: &P, body: impl FnOnce(&mut T) -> R) -> R {
+        self.enter(body)
+    }
+
+    /// Accesses the contents from thread mode, masking interrupts at priority
+    /// `P` until `body` returns.
+    ///
+    /// # Panics
+    ///
+    /// If called from within `body`.
+    pub fn lock<R>(&self, _: &Thread, body: impl FnOnce(&mut T) -> R) -> R {
+        P::mask(|| self.enter(body))
+    }
+
+    fn enter<R>(&self, body: impl FnOnce(&mut T) -> R) -> R {
+        let reentered = self.entered.swap(true, Ordering::Acquire);
+        assert!(!reentered, "PriorityCell reentered");
+        // Safety: the priority ceiling ensures that nobody else can be
+        // executing this function concurrently, and the `entered` flag ensures
+        // that this isn't a reentrant call.
+        let result = body(unsafe { &mut *self.value.get() });
+        self.entered.store(false, Ordering::Release);
+        result
+    }
+}
@@ -0,0 +1,332 @@
+//! Cycle-accurate profiling using the DWT cycle counter.
+//!
+//! Measurement signals (see `measurement`) are great for seeing *when* things
+//! happen with a logic analyzer, but they don't tell the program itself how
+//! long things took. This module reads the Cortex-M DWT cycle counter, which
+//! counts CPU cycles, and accumulates the results:
+//!
+//! - A `Scope` collects timings of some named operation, like `render_frame`,
+//!   and summarizes them per frame.
+//! - A `LineHistogram` collects the cost of rasterizing each scanline, compared
+//!   to the budget of one line's worth of cycles. If a rasterizer goes over
+//!   budget, the display tears.
+//!
+//! With the `profile` feature, the driver keeps a `LineHistogram` of all raster
+//! callbacks, which you can retrieve with `take_raster_profile`.
+//!
+//! # Simulation
+//!
+//! There's no cycle counter in simulation. Instead, `cycles` reads a mock
+//! clock, which the simulator can set using `set_mock_cycles` and
+//! `advance_mock_cycles`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Enables the cycle counter. Until this is called, `cycles` may always return
+/// the same value.
+///
+/// # Safety
+///
+/// Like `measurement::init`, this circumvents ownership of the `DCB` and `DWT`
+/// peripherals. It's safe as long as it's not preempted by code that modifies
+/// the same registers, e.g. a debugger configuring tracing. Call this from
+/// early in `main` and you're good.
+pub unsafe fn init() {
+    #[cfg(target_os = "none")]
+    {
+        use cortex_m::peripheral::{DCB, DWT};
+
+        const DEMCR_TRCENA: u32 = 1 << 24;
+        const DWT_CTRL_CYCCNTENA: u32 = 1 << 0;
+
+        (*DCB::ptr()).demcr.modify(|r| r | DEMCR_TRCENA);
+        (*DWT::ptr()).ctrl.modify(|r| r | DWT_CTRL_CYCCNTENA);
+    }
+    #[cfg(not(target_os = "none"))]
+    set_mock_cycles(0);
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "none")] {
+        /// Reads the current cycle count. This wraps around every 2^32 cycles
+        /// (about 25 seconds at 160MHz), so only differences are meaningful.
+        #[inline(always)]
+        pub fn cycles() -> u32 {
+            cortex_m::peripheral::DWT::get_cycle_count()
+        }
+    } else {
+        /// Value of the mock cycle counter used in simulation.
+        static MOCK_CYCLES: AtomicUsize = AtomicUsize::new(0);
+
+        /// Reads the current cycle count. In simulation, this is the mock
+        /// clock.
+        pub fn cycles() -> u32 {
+            MOCK_CYCLES.load(Ordering::Relaxed) as u32
+        }
+
+        /// Sets the mock clock read by `cycles` in simulation.
+        pub fn set_mock_cycles(value: u32) {
+            MOCK_CYCLES.store(value as usize, Ordering::Relaxed)
+        }
+
+        /// Advances the mock clock read by `cycles` in simulation.
+        pub fn advance_mock_cycles(delta: u32) {
+            let now = cycles().wrapping_add(delta);
+            set_mock_cycles(now)
+        }
+    }
+}
+
+/// Computes the number of CPU cycles available to rasterize each line, given
+/// the total pixels per line (including blanking) and CPU cycles per pixel.
+/// This corresponds to `Timing::line_pixels` and `Timing::cycles_per_pixel()`.
+pub fn line_budget(line_pixels: usize, cycles_per_pixel: usize) -> u32 {
+    (line_pixels * cycles_per_pixel) as u32
+}
+
+/// Summary statistics for a set of samples.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// Number of samples.
+    pub count: usize,
+    /// Sum of all samples, in cycles.
+    pub total: usize,
+    /// Smallest sample. Meaningless if `count` is zero.
+    pub min: usize,
+    /// Largest sample.
+    pub max: usize,
+}
+
+impl Stats {
+    /// Average sample, rounded down, or zero if there were no samples.
+    pub fn avg(&self) -> usize {
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// A named operation whose execution time is being profiled.
+///
+/// Scopes are designed to live in `static`s. Samples are accumulated until the
+/// next call to `end_frame`, which summarizes them. The summary of the most
+/// recent frame can then be read using `last_frame`.
+///
+/// Each `Scope` should receive samples at only one interrupt priority, but can
+/// be read and ended from a lower priority, such as thread mode.
+pub struct Scope {
+    name: &'static str,
+    current: AtomicStats,
+    last: AtomicStats,
+}
+
+impl Scope {
+    pub const fn new(name: &'static str) -> Self {
+        Scope {
+            name,
+            current: AtomicStats::new(),
+            last: AtomicStats::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Starts timing an execution of this scope. The time is recorded when the
+    /// returned guard is dropped.
+    pub fn enter(&self) -> ScopeGuard<'_> {
+        ScopeGuard {
+            scope: self,
+            start: cycles(),
+        }
+    }
+
+    /// Times an execution of `f`.
+    pub fn measure<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = self.enter();
+        f()
+    }
+
+    /// Records a sample of `cycles` measured by some other means.
+    pub fn record(&self, cycles: u32) {
+        self.current.record(cycles as usize)
+    }
+
+    /// Ends the current frame, making its samples available through
+    /// `last_frame`, and starts accumulating a new one.
+    pub fn end_frame(&self) {
+        let stats = self.current.take();
+        self.last.store(&stats)
+    }
+
+    /// Returns statistics for the most recently ended frame.
+    pub fn last_frame(&self) -> Stats {
+        self.last.load()
+    }
+}
+
+/// Times a single execution of a `Scope`; see `Scope::enter`.
+pub struct ScopeGuard<'a> {
+    scope: &'a Scope,
+    start: u32,
+}
+
+impl<'a> Drop for ScopeGuard<'a> {
+    fn drop(&mut self) {
+        self.scope.record(cycles().wrapping_sub(self.start))
+    }
+}
+
+/// `Stats` in a form that can be shared with interrupt handlers.
+///
+/// Fields are updated individually, so a reader can see a sample partially
+/// applied. This is fine for profiling purposes.
+struct AtomicStats {
+    count: AtomicUsize,
+    total: AtomicUsize,
+    min: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl AtomicStats {
+    const fn new() -> Self {
+        AtomicStats {
+            count: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            min: AtomicUsize::new(usize::MAX),
+            max: AtomicUsize::new(0),
+        }
+    }
+
+    fn record(&self, sample: usize) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(sample, Ordering::Relaxed);
+        // Samples come from a single priority level, so we don't need to worry
+        // about these being interleaved with another `record`.
+        if sample < self.min.load(Ordering::Relaxed) {
+            self.min.store(sample, Ordering::Relaxed)
+        }
+        if sample > self.max.load(Ordering::Relaxed) {
+            self.max.store(sample, Ordering::Relaxed)
+        }
+    }
+
+    fn take(&self) -> Stats {
+        Stats {
+            count: self.count.swap(0, Ordering::Relaxed),
+            total: self.total.swap(0, Ordering::Relaxed),
+            min: self.min.swap(usize::MAX, Ordering::Relaxed),
+            max: self.max.swap(0, Ordering::Relaxed),
+        }
+    }
+
+    fn load(&self) -> Stats {
+        Stats {
+            count: self.count.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            min: self.min.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+
+    fn store(&self, stats: &Stats) {
+        self.count.store(stats.count, Ordering::Relaxed);
+        self.total.store(stats.total, Ordering::Relaxed);
+        self.min.store(stats.min, Ordering::Relaxed);
+        self.max.store(stats.max, Ordering::Relaxed);
+    }
+}
+
+/// Number of buckets in a `LineHistogram`, each covering an equal fraction of
+/// the line budget.
+pub const BUCKETS: usize = 10;
+
+/// Histogram of per-scanline raster costs, relative to the cycle budget for
+/// one line.
+#[derive(Clone, Debug)]
+pub struct LineHistogram {
+    budget: u32,
+    /// Bucket `i` counts lines that took at least `i/BUCKETS` but less than
+    /// `(i+1)/BUCKETS` of the budget.
+    buckets: [usize; BUCKETS],
+    /// Count of lines that took the entire budget or more.
+    over_budget: usize,
+    /// Total number of lines recorded.
+    lines: usize,
+    /// Cost of the most expensive line, and its line number.
+    worst: Option<(u32, usize)>,
+}
+
+impl LineHistogram {
+    /// Creates an empty histogram for a line budget of `budget` cycles. See
+    /// `line_budget`.
+    pub const fn new(budget: u32) -> Self {
+        LineHistogram {
+            budget,
+            buckets: [0; BUCKETS],
+            over_budget: 0,
+            lines: 0,
+            worst: None,
+        }
+    }
+
+    /// Creates an empty histogram using the line budget for `timing`.
+    #[cfg(target_os = "none")]
+    pub fn for_timing(timing: &crate::timing::Timing) -> Self {
+        Self::new(line_budget(timing.line_pixels, timing.cycles_per_pixel()))
+    }
+
+    pub fn budget(&self) -> u32 {
+        self.budget
+    }
+
+    /// Discards all samples and sets a new budget.
+    pub fn reset(&mut self, budget: u32) {
+        *self = Self::new(budget)
+    }
+
+    /// Records that rasterizing `line` took `cycles`.
+    pub fn record(&mut self, line: usize, cycles: u32) {
+        self.lines += 1;
+        if cycles >= self.budget {
+            self.over_budget += 1;
+        } else {
+            let i = (u64::from(cycles) * BUCKETS as u64
+                / u64::from(self.budget)) as usize;
+            self.buckets[i] += 1;
+        }
+        match self.worst {
+            Some((worst, _)) if worst >= cycles => (),
+            _ => self.worst = Some((cycles, line)),
+        }
+    }
+
+    /// Line counts per bucket. Bucket `i` counts lines that used between
+    /// `i * 100 / BUCKETS` (inclusive) and `(i + 1) * 100 / BUCKETS`
+    /// (exclusive) percent of the budget.
+    pub fn buckets(&self) -> &[usize; BUCKETS] {
+        &self.buckets
+    }
+
+    /// Number of lines that used their entire budget or more. If this is
+    /// nonzero, the display has probably glitched.
+    pub fn over_budget(&self) -> usize {
+        self.over_budget
+    }
+
+    /// Total number of lines recorded.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Cost of the most expensive line recorded, and its line number.
+    pub fn worst(&self) -> Option<(u32, usize)> {
+        self.worst
+    }
+
+    /// Cycles left over on the most expensive line, or a negative number if it
+    /// went over budget.
+    pub fn headroom(&self) -> Option<i64> {
+        self.worst
+            .map(|(cycles, _)| i64::from(self.budget) - i64::from(cycles))
+    }
+}
@@ -65,6 +65,20 @@ impl<T: ?Sized + Send> SpinLock<T> {
             }
         }
     }
+
+    /// Releases the lock, even if a guard for it still exists.
+    ///
+    /// This is for recovering resources from code that has been abandoned
+    /// while holding a guard, e.g. by a panic handler that will never return
+    /// to the panicking code.
+    ///
+    /// # Safety
+    ///
+    /// Any outstanding guard must never be used again, including by dropping
+    /// it.
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release)
+    }
 }
 
 #[must_use = "if dropped, the spinlock will immediately unlock"]
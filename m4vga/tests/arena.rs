@@ -0,0 +1,114 @@
+//! Checks arena allocation over ordinary host memory.
+
+use m4vga::util::arena::{Arena, Sram2};
+
+/// Stand-in for a RAM, aligned so that offsets from its start give known
+/// alignments.
+#[repr(align(16))]
+struct Ram([u8; 64]);
+
+/// Makes an arena over bytes `start..end` of a fresh `Ram`, returning it along
+/// with the address of the `Ram`.
+///
+/// This uses `Sram2` because its address translations are the identity, so the
+/// results can be used on the host.
+fn arena(start: usize, end: usize) -> (Arena<Sram2>, usize) {
+    let ram = Box::leak(Box::new(Ram([0xAA; 64])));
+    let base = ram.0.as_mut_ptr() as usize;
+    // Safety: the leaked memory lives forever, and only the arena uses it.
+    (
+        unsafe { Arena::from_bounds(base + start, base + end) },
+        base,
+    )
+}
+
+#[test]
+fn misaligned_start_is_rounded_up() {
+    let (mut arena, base) = arena(1, 64);
+    let words = arena.alloc_slice_aligned(2, 8, 0x1234_5678u32).unwrap();
+    assert_eq!(words.as_ptr() as usize, base + 8);
+    assert_eq!(words, [0x1234_5678; 2]);
+    assert_eq!(arena.remaining(), 64 - 16);
+
+    let byte = arena.alloc_slice(1, 7u8).unwrap();
+    assert_eq!(byte.as_ptr() as usize, base + 16);
+}
+
+#[test]
+fn misaligned_start_counts_against_room() {
+    // Four words would fit in 16 bytes if it weren't for the padding.
+    let (mut arena, _) = arena(1, 17);
+    assert!(arena.alloc_slice_aligned(4, 4, 0u32).is_none());
+    assert_eq!(arena.remaining(), 16);
+}
+
+#[test]
+fn exact_fit() {
+    let (mut arena, base) = arena(0, 16);
+    let words = arena.alloc_slice_aligned(4, 16, 5u32).unwrap();
+    assert_eq!(words.as_ptr() as usize, base);
+    assert_eq!(words, [5; 4]);
+    assert_eq!(arena.remaining(), 0);
+
+    assert!(arena.alloc_slice(1, 0u8).is_none());
+    assert_eq!(arena.alloc_slice(0, 0u8).unwrap().len(), 0);
+}
+
+#[test]
+fn one_byte_too_many() {
+    let (mut arena, base) = arena(0, 15);
+    assert!(arena.alloc_slice_aligned(4, 4, 0u32).is_none());
+    // The failure doesn't use up any of the arena.
+    assert_eq!(arena.remaining(), 15);
+
+    let words = arena.alloc_slice_aligned(3, 4, 0u32).unwrap();
+    assert_eq!(words.as_ptr() as usize, base);
+    assert_eq!(arena.remaining(), 3);
+}
+
+#[test]
+fn dma_bit_band_exact_fit() {
+    let (mut arena, base) = arena(4, 20);
+    let buffer = arena.alloc_dma_bit_band(4, 0xFFFF_FFFF).unwrap();
+    assert_eq!(buffer.as_ptr() as usize, base + 4);
+    assert_eq!(buffer.dma_address(), base + 4);
+    assert_eq!(&*buffer, [0xFFFF_FFFF; 4]);
+    assert_eq!(arena.remaining(), 0);
+}
+
+#[test]
+fn dma_bit_band_misaligned_start() {
+    let (mut arena, base) = arena(2, 20);
+    let buffer = arena.alloc_dma_bit_band(3, 0).unwrap();
+    assert_eq!(buffer.as_ptr() as usize, base + 4);
+    assert_eq!(arena.remaining(), 4);
+}
+
+#[test]
+fn dma_bit_band_one_byte_too_many() {
+    let (mut arena, _) = arena(4, 19);
+    assert!(arena.alloc_dma_bit_band(4, 0).is_none());
+    assert_eq!(arena.remaining(), 15);
+    assert!(arena.alloc_dma_bit_band(3, 0).is_some());
+}
+
+#[test]
+fn huge_count_does_not_overflow() {
+    let (mut arena, _) = arena(0, 64);
+    assert!(arena.alloc_slice(usize::MAX / 2, 0u32).is_none());
+    assert_eq!(arena.remaining(), 64);
+}
+
+#[test]
+#[should_panic(expected = "bad alignment")]
+fn alignment_must_be_power_of_two() {
+    let (mut arena, _) = arena(0, 64);
+    arena.alloc_slice_aligned(1, 3, 0u8);
+}
+
+#[test]
+#[should_panic(expected = "alignment too small")]
+fn alignment_must_suit_type() {
+    let (mut arena, _) = arena(0, 64);
+    arena.alloc_slice_aligned(1, 2, 0u32);
+}
@@ -0,0 +1,120 @@
+//! Walks a `FlipBuffer` through presenting and swapping frames, using the
+//! simulated priority tokens in place of the driver.
+
+use m4vga::priority::{self, Thread};
+use m4vga::util::flip_buf::{FlipBuffer, FlipReader};
+
+fn thread() -> Thread {
+    Thread::new_checked().unwrap()
+}
+
+/// Reads the front buffer as the rasterizer would for line `ln`.
+fn front(reader: &mut FlipReader<'_, u32>, ln: usize) -> u32 {
+    priority::simulate_i0(|p| *reader.front(ln, &p))
+}
+
+#[test]
+fn first_buffer_starts_in_front() {
+    let mut buffers = FlipBuffer::new([10, 11]);
+    let (mut reader, mut writer) = buffers.split();
+    assert_eq!(front(&mut reader, 0), 10);
+
+    let back = writer.try_back(&thread()).unwrap();
+    assert_eq!(*back, 11);
+    assert_eq!(*back.prev(), 10);
+}
+
+#[test]
+fn presented_buffer_swaps_in_at_next_frame() {
+    let mut buffers = FlipBuffer::new([10, 11]);
+    let (mut reader, mut writer) = buffers.split();
+    assert_eq!(front(&mut reader, 0), 10);
+
+    *writer.try_back(&thread()).unwrap() = 21;
+    // Later lines of the same frame keep the old buffer.
+    assert_eq!(front(&mut reader, 1), 10);
+    assert_eq!(front(&mut reader, 599), 10);
+    // The line number wrapping starts a new frame.
+    assert_eq!(front(&mut reader, 0), 21);
+    assert_eq!(front(&mut reader, 1), 21);
+}
+
+#[test]
+fn two_buffers_wait_for_swap() {
+    let mut buffers = FlipBuffer::new([10, 11]);
+    let (mut reader, mut writer) = buffers.split();
+    front(&mut reader, 0);
+
+    drop(writer.try_back(&thread()).unwrap());
+    // One buffer is on display and the other is waiting for it.
+    assert!(writer.try_back(&thread()).is_none());
+
+    front(&mut reader, 0);
+    let back = writer.try_back(&thread()).unwrap();
+    assert_eq!(*back, 10);
+    assert_eq!(*back.prev(), 11);
+}
+
+#[test]
+fn flipper_swaps_without_rasterizer() {
+    let mut buffers = FlipBuffer::new([10, 11]);
+    let (mut reader, mut writer) = buffers.split();
+    let flipper = reader.flipper();
+    assert_eq!(front(&mut reader, 0), 10);
+
+    drop(writer.try_back(&thread()).unwrap());
+    // Safety: no reference from `front` is alive.
+    unsafe { flipper.flip() }
+
+    // The reader sees the flip mid-frame, and doesn't flip again at the next
+    // frame since nothing new was presented.
+    assert_eq!(front(&mut reader, 1), 11);
+    assert_eq!(front(&mut reader, 0), 11);
+
+    // The old front buffer is free for the next frame straight away.
+    assert_eq!(*writer.try_back(&thread()).unwrap(), 10);
+}
+
+#[test]
+fn flip_with_nothing_presented_keeps_front() {
+    let mut buffers = FlipBuffer::new([10, 11]);
+    let (mut reader, _writer) = buffers.split();
+    unsafe { reader.flipper().flip() }
+    assert_eq!(front(&mut reader, 0), 10);
+    assert_eq!(front(&mut reader, 0), 10);
+}
+
+#[test]
+fn three_buffers_never_wait() {
+    let mut buffers = FlipBuffer::new([10, 11, 12]);
+    let (mut reader, mut writer) = buffers.split();
+    front(&mut reader, 0);
+
+    let mut back = writer.try_back(&thread()).unwrap();
+    assert_eq!(*back, 11);
+    *back = 21;
+    drop(back);
+
+    // With 11 ready, the third buffer is free, and sees 11 as the previous
+    // frame.
+    let mut back = writer.try_back(&thread()).unwrap();
+    assert_eq!(*back, 12);
+    assert_eq!(*back.prev(), 21);
+    *back = 22;
+    drop(back);
+
+    // Presenting 12 replaced 11, which becomes free again.
+    let back = writer.try_back(&thread()).unwrap();
+    assert_eq!(*back, 21);
+    assert_eq!(*back.prev(), 22);
+    drop(back);
+
+    // Only the latest frame reaches the display.
+    assert_eq!(front(&mut reader, 0), 21);
+}
+
+#[test]
+#[should_panic(expected = "FlipBuffer needs 2 or 3 buffers")]
+fn rejects_single_buffer() {
+    FlipBuffer::new([10]);
+}
@@ -0,0 +1,58 @@
+//! Checks the simulated priority tracking used on hosted targets.
+
+use m4vga::priority::{self, InterruptPriority, Thread, I0, I1};
+
+#[test]
+fn thread_token_only_outside_interrupts() {
+    assert!(Thread::new_checked().is_some());
+    priority::simulate_i0(|_| assert!(Thread::new_checked().is_none()));
+    assert!(Thread::new_checked().is_some());
+}
+
+#[test]
+fn i0_token_only_inside_interrupts() {
+    assert!(I0::new_checked().is_none());
+    priority::simulate_i0(|_| assert!(I0::new_checked().is_some()));
+    assert!(I0::new_checked().is_none());
+}
+
+#[test]
+fn interrupt_state_is_per_thread() {
+    priority::simulate_i0(|_| {
+        std::thread::spawn(|| {
+            assert!(Thread::new_checked().is_some());
+            assert!(I0::new_checked().is_none());
+        })
+        .join()
+        .unwrap();
+    });
+}
+
+#[test]
+fn tracking_is_restored_after_panic() {
+    let result = std::panic::catch_unwind(|| {
+        priority::simulate_i0(|_| panic!("in handler"));
+    });
+    assert!(result.is_err());
+    assert!(Thread::new_checked().is_some());
+    I0::mask(|| ());
+    priority::simulate_i0(|_| ());
+}
+
+#[test]
+#[should_panic(expected = "simulated interrupt while masked")]
+fn interrupt_while_masked_panics() {
+    I0::mask(|| priority::simulate_i0(|_| ()));
+}
+
+#[test]
+#[should_panic(expected = "simulated interrupt while masked")]
+fn masking_higher_level_also_defers_i0() {
+    I1::mask(|| priority::simulate_i0(|_| ()));
+}
+
+#[test]
+#[should_panic(expected = "preempting equal or higher priority")]
+fn nested_interrupt_panics() {
+    priority::simulate_i0(|_| priority::simulate_i0(|_| ()));
+}
@@ -0,0 +1,46 @@
+//! Checks `PriorityCell` access from simulated thread mode and interrupts.
+
+use m4vga::priority::{self, Thread, I0};
+use m4vga::util::priority_cell::PriorityCell;
+
+fn thread() -> Thread {
+    Thread::new_checked().unwrap()
+}
+
+#[test]
+fn lock_and_with_see_each_others_writes() {
+    let cell = PriorityCell::<u32, I0>::new(1);
+
+    let old = cell.lock(&thread(), |v| std::mem::replace(v, 2));
+    assert_eq!(old, 1);
+
+    priority::simulate_i0(|p| cell.with(&p, |v| *v += 10));
+    assert_eq!(cell.lock(&thread(), |v| *v), 12);
+
+    // Each access leaves the cell ready for the next.
+    priority::simulate_i0(|p| cell.with(&p, |v| *v += 10));
+    assert_eq!(cell.into_inner(), 22);
+}
+
+#[test]
+#[should_panic(expected = "simulated interrupt while masked")]
+fn lock_masks_the_interrupt() {
+    let cell = PriorityCell::<u32, I0>::new(0);
+    cell.lock(&thread(), |_| {
+        priority::simulate_i0(|p| cell.with(&p, |_| ()));
+    });
+}
+
+#[test]
+#[should_panic(expected = "PriorityCell reentered")]
+fn nested_lock_panics() {
+    let cell = PriorityCell::<u32, I0>::new(0);
+    cell.lock(&thread(), |_| cell.lock(&thread(), |_| ()));
+}
+
+#[test]
+#[should_panic(expected = "PriorityCell reentered")]
+fn nested_with_panics() {
+    let cell = PriorityCell::<u32, I0>::new(0);
+    priority::simulate_i0(|p| cell.with(&p, |_| cell.with(&p, |_| ())));
+}
@@ -0,0 +1,135 @@
+//! Checks profiling statistics, driven by the simulated cycle counter.
+
+use std::sync::{Mutex, MutexGuard};
+
+use m4vga::util::profile::{self, LineHistogram, Scope, Stats, BUCKETS};
+
+/// The mock clock is global, so tests that use it take turns.
+fn exclusive() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    unsafe { profile::init() };
+    guard
+}
+
+#[test]
+fn mock_clock() {
+    let _guard = exclusive();
+    assert_eq!(profile::cycles(), 0);
+    profile::advance_mock_cycles(100);
+    assert_eq!(profile::cycles(), 100);
+    profile::set_mock_cycles(u32::MAX);
+    profile::advance_mock_cycles(2);
+    assert_eq!(profile::cycles(), 1);
+}
+
+#[test]
+fn scope_times_with_mock_clock() {
+    let _guard = exclusive();
+    static SCOPE: Scope = Scope::new("test");
+
+    SCOPE.measure(|| profile::advance_mock_cycles(30));
+    {
+        let _timing = SCOPE.enter();
+        profile::advance_mock_cycles(10);
+    }
+    // Nothing is visible until the frame ends.
+    assert_eq!(SCOPE.last_frame().count, 0);
+
+    SCOPE.end_frame();
+    let stats = SCOPE.last_frame();
+    assert_eq!(
+        stats,
+        Stats {
+            count: 2,
+            total: 40,
+            min: 10,
+            max: 30,
+        }
+    );
+    assert_eq!(stats.avg(), 20);
+
+    // The next frame starts from scratch, and the clock wrapping around
+    // doesn't disturb the measurement.
+    profile::set_mock_cycles(u32::MAX - 4);
+    SCOPE.measure(|| profile::advance_mock_cycles(7));
+    SCOPE.end_frame();
+    assert_eq!(SCOPE.last_frame().total, 7);
+    assert_eq!(SCOPE.last_frame().count, 1);
+}
+
+#[test]
+fn stats_avg() {
+    assert_eq!(Stats::default().avg(), 0);
+    let stats = Stats {
+        count: 3,
+        total: 11,
+        min: 1,
+        max: 6,
+    };
+    assert_eq!(stats.avg(), 3);
+}
+
+#[test]
+fn line_budget() {
+    // 800x600 at 60 Hz: 1056 pixels per line, 4 CPU cycles each.
+    assert_eq!(profile::line_budget(1056, 4), 4224);
+}
+
+#[test]
+fn histogram_buckets_by_fraction_of_budget() {
+    let mut h = LineHistogram::new(1000);
+    assert_eq!(h.worst(), None);
+    assert_eq!(h.headroom(), None);
+
+    h.record(0, 0);
+    h.record(1, 99);
+    h.record(2, 100);
+    h.record(3, 999);
+
+    let mut expected = [0; BUCKETS];
+    expected[0] = 2;
+    expected[1] = 1;
+    expected[BUCKETS - 1] = 1;
+    assert_eq!(h.buckets(), &expected);
+    assert_eq!(h.lines(), 4);
+    assert_eq!(h.over_budget(), 0);
+    assert_eq!(h.worst(), Some((999, 3)));
+    assert_eq!(h.headroom(), Some(1));
+}
+
+#[test]
+fn histogram_counts_over_budget() {
+    let mut h = LineHistogram::new(1000);
+    h.record(10, 1000);
+    h.record(11, 1500);
+    h.record(12, 200);
+
+    assert_eq!(h.over_budget(), 2);
+    assert_eq!(h.lines(), 3);
+    assert_eq!(h.buckets().iter().sum::<usize>(), 1);
+    assert_eq!(h.worst(), Some((1500, 11)));
+    assert_eq!(h.headroom(), Some(-500));
+}
+
+#[test]
+fn histogram_keeps_first_worst_line() {
+    let mut h = LineHistogram::new(1000);
+    h.record(5, 700);
+    h.record(6, 700);
+    h.record(7, 300);
+    assert_eq!(h.worst(), Some((700, 5)));
+
+    h.reset(500);
+    assert_eq!(h.budget(), 500);
+    assert_eq!(h.lines(), 0);
+    assert_eq!(h.worst(), None);
+}
+
+#[test]
+fn histogram_handles_huge_costs() {
+    let mut h = LineHistogram::new(u32::MAX);
+    h.record(0, u32::MAX - 1);
+    assert_eq!(h.buckets()[BUCKETS - 1], 1);
+    assert_eq!(h.headroom(), Some(1));
+}
@@ -0,0 +1,99 @@
+//! Checks the simulated measurement trace. This needs the `measurement`
+//! feature: `cargo test -p m4vga --features measurement`.
+
+use std::sync::{Mutex, MutexGuard};
+
+use m4vga::util::measurement::{self, trace};
+
+/// The trace is global, so tests take turns with it.
+fn exclusive() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    trace::reset();
+    guard
+}
+
+fn vcd() -> String {
+    let mut out = String::new();
+    trace::write_vcd(&mut out, "1 ns").unwrap();
+    out
+}
+
+#[test]
+fn records_events_as_vcd() {
+    let _guard = exclusive();
+    trace::set_time(10);
+    measurement::sig_a_set();
+    trace::advance(5);
+    measurement::sig_b_set();
+    measurement::sig_a_clear();
+    trace::advance(5);
+    measurement::sig_b_clear();
+
+    assert_eq!(trace::time(), 20);
+    assert_eq!(trace::dropped(), 0);
+    let mut events = Vec::new();
+    trace::for_each_event(|e| events.push((e.time, e.signal, e.level)));
+    assert_eq!(
+        events,
+        [
+            (10, trace::Signal::A, true),
+            (15, trace::Signal::B, true),
+            (15, trace::Signal::A, false),
+            (20, trace::Signal::B, false),
+        ]
+    );
+
+    assert_eq!(
+        vcd(),
+        "$version m4vga measurement trace $end\n\
+         $timescale 1 ns $end\n\
+         $scope module m4vga $end\n\
+         $var wire 1 ! sig_a $end\n\
+         $var wire 1 \" sig_b $end\n\
+         $var wire 1 # sig_c $end\n\
+         $var wire 1 $ sig_d $end\n\
+         $upscope $end\n\
+         $enddefinitions $end\n\
+         #10\n\
+         $dumpvars\n\
+         0!\n\
+         0\"\n\
+         0#\n\
+         0$\n\
+         $end\n\
+         1!\n\
+         #15\n\
+         1\"\n\
+         0!\n\
+         #20\n\
+         0\"\n"
+    );
+}
+
+#[test]
+fn full_buffer_keeps_initial_levels() {
+    let _guard = exclusive();
+    let total = trace::CAPACITY as u64 + 3;
+    for t in 0..total {
+        trace::set_time(t);
+        if t % 2 == 0 {
+            measurement::sig_c_set();
+        } else {
+            measurement::sig_c_clear();
+        }
+    }
+
+    assert_eq!(trace::dropped(), 3);
+    let mut times = Vec::new();
+    trace::for_each_event(|e| times.push(e.time));
+    assert_eq!(times, (3..total).collect::<Vec<_>>());
+
+    // The three discarded events left C set, and the oldest retained event
+    // clears it.
+    let vcd = vcd();
+    let dump = &vcd[vcd.find("#3\n").unwrap()..];
+    assert!(
+        dump.starts_with("#3\n$dumpvars\n0!\n0\"\n1#\n0$\n$end\n0#\n#4\n1#\n")
+    );
+}
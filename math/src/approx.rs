@@ -0,0 +1,304 @@
+//! Fast approximations of `sin`, `cos`, `atan2`, and `sqrt` for `f32`.
+//!
+//! The `libm` routines are careful to be correct to the last bit, which costs
+//! more than a per-frame effect can usually afford on the M4. These trade some
+//! precision for speed, with documented error bounds, and give the same
+//! results on every target, since they're built only from basic arithmetic.
+//!
+//! Precision is chosen by type: `Fast` or `Accurate`, both of which implement
+//! `Approx`. The bounds are in each type's associated constants, and are
+//! checked by dense sweeps in the tests.
+//!
+//! ```
+//! use math::approx::{Accurate, Approx, Fast};
+//!
+//! let (s, c) = Fast::sin_cos(1.);
+//! assert!((s - 0.841471).abs() <= Fast::SIN_ERROR);
+//! assert!((c - 0.540302).abs() <= Fast::SIN_ERROR);
+//! assert!((Accurate::sqrt(2.) - 1.414214).abs() < 1e-5);
+//! ```
+
+use core::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+/// Log2 of the number of segments in `SINE`.
+const SINE_BITS: u32 = 10;
+const SINE_SEGMENTS: usize = 1 << SINE_BITS;
+
+/// One full turn of the sine function, with the first entry repeated at the
+/// end so that interpolation never needs to wrap.
+static SINE: [f32; SINE_SEGMENTS + 1] = sine_table();
+
+const fn sine_table() -> [f32; SINE_SEGMENTS + 1] {
+    let mut table = [0.; SINE_SEGMENTS + 1];
+    let mut i = 0;
+    while i <= SINE_SEGMENTS {
+        // Evaluate the Taylor series in f64, on -π..π where 25 terms are
+        // plenty, and round once at the end.
+        let turns = i as f64 / SINE_SEGMENTS as f64;
+        let x = if turns > 0.5 { turns - 1. } else { turns }
+            * core::f64::consts::TAU;
+        let mut term = x;
+        let mut sum = x;
+        let mut k = 1;
+        while k < 25 {
+            term = -term * x * x / ((2 * k) as f64 * (2 * k + 1) as f64);
+            sum += term;
+            k += 1;
+        }
+        table[i] = sum as f32;
+        i += 1;
+    }
+    table
+}
+
+/// Looks up `sin(x)` and `cos(x)` in `SINE`, using every `stride`th entry,
+/// with linear interpolation.
+fn sine_lookup(x: f32, stride: usize) -> (f32, f32) {
+    let segments = (SINE_SEGMENTS / stride) as f32;
+    let t = x * (segments / (2. * PI));
+    // `as` truncates toward zero; step negative values down to the floor.
+    let whole = t as i32 - (t < 0.) as i32;
+    let frac = t - whole as f32;
+    // Negative indices wrap correctly, because the table size is a power of
+    // two.
+    let i = (whole as usize).wrapping_mul(stride) & (SINE_SEGMENTS - 1);
+    // Cosine is a quarter turn ahead.
+    let j = (i + SINE_SEGMENTS / 4) & (SINE_SEGMENTS - 1);
+    let lerp = |k: usize| SINE[k] + (SINE[k + stride] - SINE[k]) * frac;
+    (lerp(i), lerp(j))
+}
+
+/// Extends an approximation of `atan(z)`, for `0 <= z <= 1`, to all four
+/// quadrants.
+fn atan2_with(y: f32, x: f32, atan: impl Fn(f32) -> f32) -> f32 {
+    let (ax, ay) = (x.abs(), y.abs());
+    if ax == 0. && ay == 0. {
+        return 0.;
+    }
+    // Keep the ratio within 0..=1, where the approximations are accurate.
+    let a = if ay > ax {
+        FRAC_PI_2 - atan(ax / ay)
+    } else {
+        atan(ay / ax)
+    };
+    let a = if x < 0. { PI - a } else { a };
+    if y < 0. {
+        -a
+    } else {
+        a
+    }
+}
+
+/// Approximates `1/sqrt(x)` by the well-known bit-level initial guess,
+/// refined by `steps` rounds of Newton's method.
+fn rsqrt_with(x: f32, steps: usize) -> f32 {
+    let mut r = f32::from_bits(0x5f37_59df - (x.to_bits() >> 1));
+    for _ in 0..steps {
+        r *= 1.5 - 0.5 * x * r * r;
+    }
+    r
+}
+
+/// Approximations of transcendental functions at some precision.
+///
+/// The error bounds hold for arguments of magnitude up to 100 for `sin` and
+/// `cos`; past that, error grows with the argument, much as the argument's own
+/// rounding error does. For `rsqrt` and `sqrt`, they hold for all positive,
+/// finite, normal arguments, and the results for anything else are
+/// unspecified (but won't panic).
+pub trait Approx {
+    /// Largest absolute error of `sin`, `cos`, and `sin_cos`.
+    const SIN_ERROR: f32;
+    /// Largest absolute error of `atan2`, in radians.
+    const ATAN2_ERROR: f32;
+    /// Largest error of `rsqrt` and `sqrt`, relative to the correct result.
+    const SQRT_ERROR: f32;
+
+    /// Computes the sine and cosine of an angle in radians.
+    fn sin_cos(x: f32) -> (f32, f32);
+
+    fn sin(x: f32) -> f32 {
+        Self::sin_cos(x).0
+    }
+
+    fn cos(x: f32) -> f32 {
+        Self::sin_cos(x).1
+    }
+
+    /// Computes the four-quadrant arctangent of `y / x`, in radians, in the
+    /// range -π to π. Gives zero if both are zero.
+    fn atan2(y: f32, x: f32) -> f32;
+
+    /// Computes the reciprocal square root, `1 / sqrt(x)`.
+    fn rsqrt(x: f32) -> f32;
+
+    /// Computes the square root. Zero gives zero.
+    fn sqrt(x: f32) -> f32 {
+        if x == 0. {
+            0.
+        } else {
+            x * Self::rsqrt(x)
+        }
+    }
+}
+
+/// The cheapest approximations: good to about three decimal places, which is
+/// usually plenty for animation.
+///
+/// `sin_cos` interpolates a 64-segment table, and `atan2` uses a cubic
+/// polynomial due to Rajan et al. `rsqrt` uses one Newton step.
+#[derive(Copy, Clone, Debug)]
+pub struct Fast;
+
+impl Approx for Fast {
+    const SIN_ERROR: f32 = 1.3e-3;
+    const ATAN2_ERROR: f32 = 1.6e-3;
+    const SQRT_ERROR: f32 = 1.8e-3;
+
+    fn sin_cos(x: f32) -> (f32, f32) {
+        sine_lookup(x, SINE_SEGMENTS / 64)
+    }
+
+    fn atan2(y: f32, x: f32) -> f32 {
+        atan2_with(y, x, |z| {
+            FRAC_PI_4 * z + z * (1. - z) * (0.2447 + 0.0663 * z)
+        })
+    }
+
+    fn rsqrt(x: f32) -> f32 {
+        rsqrt_with(x, 1)
+    }
+}
+
+/// Approximations good to about five decimal places, still well below the
+/// cost of `libm`.
+///
+/// `sin_cos` interpolates a 1024-segment table, and `atan2` uses a ninth-order
+/// polynomial (Abramowitz and Stegun 4.4.47). `rsqrt` uses two Newton steps.
+#[derive(Copy, Clone, Debug)]
+pub struct Accurate;
+
+impl Approx for Accurate {
+    const SIN_ERROR: f32 = 1e-5;
+    const ATAN2_ERROR: f32 = 1.2e-5;
+    const SQRT_ERROR: f32 = 5e-6;
+
+    fn sin_cos(x: f32) -> (f32, f32) {
+        sine_lookup(x, 1)
+    }
+
+    fn atan2(y: f32, x: f32) -> f32 {
+        const C: [f32; 5] =
+            [0.999_866, -0.330_299_5, 0.180_141, -0.085_133, 0.020_835_1];
+        atan2_with(y, x, |z| {
+            let z2 = z * z;
+            z * C.iter().rev().fold(0., |acc, &c| acc * z2 + c)
+        })
+    }
+
+    fn rsqrt(x: f32) -> f32 {
+        rsqrt_with(x, 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Finds the largest absolute error of `f` against `reference` over
+    /// `count` evenly spaced points from `lo` to `hi`.
+    fn worst(
+        lo: f64,
+        hi: f64,
+        count: usize,
+        f: impl Fn(f32) -> f32,
+        reference: impl Fn(f64) -> f64,
+    ) -> f64 {
+        (0..=count)
+            .map(|i| {
+                let x = (lo + (hi - lo) * i as f64 / count as f64) as f32;
+                (f64::from(f(x)) - reference(f64::from(x))).abs()
+            })
+            .fold(0., f64::max)
+    }
+
+    /// Checks that the measured error `actual` is within the documented
+    /// `bound` -- but not wildly within it, which would make the bound useless
+    /// for choosing between implementations.
+    fn check_bound(what: &str, actual: f64, bound: f32) {
+        let bound = f64::from(bound);
+        assert!(actual <= bound, "{} error {} > {}", what, actual, bound);
+        assert!(
+            actual * 2. > bound,
+            "{} error {} << {}",
+            what,
+            actual,
+            bound
+        );
+    }
+
+    fn check_sin<A: Approx>() {
+        let s = worst(-100., 100., 400_000, A::sin, f64::sin);
+        let c = worst(-100., 100., 400_000, A::cos, f64::cos);
+        check_bound("sin", s, A::SIN_ERROR);
+        let bound = f64::from(A::SIN_ERROR);
+        assert!(c <= bound, "cos error {} > {}", c, bound);
+    }
+
+    fn check_atan2<A: Approx>() {
+        // Sweep the angle all the way around, at a few radii.
+        let mut e: f64 = 0.;
+        for &r in &[1e-3, 1., 250.] {
+            for i in 0..=100_000 {
+                let a =
+                    (i as f64 / 100_000. - 0.5) * 2. * core::f64::consts::PI;
+                let (y, x) = ((r * a.sin()) as f32, (r * a.cos()) as f32);
+                let expected = f64::from(y).atan2(f64::from(x));
+                e = e.max((f64::from(A::atan2(y, x)) - expected).abs());
+            }
+        }
+        check_bound("atan2", e, A::ATAN2_ERROR);
+        assert_eq!(A::atan2(0., 0.), 0.);
+        assert_eq!(A::atan2(0., -1.), PI);
+    }
+
+    fn check_sqrt<A: Approx>() {
+        let mut e: f64 = 0.;
+        // Every mantissa pattern in a couple of octaves, plus a wide sweep.
+        for bits in (0x3f80_0000u32..0x4080_0000).step_by(7) {
+            let x = f32::from_bits(bits);
+            let expected = f64::from(x).sqrt();
+            e = e.max((f64::from(A::sqrt(x)) / expected - 1.).abs());
+            let expected = 1. / expected;
+            e = e.max((f64::from(A::rsqrt(x)) / expected - 1.).abs());
+        }
+        for i in -100..100 {
+            let x = 1.37f32 * 2f32.powi(i);
+            let expected = f64::from(x).sqrt();
+            e = e.max((f64::from(A::sqrt(x)) / expected - 1.).abs());
+        }
+        check_bound("sqrt", e, A::SQRT_ERROR);
+        assert_eq!(A::sqrt(0.), 0.);
+    }
+
+    #[test]
+    fn fast_error_bounds() {
+        check_sin::<Fast>();
+        check_atan2::<Fast>();
+        check_sqrt::<Fast>();
+    }
+
+    #[test]
+    fn accurate_error_bounds() {
+        check_sin::<Accurate>();
+        check_atan2::<Accurate>();
+        check_sqrt::<Accurate>();
+    }
+
+    #[test]
+    fn table_endpoints() {
+        assert_eq!(SINE[0], 0.);
+        assert_eq!(SINE[SINE_SEGMENTS / 4], 1.);
+        assert_eq!(SINE[SINE_SEGMENTS], SINE[0]);
+    }
+}
@@ -0,0 +1,304 @@
+//! Clipping against the view frustum, in homogeneous clip space.
+//!
+//! A point that has been through a projection matrix like
+//! `Mat4::perspective`, but not yet through `project`, is inside the view
+//! frustum when each of its X, Y, and Z is between -W and W. Clipping there,
+//! before the divide, works even for points at or behind the camera, where
+//! `project` would produce nonsense.
+//!
+//! The usual pipeline is therefore: transform into clip space, clip, and only
+//! then apply the viewport transform (which commutes with the divide) and
+//! `project`.
+
+use num_traits::zero;
+
+use crate::{Real, Vec4};
+
+/// Outcode bit: the point is left of the frustum (X < -W).
+pub const LEFT: u8 = 1 << 0;
+/// Outcode bit: the point is right of the frustum (X > W).
+pub const RIGHT: u8 = 1 << 1;
+/// Outcode bit: the point is below the frustum (Y < -W).
+pub const BOTTOM: u8 = 1 << 2;
+/// Outcode bit: the point is above the frustum (Y > W).
+pub const TOP: u8 = 1 << 3;
+/// Outcode bit: the point is closer than the near plane (Z < -W). This
+/// includes everything behind the camera.
+pub const NEAR: u8 = 1 << 4;
+/// Outcode bit: the point is beyond the far plane (Z > W).
+pub const FAR: u8 = 1 << 5;
+
+/// Most vertices a triangle can have after clipping: each of the six planes
+/// can add at most one.
+pub const MAX_VERTICES: usize = 9;
+
+/// Signed distances (scaled by an arbitrary positive factor) from `v` to each
+/// frustum plane, in outcode bit order. Non-negative means inside.
+fn distances<T: Real>(v: Vec4<T>) -> [T; 6] {
+    let Vec4(x, y, z, w) = v;
+    [w + x, w - x, w + y, w - y, w + z, w - z]
+}
+
+/// Computes the Cohen-Sutherland outcode of a clip-space point: a bit for
+/// each frustum plane that it's outside of. Zero means it's inside the
+/// frustum.
+///
+/// If two points' outcodes have a bit in common, any line or polygon between
+/// them is entirely outside, and can be rejected without clipping.
+pub fn outcode<T: Real>(v: Vec4<T>) -> u8 {
+    distances(v)
+        .iter()
+        .enumerate()
+        .filter(|(_, &d)| d < zero())
+        .fold(0, |code, (i, _)| code | 1 << i)
+}
+
+/// Interpolates between two clip-space points.
+fn mix<T: Real>(a: Vec4<T>, b: Vec4<T>, t: T) -> Vec4<T> {
+    Vec4(
+        a.0 + (b.0 - a.0) * t,
+        a.1 + (b.1 - a.1) * t,
+        a.2 + (b.2 - a.2) * t,
+        a.3 + (b.3 - a.3) * t,
+    )
+}
+
+/// Clips the segment from `a` to `b` to the view frustum, returning the
+/// visible part, or `None` if it's entirely outside.
+///
+/// Endpoints that are already inside are returned unchanged.
+pub fn clip_line<T: Real>(
+    a: Vec4<T>,
+    b: Vec4<T>,
+) -> Option<(Vec4<T>, Vec4<T>)> {
+    let (da, db) = (distances(a), distances(b));
+    // Parametric range of the visible part, Liang-Barsky style.
+    let mut t0 = zero();
+    let mut t1 = T::one();
+    for (&da, &db) in da.iter().zip(db.iter()) {
+        match (da < zero(), db < zero()) {
+            (true, true) => return None,
+            (true, false) => {
+                let t = da / (da - db);
+                if t > t0 {
+                    t0 = t
+                }
+            }
+            (false, true) => {
+                let t = da / (da - db);
+                if t < t1 {
+                    t1 = t
+                }
+            }
+            (false, false) => (),
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let a2 = if t0 > zero() { mix(a, b, t0) } else { a };
+    let b2 = if t1 < T::one() { mix(a, b, t1) } else { b };
+    Some((a2, b2))
+}
+
+/// A convex polygon produced by `clip_triangle`.
+#[derive(Copy, Clone, Debug)]
+pub struct Polygon<T> {
+    vertices: [Vec4<T>; MAX_VERTICES],
+    len: usize,
+}
+
+impl<T: Real> Polygon<T> {
+    fn new() -> Self {
+        Polygon {
+            vertices: [Vec4(zero(), zero(), zero(), zero()); MAX_VERTICES],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, v: Vec4<T>) {
+        self.vertices[self.len] = v;
+        self.len += 1;
+    }
+
+    /// The vertices, in the same winding order as the original triangle.
+    pub fn vertices(&self) -> &[Vec4<T>] {
+        &self.vertices[..self.len]
+    }
+
+    /// Checks whether clipping left anything.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Splits the polygon back into triangles, as a fan around its first
+    /// vertex, preserving winding order.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec4<T>; 3]> + '_ {
+        let v = self.vertices();
+        (2..v.len()).map(move |i| [v[0], v[i - 1], v[i]])
+    }
+}
+
+/// Clips a triangle to the view frustum.
+///
+/// The result is empty if the triangle is entirely outside. If it's entirely
+/// inside, the result is the original triangle, unchanged.
+pub fn clip_triangle<T: Real>(tri: [Vec4<T>; 3]) -> Polygon<T> {
+    let mut poly = Polygon::new();
+    for &v in &tri {
+        poly.push(v);
+    }
+
+    // Sutherland-Hodgman: clip against one plane at a time, skipping planes
+    // that every vertex is already inside.
+    let outside = tri.iter().fold(0, |c, &v| c | outcode(v));
+    for plane in 0..6 {
+        if outside & 1 << plane == 0 {
+            continue;
+        }
+        let input = poly;
+        poly = Polygon::new();
+        let verts = input.vertices();
+        for (i, &b) in verts.iter().enumerate() {
+            let a = verts[(i + verts.len() - 1) % verts.len()];
+            let (da, db) = (distances(a)[plane], distances(b)[plane]);
+            let (a_in, b_in) = (da >= zero(), db >= zero());
+            if a_in != b_in {
+                poly.push(mix(a, b, da / (da - db)));
+            }
+            if b_in {
+                poly.push(b);
+            }
+        }
+        if poly.is_empty() {
+            break;
+        }
+    }
+    poly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Augment, Mat4f, Project, Vec3, Vec4f};
+
+    fn inside(v: Vec4f) -> bool {
+        distances(v).iter().all(|&d| d >= -1e-5)
+    }
+
+    #[test]
+    fn outcodes() {
+        assert_eq!(outcode(Vec4(0., 0., 0., 1.)), 0);
+        assert_eq!(outcode(Vec4(1., -1., 1., 1.)), 0);
+        assert_eq!(outcode(Vec4(-2., 0., 0., 1.)), LEFT);
+        assert_eq!(outcode(Vec4(2., 2., 2., 1.)), RIGHT | TOP | FAR);
+        assert_eq!(outcode(Vec4(0., -3., -3., 1.)), BOTTOM | NEAR);
+        // Behind the camera, everything is outside the near plane.
+        let p = Mat4f::perspective(-1., 1., 1., -1., 1., 10.);
+        let behind = p * Vec3(0., 0., 5.).augment();
+        assert_ne!(outcode(behind) & NEAR, 0);
+    }
+
+    #[test]
+    fn lines() {
+        let a = Vec4(0., 0., 0., 1.);
+        let b = Vec4(0.5, -0.5, 0.25, 1.);
+        assert_eq!(clip_line(a, b).map(|(a, b)| (a.0, b.0)), Some((0., 0.5)));
+
+        // Crossing the right plane at x = 1.
+        let (a2, b2) = clip_line(a, Vec4(3., 0., 0., 1.)).unwrap();
+        assert_eq!((a2.0, b2.0), (0., 1.));
+
+        // Both ends outside, but through the middle.
+        let (a2, b2) =
+            clip_line(Vec4(-3., 0., 0., 1.), Vec4(3., 0., 0., 1.)).unwrap();
+        assert_eq!((a2.0, b2.0), (-1., 1.));
+
+        // Both outside, missing the frustum entirely past a corner.
+        assert!(
+            clip_line(Vec4(-3., 0.5, 0., 1.), Vec4(0.5, 3., 0., 1.)).is_none()
+        );
+        assert!(clip_line(Vec4(2., 0., 0., 1.), Vec4(3., 0., 0., 1.)).is_none());
+    }
+
+    /// A segment running from in front of the camera to behind it: the
+    /// unclipped projection flips the far end to the wrong side, while the
+    /// clipped one stops at the near plane.
+    #[test]
+    fn lines_through_the_camera() {
+        let p = Mat4f::perspective(-1., 1., 1., -1., 1., 10.);
+        let front = p * Vec3(0.5, 0., -5.).augment();
+        let back = p * Vec3(0.5, 0., 5.).augment();
+        assert!(back.project().0 < 0.);
+
+        let (a, b) = clip_line(front, back).unwrap();
+        assert_eq!(a.0, front.0);
+        let b = b.project();
+        assert!((b.2 + 1.).abs() < 1e-5);
+        assert!(b.0 > 0.);
+    }
+
+    #[test]
+    fn triangles() {
+        let inner = [
+            Vec4(0., 0., 0., 1.),
+            Vec4(0.5, 0., 0., 1.),
+            Vec4(0., 0.5, 0., 1.),
+        ];
+        let poly = clip_triangle(inner);
+        assert_eq!(poly.vertices().len(), 3);
+        assert_eq!(poly.vertices()[1].0, 0.5);
+
+        let outer = [
+            Vec4(2., 0., 0., 1.),
+            Vec4(3., 0., 0., 1.),
+            Vec4(2., 1., 0., 1.),
+        ];
+        assert!(clip_triangle(outer).is_empty());
+
+        // Corner cut off by the right plane: a quad, two triangles.
+        let poly = clip_triangle([
+            Vec4(0., 0., 0., 1.),
+            Vec4(2., 0., 0., 1.),
+            Vec4(0., 0.5, 0., 1.),
+        ]);
+        assert_eq!(poly.vertices().len(), 4);
+        assert_eq!(poly.triangles().count(), 2);
+        assert!(poly.vertices().iter().all(|&v| inside(v)));
+
+        // Covering all but one corner of the frustum's cross-section: a
+        // pentagon.
+        let poly = clip_triangle([
+            Vec4(-1.5, -1.5, 0., 1.),
+            Vec4(3., -1.5, 0., 1.),
+            Vec4(-1.5, 3., 0., 1.),
+        ]);
+        assert_eq!(poly.vertices().len(), 5);
+        assert!(poly.vertices().iter().all(|&v| inside(v)));
+        let area: f32 = poly
+            .triangles()
+            .map(|[a, b, c]| {
+                ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)) / 2.
+            })
+            .sum();
+        // Counterclockwise, like the input, and missing the corner.
+        assert!((area - 3.875).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fixed_point() {
+        use crate::I16F16;
+        let f = |x: f32, w: f32| {
+            Vec4(
+                I16F16::from_f32(x),
+                I16F16::ZERO,
+                I16F16::ZERO,
+                I16F16::from_f32(w),
+            )
+        };
+        let (a, b) = clip_line(f(0., 2.), f(6., 2.)).unwrap();
+        assert_eq!(a.0, I16F16::ZERO);
+        assert!((b.0.to_f32() - 2.).abs() <= 1. / 32768.);
+        assert_eq!(outcode(f(-3., 2.)), LEFT);
+    }
+}
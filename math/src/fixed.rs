@@ -0,0 +1,497 @@
+//! Fixed-point scalars.
+//!
+//! `I16F16` has 16 integer and 16 fractional bits in an `i32`; `I8F8` has 8 and
+//! 8 in an `i16`. Both can be used as the element type of the vectors and
+//! matrices in this crate, and implement `Real`, so the transform constructors
+//! work with them too.
+//!
+//! Unlike `f32`, arithmetic on these types produces bit-identical results on
+//! every target, whether or not it has an FPU. Multiplication rounds to the
+//! nearest representable value; division rounds toward zero. As with the
+//! integer types, overflow panics in debug builds and wraps in release builds,
+//! and division by zero panics.
+
+use core::fmt;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use num_traits::{One, Zero};
+
+use crate::Real;
+
+macro_rules! fixed {
+    (
+        $(#[$attr:meta])*
+        $name:ident, $bits:ty, $wide:ty, $int:ty, $frac:expr
+    ) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+        #[repr(transparent)]
+        pub struct $name($bits);
+
+        impl $name {
+            /// Number of fractional bits.
+            pub const FRAC_BITS: u32 = $frac;
+            pub const ZERO: Self = $name(0);
+            pub const ONE: Self = $name(1 << $frac);
+            pub const MIN: Self = $name(<$bits>::min_value());
+            pub const MAX: Self = $name(<$bits>::max_value());
+
+            /// Reinterprets a raw integer, scaled by `2^FRAC_BITS`, as a
+            /// fixed-point number.
+            pub const fn from_bits(bits: $bits) -> Self {
+                $name(bits)
+            }
+
+            /// Gets the raw representation, scaled by `2^FRAC_BITS`.
+            pub const fn to_bits(self) -> $bits {
+                self.0
+            }
+
+            pub const fn from_int(n: $int) -> Self {
+                $name((n as $bits) << $frac)
+            }
+
+            /// Converts from `f32`, rounding to the nearest representable
+            /// value. Out-of-range values saturate, and NaN becomes zero.
+            pub fn from_f32(v: f32) -> Self {
+                let scaled = v * (1 << $frac) as f32;
+                let rounded = if scaled < 0. {
+                    scaled - 0.5
+                } else {
+                    scaled + 0.5
+                };
+                $name(rounded as $bits)
+            }
+
+            pub fn to_f32(self) -> f32 {
+                self.0 as f32 / (1 << $frac) as f32
+            }
+        }
+
+        impl From<$int> for $name {
+            fn from(n: $int) -> Self {
+                Self::from_int(n)
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                $name(self.0 + rhs.0)
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                $name(self.0 - rhs.0)
+            }
+        }
+
+        impl Neg for $name {
+            type Output = Self;
+            fn neg(self) -> Self {
+                $name(-self.0)
+            }
+        }
+
+        impl Mul for $name {
+            type Output = Self;
+            fn mul(self, rhs: Self) -> Self {
+                let product = <$wide>::from(self.0) * <$wide>::from(rhs.0);
+                let rounded = (product + (1 << ($frac - 1))) >> $frac;
+                debug_assert!(
+                    rounded >= <$wide>::from(<$bits>::min_value())
+                        && rounded <= <$wide>::from(<$bits>::max_value()),
+                    "fixed-point multiply overflow"
+                );
+                $name(rounded as $bits)
+            }
+        }
+
+        impl Div for $name {
+            type Output = Self;
+            fn div(self, rhs: Self) -> Self {
+                let quotient =
+                    (<$wide>::from(self.0) << $frac) / <$wide>::from(rhs.0);
+                debug_assert!(
+                    quotient >= <$wide>::from(<$bits>::min_value())
+                        && quotient <= <$wide>::from(<$bits>::max_value()),
+                    "fixed-point divide overflow"
+                );
+                $name(quotient as $bits)
+            }
+        }
+
+        impl Zero for $name {
+            fn zero() -> Self {
+                Self::ZERO
+            }
+
+            fn is_zero(&self) -> bool {
+                self.0 == 0
+            }
+        }
+
+        impl One for $name {
+            fn one() -> Self {
+                Self::ONE
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self.to_f32())
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.to_f32(), f)
+            }
+        }
+    };
+}
+
+fixed! {
+    /// Signed Q16.16 fixed-point number, with a range of about ±32768 and a
+    /// resolution of about 0.000015.
+    I16F16, i32, i64, i16, 16
+}
+
+fixed! {
+    /// Signed Q8.8 fixed-point number, with a range of about ±128 and a
+    /// resolution of about 0.004.
+    I8F8, i16, i32, i8, 8
+}
+
+/// Scale factor from radians to turns, where a full turn is 2^32: that is,
+/// 2^32 / 2π, rounded.
+const TURNS_PER_RADIAN: i64 = 683_565_276;
+
+/// Taylor series coefficients for `sin(z * π/2)`, in Q2.30: the odd powers of
+/// π/2 divided by their factorials. Through the ninth power, the error on
+/// `0..=1` is below 4e-6, which is less than the resolution of `I16F16`.
+const SIN_COEFFS: [i64; 5] =
+    [1_686_629_713, 693_598_668, 85_569_306, 5_026_995, 172_272];
+
+/// Computes the sine of an angle given as a fraction of a turn, in Q2.30.
+fn sin_turns(phase: u32) -> i64 {
+    let quadrant = phase >> 30;
+    let z = i64::from(phase & 0x3FFF_FFFF);
+    // The second and fourth quadrants mirror the first and third.
+    let z = if quadrant & 1 != 0 { (1 << 30) - z } else { z };
+
+    let z2 = (z * z) >> 30;
+    let mut r = SIN_COEFFS[4];
+    for &c in SIN_COEFFS[..4].iter().rev() {
+        r = c - ((z2 * r) >> 30);
+    }
+    let s = (z * r) >> 30;
+
+    if quadrant >= 2 {
+        -s
+    } else {
+        s
+    }
+}
+
+/// π and fractions of it, in Q2.30.
+const PI: i64 = 3_373_259_426;
+const FRAC_PI_2: i64 = 1_686_629_713;
+const FRAC_PI_4: i64 = 843_314_857;
+/// tan(π/8), in Q2.30.
+const TAN_FRAC_PI_8: i64 = 444_758_426;
+
+/// Taylor series coefficients for `atan(u)`, in Q2.30: the reciprocals of the
+/// odd numbers. For `|u| <= tan(π/8)`, the error through the thirteenth power
+/// is below 2e-7.
+const ATAN_COEFFS: [i64; 7] = [
+    1_073_741_824,
+    357_913_941,
+    214_748_365,
+    153_391_689,
+    119_304_647,
+    97_612_893,
+    82_595_525,
+];
+
+/// Computes `atan2(y, x)` in Q2.30, for any scale of `y` and `x` up to 2^31.
+fn atan2_q30(y: i64, x: i64) -> i64 {
+    if x == 0 && y == 0 {
+        return 0;
+    }
+    let (ax, ay) = (x.abs(), y.abs());
+
+    // Fold into the first octant, where the ratio is at most 1...
+    let steep = ay > ax;
+    let z = if steep {
+        (ax << 30) / ay
+    } else {
+        (ay << 30) / ax
+    };
+    // ...and then, using atan(z) = π/4 + atan((z - 1) / (z + 1)), into a
+    // range where the series converges quickly.
+    let (u, base) = if z > TAN_FRAC_PI_8 {
+        (((z - (1 << 30)) << 30) / (z + (1 << 30)), FRAC_PI_4)
+    } else {
+        (z, 0)
+    };
+
+    let u2 = (u * u) >> 30;
+    let mut r = ATAN_COEFFS[6];
+    for &c in ATAN_COEFFS[..6].iter().rev() {
+        r = c - ((u2 * r) >> 30);
+    }
+    let mut a = base + ((u * r) >> 30);
+
+    if steep {
+        a = FRAC_PI_2 - a;
+    }
+    if x < 0 {
+        a = PI - a;
+    }
+    if y < 0 {
+        -a
+    } else {
+        a
+    }
+}
+
+/// Integer square root, rounded to nearest.
+fn isqrt(mut n: u64) -> u64 {
+    let mut root = 0;
+    let mut bit = 1 << 62;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= root + bit {
+            n -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    // `n` is now the remainder. Round up if the root is closer to the next
+    // integer.
+    if n > root {
+        root + 1
+    } else {
+        root
+    }
+}
+
+/// Converts Q2.30 to `I16F16`, rounding to nearest.
+fn from_q30(v: i64) -> I16F16 {
+    I16F16(((v + (1 << 13)) >> 14) as i32)
+}
+
+impl I8F8 {
+    /// Widens to `I16F16`, exactly.
+    fn widen(self) -> I16F16 {
+        I16F16(i32::from(self.0) << 8)
+    }
+
+    /// Narrows from `I16F16`, rounding to nearest.
+    fn narrow(x: I16F16) -> Self {
+        I8F8(((x.0 + (1 << 7)) >> 8) as i16)
+    }
+}
+
+impl Real for I16F16 {
+    fn from_f32(v: f32) -> Self {
+        I16F16::from_f32(v)
+    }
+
+    fn to_f32(self) -> f32 {
+        I16F16::to_f32(self)
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        let phase = ((i64::from(self.0) * TURNS_PER_RADIAN) >> 16) as u32;
+        (
+            from_q30(sin_turns(phase)),
+            from_q30(sin_turns(phase.wrapping_add(1 << 30))),
+        )
+    }
+
+    fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        I16F16(isqrt((self.0 as u64) << 16) as i32)
+    }
+
+    fn atan2(self, x: Self) -> Self {
+        from_q30(atan2_q30(i64::from(self.0), i64::from(x.0)))
+    }
+}
+
+impl Real for I8F8 {
+    fn from_f32(v: f32) -> Self {
+        I8F8::from_f32(v)
+    }
+
+    fn to_f32(self) -> f32 {
+        I8F8::to_f32(self)
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        let (s, c) = self.widen().sin_cos();
+        (Self::narrow(s), Self::narrow(c))
+    }
+
+    fn sqrt(self) -> Self {
+        Self::narrow(self.widen().sqrt())
+    }
+
+    fn atan2(self, x: Self) -> Self {
+        Self::narrow(self.widen().atan2(x.widen()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HomoTransform, Mat3, Mat4, Project, Vec3, Vec4};
+
+    /// One unit in the last place of `I16F16`.
+    const ULP: f32 = 1. / 65536.;
+
+    fn fx(v: f32) -> I16F16 {
+        I16F16::from_f32(v)
+    }
+
+    /// Exact conversion, for comparisons; `to_f32` rounds large values.
+    fn exact(v: I16F16) -> f64 {
+        f64::from(v.to_bits()) / 65536.
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(I16F16::from_int(-3).to_bits(), -3 << 16);
+        assert_eq!(fx(1.5).to_bits(), 0x1_8000);
+        assert_eq!(fx(-1.5).to_bits(), -0x1_8000);
+        assert_eq!(fx(ULP * 0.49).to_bits(), 0);
+        assert_eq!(fx(1e9), I16F16::MAX);
+        assert_eq!(I8F8::from_f32(-0.25).to_bits(), -64);
+        assert_eq!(I8F8::from(2).to_f32(), 2.);
+    }
+
+    #[test]
+    fn arithmetic_matches_f32() {
+        let values = [-300.25, -7.5, -1., -0.3, 0., 0.001, 0.7, 2., 13.37, 99.];
+        for &a in &values {
+            for &b in &values {
+                let (fa, fb) = (fx(a), fx(b));
+                assert_eq!((fa + fb).to_f32(), fx(a).to_f32() + fx(b).to_f32());
+                let (a64, b64) = (exact(fa), exact(fb));
+                let product = a64 * b64;
+                if product.abs() < 32767. {
+                    let err = (exact(fa * fb) - product).abs();
+                    assert!(err <= ULP as f64 / 2., "{} * {}: {}", a, b, err);
+                }
+                if b != 0. {
+                    let quotient = a64 / b64;
+                    if quotient.abs() < 32767. {
+                        let err = (exact(fa / fb) - quotient).abs();
+                        assert!(err < ULP as f64, "{} / {}: {}", a, b, err);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sin_cos_accuracy() {
+        let mut worst: f32 = 0.;
+        for i in -2000..=2000 {
+            let angle = fx(i as f32 * 0.0117);
+            let (s, c) = angle.sin_cos();
+            let a = angle.to_f32() as f64;
+            worst = worst
+                .max((s.to_f32() as f64 - a.sin()).abs() as f32)
+                .max((c.to_f32() as f64 - a.cos()).abs() as f32);
+        }
+        assert!(worst <= 1.5 * ULP, "worst error {}", worst);
+
+        // Exact at the axes.
+        assert_eq!(I16F16::ZERO.sin_cos(), (I16F16::ZERO, I16F16::ONE));
+
+        let (s, c) = I8F8::from_f32(1.).sin_cos();
+        assert!((s.to_f32() - 1f32.sin()).abs() < 1. / 256.);
+        assert!((c.to_f32() - 1f32.cos()).abs() < 1. / 256.);
+    }
+
+    #[test]
+    fn sqrt_and_atan2_accuracy() {
+        for i in 0..5000 {
+            let v = fx(i as f32 * 6.1 + 0.013);
+            let expected = exact(v).sqrt();
+            let err = (exact(v.sqrt()) - expected).abs();
+            assert!(err <= f64::from(ULP) / 2., "sqrt({}): {}", v, err);
+        }
+        assert_eq!(fx(-4.).sqrt(), I16F16::ZERO);
+        assert_eq!(I8F8::from_f32(6.25).sqrt(), I8F8::from_f32(2.5));
+
+        let mut worst: f64 = 0.;
+        for i in -40..=40 {
+            for j in -40..=40 {
+                let (y, x) = (fx(i as f32 * 0.37), fx(j as f32 * 0.29));
+                if i == 0 && j == 0 {
+                    assert_eq!(y.atan2(x), I16F16::ZERO);
+                    continue;
+                }
+                let expected = exact(y).atan2(exact(x));
+                worst = worst.max((exact(y.atan2(x)) - expected).abs());
+            }
+        }
+        assert!(worst <= f64::from(ULP), "worst error {}", worst);
+    }
+
+    /// Runs a point through rotation and perspective in both `f32` and
+    /// `I16F16`, and compares the projected results.
+    #[test]
+    fn transforms_match_f32() {
+        let points =
+            [(1., 2., 3.), (-4., 0.5, -2.), (3.25, -3., 4.), (0., 0., 0.)];
+        for frame in 0..50 {
+            let angle = frame as f32 * 0.13;
+            let build = |f: &dyn Fn(f32) -> I16F16| {
+                Mat4::perspective(f(-1.), f(-1.), f(1.), f(1.), f(2.), f(20.))
+                    * Mat4::translate(Vec3(f(0.), f(0.), f(-10.)))
+                    * Mat4::rotate_y(f(angle))
+                    * Mat4::rotate_z(f(angle * 0.7))
+            };
+            let fixed = build(&fx);
+            let float = Mat4::perspective(-1., -1., 1., 1., 2., 20.)
+                * Mat4::translate(Vec3(0., 0., -10.))
+                * Mat4::rotate_y(angle)
+                * Mat4::rotate_z(angle * 0.7);
+
+            for &(x, y, z) in &points {
+                let Vec3(fx_, fy, fz) =
+                    (fixed * Vec4(fx(x), fx(y), fx(z), I16F16::ONE)).project();
+                let Vec3(ex, ey, ez) = (float * Vec4(x, y, z, 1.)).project();
+                for &(a, e) in &[(fx_, ex), (fy, ey), (fz, ez)] {
+                    let err = (a.to_f32() - e).abs();
+                    assert!(err < 0.002, "frame {}: {} vs {}", frame, a, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mat3_rotate_matches_f32() {
+        for i in 0..100 {
+            let angle = i as f32 * 0.07 - 3.;
+            let fixed = Mat3::rotate(fx(angle)) * Vec3(fx(5.), fx(-2.), fx(1.));
+            let float = Mat3::rotate(angle) * Vec3(5., -2., 1.);
+            assert!((fixed.0.to_f32() - float.0).abs() < 0.0005);
+            assert!((fixed.1.to_f32() - float.1).abs() < 0.0005);
+            assert_eq!(fixed.2, I16F16::ONE);
+        }
+    }
+}
@@ -4,6 +4,15 @@
 
 use num_traits::{one, zero, One, Zero};
 
+pub mod approx;
+pub mod clip;
+pub mod fixed;
+pub mod noise;
+pub mod quat;
+
+pub use fixed::{I16F16, I8F8};
+pub use quat::Quat;
+
 /// The `Vector` trait describes types that act like vectors, in the
 /// mathematical sense.
 ///
@@ -22,6 +31,63 @@ pub trait Element: Zero + One {}
 
 impl<T> Element for T where T: Zero + One {}
 
+/// Scalars that can stand in for real numbers when building transforms:
+/// `f32`, or one of the fixed-point types in `fixed`.
+pub trait Real:
+    Element
+    + Copy
+    + PartialOrd
+    + core::ops::Neg<Output = Self>
+    + core::ops::Sub<Output = Self>
+    + core::ops::Div<Output = Self>
+{
+    /// Converts from `f32`, rounding to the nearest representable value.
+    fn from_f32(v: f32) -> Self;
+
+    fn to_f32(self) -> f32;
+
+    /// Computes the sine and cosine of an angle in radians.
+    fn sin_cos(self) -> (Self, Self);
+
+    fn sin(self) -> Self {
+        self.sin_cos().0
+    }
+
+    fn cos(self) -> Self {
+        self.sin_cos().1
+    }
+
+    /// Computes the square root. Negative inputs produce NaN for `f32`, and
+    /// zero for the fixed-point types.
+    fn sqrt(self) -> Self;
+
+    /// Computes the four-quadrant arctangent of `self / x`, in radians, in the
+    /// range -π to π.
+    fn atan2(self, x: Self) -> Self;
+}
+
+impl Real for f32 {
+    fn from_f32(v: f32) -> Self {
+        v
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn sin_cos(self) -> (Self, Self) {
+        (libm::F32Ext::sin(self), libm::F32Ext::cos(self))
+    }
+
+    fn sqrt(self) -> Self {
+        libm::F32Ext::sqrt(self)
+    }
+
+    fn atan2(self, x: Self) -> Self {
+        libm::F32Ext::atan2(self, x)
+    }
+}
+
 /// A vector in homogeneous coordinates that can be projected down to a
 /// lower-dimensional cartesian space.
 pub trait Project: Vector
@@ -337,6 +403,14 @@ where
             Vec4(zero(), zero(), zero(), one()),
         )
     }
+    pub fn rotate_x_pre(sin: T, cos: T) -> Self {
+        Mat4(
+            Vec4(one(), zero(), zero(), zero()),
+            Vec4(zero(), cos.clone(), -sin.clone(), zero()),
+            Vec4(zero(), sin, cos, zero()),
+            Vec4(zero(), zero(), zero(), one()),
+        )
+    }
     pub fn rotate_z_pre(sin: T, cos: T) -> Self {
         Mat4(
             Vec4(cos.clone(), -sin.clone(), zero(), zero()),
@@ -347,33 +421,190 @@ where
     }
 }
 
-impl Mat4f {
-    pub fn rotate_y(angle: f32) -> Self {
-        use libm::F32Ext;
-        Mat4::rotate_y_pre(angle.sin(), angle.cos())
+impl<T> Mat4<T>
+where
+    T: Element
+        + Copy
+        + PartialEq
+        + core::ops::Neg<Output = T>
+        + core::ops::Sub<Output = T>
+        + core::ops::Div<Output = T>,
+{
+    /// Orthographic projection, mapping the given box onto the cube from -1
+    /// to 1 on each axis. Like `perspective`, the camera looks down -Z, so
+    /// `near` and `far` are distances in front of it.
+    pub fn ortho(
+        left: T,
+        top: T,
+        right: T,
+        bottom: T,
+        near: T,
+        far: T,
+    ) -> Self {
+        let two = T::one() + T::one();
+        let width = right - left;
+        let height = top - bottom;
+        let depth = far - near;
+        Mat4(
+            Vec4(two / width, zero(), zero(), -(right + left) / width),
+            Vec4(zero(), two / height, zero(), -(top + bottom) / height),
+            Vec4(zero(), zero(), -two / depth, -(far + near) / depth),
+            Vec4(zero(), zero(), zero(), one()),
+        )
+    }
+
+    pub fn determinant(self) -> T {
+        let (s, c) = self.minors();
+        Self::expand(&s, &c)
+    }
+
+    /// Computes the inverse, or `None` if the matrix is singular.
+    pub fn inverse(self) -> Option<Self> {
+        let (s, c) = self.minors();
+        let det = Self::expand(&s, &c);
+        if det == zero() {
+            return None;
+        }
+        let Mat4(
+            Vec4(a00, a01, a02, a03),
+            Vec4(a10, a11, a12, a13),
+            Vec4(a20, a21, a22, a23),
+            Vec4(a30, a31, a32, a33),
+        ) = self;
+        // Each entry is a cofactor, built from the 2x2 minors, divided
+        // through by the determinant. Dividing each entry, rather than
+        // multiplying by 1/det, keeps precision in the fixed-point types.
+        let d = |v: T| v / det;
+        Some(Mat4(
+            Vec4(
+                d(a11 * c[5] - a12 * c[4] + a13 * c[3]),
+                d(-(a01 * c[5]) + a02 * c[4] - a03 * c[3]),
+                d(a31 * s[5] - a32 * s[4] + a33 * s[3]),
+                d(-(a21 * s[5]) + a22 * s[4] - a23 * s[3]),
+            ),
+            Vec4(
+                d(-(a10 * c[5]) + a12 * c[2] - a13 * c[1]),
+                d(a00 * c[5] - a02 * c[2] + a03 * c[1]),
+                d(-(a30 * s[5]) + a32 * s[2] - a33 * s[1]),
+                d(a20 * s[5] - a22 * s[2] + a23 * s[1]),
+            ),
+            Vec4(
+                d(a10 * c[4] - a11 * c[2] + a13 * c[0]),
+                d(-(a00 * c[4]) + a01 * c[2] - a03 * c[0]),
+                d(a30 * s[4] - a31 * s[2] + a33 * s[0]),
+                d(-(a20 * s[4]) + a21 * s[2] - a23 * s[0]),
+            ),
+            Vec4(
+                d(-(a10 * c[3]) + a11 * c[1] - a12 * c[0]),
+                d(a00 * c[3] - a01 * c[1] + a02 * c[0]),
+                d(-(a30 * s[3]) + a31 * s[1] - a32 * s[0]),
+                d(a20 * s[3] - a21 * s[1] + a22 * s[0]),
+            ),
+        ))
+    }
+
+    /// Computes the matrix for transforming surface normals, which is the
+    /// inverse transpose of the upper-left 3x3. Unlike the matrix itself, it
+    /// keeps normals perpendicular to their surfaces under non-uniform
+    /// scaling. Returns `None` if the matrix is singular.
+    ///
+    /// The results are not renormalized; for anything but a pure rotation,
+    /// callers that need unit normals must do that themselves.
+    pub fn normal_matrix(self) -> Option<Mat3<T>> {
+        let Mat4(r0, r1, r2, _) = self;
+        Mat3(
+            Vec3(r0.0, r0.1, r0.2),
+            Vec3(r1.0, r1.1, r1.2),
+            Vec3(r2.0, r2.1, r2.2),
+        )
+        .inverse()
+        .map(Matrix::transpose)
+    }
+
+    /// Computes the 2x2 minors of the top two rows, and of the bottom two,
+    /// shared by `determinant` and `inverse`.
+    fn minors(self) -> ([T; 6], [T; 6]) {
+        let Mat4(r0, r1, r2, r3) = self;
+        let m = |a: &Vec4<T>, b: &Vec4<T>| {
+            [
+                a.0 * b.1 - b.0 * a.1,
+                a.0 * b.2 - b.0 * a.2,
+                a.0 * b.3 - b.0 * a.3,
+                a.1 * b.2 - b.1 * a.2,
+                a.1 * b.3 - b.1 * a.3,
+                a.2 * b.3 - b.2 * a.3,
+            ]
+        };
+        (m(&r0, &r1), m(&r2, &r3))
+    }
+
+    /// Finds the determinant from the minors, by Laplace expansion along the
+    /// top two rows.
+    fn expand(s: &[T; 6], c: &[T; 6]) -> T {
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1]
+            + s[5] * c[0]
+    }
+}
+
+impl<T: Real> Mat4<T> {
+    pub fn rotate_x(angle: T) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::rotate_x_pre(sin, cos)
+    }
+
+    pub fn rotate_y(angle: T) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::rotate_y_pre(sin, cos)
     }
 
-    pub fn rotate_z(angle: f32) -> Self {
-        use libm::F32Ext;
-        Mat4::rotate_z_pre(angle.sin(), angle.cos())
+    pub fn rotate_z(angle: T) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat4::rotate_z_pre(sin, cos)
+    }
+
+    /// A view matrix for a camera at `eye` looking toward `target`, with `up`
+    /// roughly upward on screen. As with `perspective`, the camera ends up
+    /// looking down -Z.
+    ///
+    /// `up` must not be parallel to the line of sight.
+    pub fn look_at(eye: Vec3<T>, target: Vec3<T>, up: Vec3<T>) -> Self {
+        fn normalize<T: Real>(v: Vec3<T>) -> Vec3<T> {
+            let len = v.dot(v).sqrt();
+            v.map(|c| c / len)
+        }
+        let f = normalize(target - eye);
+        let s = normalize(f.cross(up));
+        let u = s.cross(f);
+        Mat4(
+            Vec4(s.0, s.1, s.2, -s.dot(eye)),
+            Vec4(u.0, u.1, u.2, -u.dot(eye)),
+            Vec4(-f.0, -f.1, -f.2, f.dot(eye)),
+            Vec4(zero(), zero(), zero(), one()),
+        )
     }
 
     pub fn perspective(
-        left: f32,
-        top: f32,
-        right: f32,
-        bottom: f32,
-        near: f32,
-        far: f32,
+        left: T,
+        top: T,
+        right: T,
+        bottom: T,
+        near: T,
+        far: T,
     ) -> Self {
+        let two = T::one() + T::one();
         let width = right - left;
         let height = top - bottom;
         let depth = far - near;
         Mat4(
-            Vec4(2. * near / width, 0., (right + left) / width, 0.),
-            Vec4(0., 2. * near / height, (top + bottom) / height, 0.),
-            Vec4(0., 0., -(far + near) / depth, -2. * far * near / depth),
-            Vec4(0., 0., -1., 0.),
+            Vec4(two * near / width, zero(), (right + left) / width, zero()),
+            Vec4(zero(), two * near / height, (top + bottom) / height, zero()),
+            Vec4(
+                zero(),
+                zero(),
+                -(far + near) / depth,
+                -(two * far * near) / depth,
+            ),
+            Vec4(zero(), zero(), -T::one(), zero()),
         )
     }
 }
@@ -496,10 +727,43 @@ impl<T> Mat3<T> {
     }
 }
 
-impl Mat3<f32> {
-    pub fn rotate(a: f32) -> Self {
-        use libm::F32Ext;
-        Self::rotate_pre(a.clone().sin(), a.cos())
+impl<T> Mat3<T>
+where
+    T: Element
+        + Copy
+        + PartialEq
+        + core::ops::Sub<Output = T>
+        + core::ops::Div<Output = T>,
+{
+    pub fn determinant(self) -> T {
+        let Mat3(r0, r1, r2) = self;
+        r0.dot(r1.cross(r2))
+    }
+
+    /// Computes the inverse, or `None` if the matrix is singular.
+    pub fn inverse(self) -> Option<Self> {
+        let Mat3(r0, r1, r2) = self;
+        // The columns of the inverse are the cross products of pairs of rows,
+        // scaled by the determinant.
+        let c0 = r1.cross(r2);
+        let c1 = r2.cross(r0);
+        let c2 = r0.cross(r1);
+        let det = r0.dot(c0);
+        if det == zero() {
+            return None;
+        }
+        Some(Mat3(c0, c1, c2).transpose().map(|v| v / det))
+    }
+
+    fn map(self, mut f: impl FnMut(T) -> T) -> Self {
+        Mat3(self.0.map(&mut f), self.1.map(&mut f), self.2.map(&mut f))
+    }
+}
+
+impl<T: Real> Mat3<T> {
+    pub fn rotate(a: T) -> Self {
+        let (sin, cos) = a.sin_cos();
+        Self::rotate_pre(sin, cos)
     }
 }
 
@@ -624,3 +888,124 @@ pub type Vec3i = Vec3<i32>;
 pub type Mat3f = Mat3<f32>;
 /// Convenient shorthand for `Mat4<f32>`.
 pub type Mat4f = Mat4<f32>;
+/// Convenient shorthand for `Quat<f32>`.
+pub type Quatf = Quat<f32>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close4(a: Mat4f, b: Mat4f, tol: f32) -> bool {
+        let a = [a.0, a.1, a.2, a.3];
+        let b = [b.0, b.1, b.2, b.3];
+        a.iter().zip(b.iter()).all(|(r, s)| {
+            (r.0 - s.0).abs() <= tol
+                && (r.1 - s.1).abs() <= tol
+                && (r.2 - s.2).abs() <= tol
+                && (r.3 - s.3).abs() <= tol
+        })
+    }
+
+    fn apply(m: Mat4f, v: Vec3f) -> Vec3f {
+        (m * v.augment()).project()
+    }
+
+    fn sample() -> Mat4f {
+        Mat4f::translate(Vec3(3., -2., 7.))
+            * Mat4f::rotate_x(0.4)
+            * Mat4f::rotate_y(-1.1)
+            * Mat4f::scale(Vec3(2., 0.5, 3.))
+    }
+
+    #[test]
+    fn rotate_x_matches_y_and_z() {
+        // Rotating a quarter turn about each axis cycles the other two.
+        let m = Mat4f::rotate_x(core::f32::consts::FRAC_PI_2);
+        let v = apply(m, Vec3(0., 1., 0.));
+        assert!((v.2 - 1.).abs() < 1e-6 && v.1.abs() < 1e-6);
+        let v = apply(Mat4f::rotate_y(core::f32::consts::FRAC_PI_2), v);
+        assert!((v.0 - 1.).abs() < 1e-6 && v.2.abs() < 1e-6);
+        let v = apply(Mat4f::rotate_z(core::f32::consts::FRAC_PI_2), v);
+        assert!((v.1 - 1.).abs() < 1e-6 && v.0.abs() < 1e-6);
+    }
+
+    #[test]
+    fn determinant_and_inverse() {
+        let m = sample();
+        // Rotations and translations preserve volume; scaling multiplies it.
+        assert!((m.determinant() - 3.).abs() < 1e-5);
+        assert_eq!(Mat4f::identity().determinant(), 1.);
+
+        let inv = m.inverse().unwrap();
+        assert!(close4(m * inv, Mat4f::identity(), 1e-5));
+        assert!(close4(inv * m, Mat4f::identity(), 1e-5));
+        assert!(close4(inv.transpose().transpose(), inv, 0.));
+
+        let flat = Mat4f::scale(Vec3(1., 0., 1.));
+        assert_eq!(flat.determinant(), 0.);
+        assert!(flat.inverse().is_none());
+
+        let m3 = Mat3f::translate(2., 3.)
+            * Mat3f::rotate(0.6)
+            * Mat3f::scale(4., 0.5);
+        assert!((m3.determinant() - 2.).abs() < 1e-5);
+        let p = Vec3(5., -1., 1.);
+        let back = m3.inverse().unwrap() * (m3 * p);
+        assert!((back.0 - p.0).abs() < 1e-5 && (back.1 - p.1).abs() < 1e-5);
+        assert!(Mat3f::scale(0., 1.).inverse().is_none());
+    }
+
+    #[test]
+    fn inverse_in_fixed_point() {
+        let f = I16F16::from_f32;
+        let m = Mat4::translate(Vec3(f(3.), f(-2.), f(7.)))
+            * Mat4::rotate_y(f(0.8))
+            * Mat4::scale(Vec3(f(2.), f(2.), f(2.)));
+        let p = Vec3(f(1.5), f(-4.), f(0.25));
+        let back = m.inverse().unwrap() * (m * p.augment());
+        for (a, b) in [(back.0, p.0), (back.1, p.1), (back.2, p.2)].iter() {
+            assert!((a.to_f32() - b.to_f32()).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let m = sample();
+        let n = m.normal_matrix().unwrap();
+        // A surface spanned by two tangents, and its normal.
+        let (t0, t1) = (Vec3(1., 2., 0.), Vec3(0., -1., 3.));
+        let normal = t0.cross(t1);
+        let origin = apply(m, Vec3(0., 0., 0.));
+        let t0 = apply(m, t0) - origin;
+        let t1 = apply(m, t1) - origin;
+        let normal = n * normal;
+        assert!(normal.dot(t0).abs() < 1e-4);
+        assert!(normal.dot(t1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ortho_maps_box_to_cube() {
+        let m = Mat4f::ortho(-4., 3., 4., -3., 1., 11.);
+        let a = apply(m, Vec3(-4., -3., -1.));
+        let b = apply(m, Vec3(4., 3., -11.));
+        assert_eq!((a.0, a.1, a.2), (-1., -1., -1.));
+        assert_eq!((b.0, b.1, b.2), (1., 1., 1.));
+    }
+
+    #[test]
+    fn look_at_puts_target_ahead() {
+        let eye = Vec3(4., 3., 10.);
+        let target = Vec3(1., -2., 0.);
+        let m = Mat4f::look_at(eye, target, Vec3(0., 1., 0.));
+        // The eye lands at the origin, and the target straight down -Z.
+        let e = apply(m, eye);
+        assert!(e.0.abs() < 1e-5 && e.1.abs() < 1e-5 && e.2.abs() < 1e-5);
+        let t = apply(m, target);
+        let dist = (target - eye).dot(target - eye).sqrt();
+        assert!(t.0.abs() < 1e-5 && t.1.abs() < 1e-5);
+        assert!((t.2 + dist).abs() < 1e-4);
+        // Up stays up.
+        assert!(apply(m, Vec3(4., 4., 10.)).1 > 0.);
+        assert!((m.determinant() - 1.).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,373 @@
+//! Gradient noise, for procedural textures and effects.
+//!
+//! `Noise` implements Ken Perlin's "improved" gradient noise in two and three
+//! dimensions, plus fractal Brownian motion (several octaves of noise summed
+//! at doubling frequencies), which is the usual starting point for plasma,
+//! clouds, fire, and terrain.
+//!
+//! Everything is generic over `Lattice`, which is implemented for `f32` and
+//! `I16F16`. The fixed-point versions use only integer arithmetic.
+//!
+//! Results depend only on the seed and the inputs: the permutation is shuffled
+//! with a generator defined here, rather than one from `rand` whose algorithm
+//! may vary with the target or the crate version, so a given seed produces
+//! the same texture on the host and on the device.
+
+use crate::{Real, I16F16};
+
+/// Scalars that noise can be computed in.
+pub trait Lattice: Real {
+    /// Splits a coordinate into the integer lattice cell containing it and
+    /// the position within that cell, from 0 (inclusive) to 1 (exclusive).
+    fn floor_frac(self) -> (i32, Self);
+
+    /// Converts a small integer exactly.
+    fn from_i32(v: i32) -> Self;
+}
+
+impl Lattice for f32 {
+    fn floor_frac(self) -> (i32, Self) {
+        if self.abs() >= 16_777_216. {
+            // Beyond 2^24, every `f32` is an integer, but perhaps not one that
+            // fits in an `i32`. The cell only feeds the hash, so wrap it.
+            return (self as i64 as i32, 0.);
+        }
+        // `as` truncates toward zero; step negative values down to the floor.
+        let mut cell = self as i32;
+        if (cell as f32) > self {
+            cell -= 1;
+        }
+        (cell, self - cell as f32)
+    }
+
+    fn from_i32(v: i32) -> Self {
+        v as f32
+    }
+}
+
+impl Lattice for I16F16 {
+    fn floor_frac(self) -> (i32, Self) {
+        let bits = self.to_bits();
+        (bits >> 16, I16F16::from_bits(bits & 0xFFFF))
+    }
+
+    fn from_i32(v: i32) -> Self {
+        I16F16::from_int(v as i16)
+    }
+}
+
+/// A seeded gradient noise generator.
+#[derive(Clone)]
+pub struct Noise {
+    /// A permutation of 0..=255, used to hash lattice points.
+    perm: [u8; 256],
+}
+
+impl Noise {
+    /// Makes a generator from a seed. Equal seeds give identical noise.
+    pub fn seed_from_u64(seed: u64) -> Self {
+        let mut perm = [0; 256];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = i as u8;
+        }
+        // Fisher-Yates shuffle, driven by SplitMix64.
+        let mut state = seed;
+        for i in (1..perm.len()).rev() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            let j = ((z >> 32) * (i as u64 + 1)) >> 32;
+            perm.swap(i, j as usize);
+        }
+        Noise { perm }
+    }
+
+    fn hash(&self, h: usize, i: i32) -> usize {
+        usize::from(self.perm[(h as i32).wrapping_add(i) as usize & 0xFF])
+    }
+
+    /// Computes two-dimensional gradient noise at `(x, y)`.
+    ///
+    /// The result is zero at integer coordinates, varies smoothly in between,
+    /// and stays within -1 to 1.
+    pub fn gradient2<T: Lattice>(&self, x: T, y: T) -> T {
+        let (xi, xf) = x.floor_frac();
+        let (yi, yf) = y.floor_frac();
+
+        let corner = |dx: i32, dy: i32| {
+            let h = self.hash(0, xi.wrapping_add(dx));
+            let h = self.hash(h, yi.wrapping_add(dy));
+            let (x, y) = (xf - T::from_i32(dx), yf - T::from_i32(dy));
+            grad2(h, x, y)
+        };
+        let (u, v) = (fade(xf), fade(yf));
+        let bottom = lerp(u, corner(0, 0), corner(1, 0));
+        let top = lerp(u, corner(0, 1), corner(1, 1));
+        lerp(v, bottom, top)
+    }
+
+    /// Computes three-dimensional gradient noise at `(x, y, z)`.
+    ///
+    /// The result is zero at integer coordinates and varies smoothly in
+    /// between. Unlike `gradient2`, it can stray slightly outside -1 to 1: the
+    /// extremes are about ±1.0364, so it stays within -1.04 to 1.04. Clamp it
+    /// if that matters, e.g. when indexing a table.
+    pub fn gradient3<T: Lattice>(&self, x: T, y: T, z: T) -> T {
+        let (xi, xf) = x.floor_frac();
+        let (yi, yf) = y.floor_frac();
+        let (zi, zf) = z.floor_frac();
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let h = self.hash(0, xi.wrapping_add(dx));
+            let h = self.hash(h, yi.wrapping_add(dy));
+            let h = self.hash(h, zi.wrapping_add(dz));
+            let p = (
+                xf - T::from_i32(dx),
+                yf - T::from_i32(dy),
+                zf - T::from_i32(dz),
+            );
+            grad3(h, p.0, p.1, p.2)
+        };
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+        let plane = |dz| {
+            let bottom = lerp(u, corner(0, 0, dz), corner(1, 0, dz));
+            let top = lerp(u, corner(0, 1, dz), corner(1, 1, dz));
+            lerp(v, bottom, top)
+        };
+        lerp(w, plane(0), plane(1))
+    }
+
+    /// Computes two-dimensional fractal Brownian motion: `octaves` layers of
+    /// `gradient2`, each at twice the frequency and half the amplitude of the
+    /// last. The sum is normalized to stay within -1 to 1.
+    ///
+    /// For fixed-point inputs, the highest octave samples at
+    /// `2^(octaves - 1)` times the input coordinates, which must fit.
+    pub fn fbm2<T: Lattice>(&self, x: T, y: T, octaves: u32) -> T {
+        self.fbm(octaves, |f| self.gradient2(x * f, y * f))
+    }
+
+    /// Computes three-dimensional fractal Brownian motion, like `fbm2`. The sum
+    /// is normalized the same way, but inherits the range of `gradient3`, and
+    /// so stays within -1.04 to 1.04.
+    pub fn fbm3<T: Lattice>(&self, x: T, y: T, z: T, octaves: u32) -> T {
+        self.fbm(octaves, |f| self.gradient3(x * f, y * f, z * f))
+    }
+
+    fn fbm<T: Lattice>(&self, octaves: u32, sample: impl Fn(T) -> T) -> T {
+        let two = T::from_i32(2);
+        let mut sum = T::zero();
+        let mut total = T::zero();
+        let mut amplitude = T::one();
+        let mut frequency = T::one();
+        for _ in 0..octaves {
+            sum = sum + sample(frequency) * amplitude;
+            total = total + amplitude;
+            amplitude = amplitude / two;
+            frequency = frequency * two;
+        }
+        if octaves == 0 {
+            sum
+        } else {
+            sum / total
+        }
+    }
+}
+
+/// Perlin's quintic smoothstep, `6t^5 - 15t^4 + 10t^3`.
+fn fade<T: Lattice>(t: T) -> T {
+    let (six, fifteen, ten) =
+        (T::from_i32(6), T::from_i32(15), T::from_i32(10));
+    t * t * t * (t * (t * six - fifteen) + ten)
+}
+
+fn lerp<T: Lattice>(t: T, a: T, b: T) -> T {
+    a + (b - a) * t
+}
+
+/// Dots `(x, y)` with one of eight gradients, chosen by `h`: the four axes
+/// and the four diagonals.
+fn grad2<T: Lattice>(h: usize, x: T, y: T) -> T {
+    match h & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+/// Dots `(x, y, z)` with one of the twelve cube-edge gradients from Perlin's
+/// improved noise, chosen by `h`. The four extra cases repeat existing
+/// gradients, so that 16 hash values map evenly.
+fn grad3<T: Lattice>(h: usize, x: T, y: T, z: T) -> T {
+    let h = h & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fx(v: f32) -> I16F16 {
+        I16F16::from_f32(v)
+    }
+
+    #[test]
+    fn permutation_is_a_permutation() {
+        for &seed in &[0, 1, 11181981, u64::MAX] {
+            let noise = Noise::seed_from_u64(seed);
+            let mut seen = [false; 256];
+            for &p in noise.perm.iter() {
+                assert!(!seen[usize::from(p)]);
+                seen[usize::from(p)] = true;
+            }
+        }
+        let a = Noise::seed_from_u64(1).perm;
+        let b = Noise::seed_from_u64(2).perm;
+        assert!(a.iter().zip(b.iter()).any(|(a, b)| a != b));
+    }
+
+    #[test]
+    fn floor_frac() {
+        assert_eq!(2.75f32.floor_frac(), (2, 0.75));
+        assert_eq!((-2.25f32).floor_frac(), (-3, 0.75));
+        assert_eq!((-3f32).floor_frac(), (-3, 0.));
+        assert_eq!(fx(-2.25).floor_frac(), (-3, fx(0.75)));
+        assert_eq!(3e9f32.floor_frac().1, 0.);
+        assert_eq!((-3e9f32).floor_frac().1, 0.);
+    }
+
+    /// Lattice cells wrap around, rather than overflowing, far from the
+    /// origin.
+    #[test]
+    fn huge_coordinates() {
+        let noise = Noise::seed_from_u64(1);
+        for &far in &[3e9, -3e9, 2_147_483_520., 1e30] {
+            assert!(noise.gradient2(0.5, far).abs() <= 1.);
+            assert!(noise.gradient2(far, 0.5).abs() <= 1.);
+            assert!(noise.gradient3(0.5, 0.5, far).abs() <= 1.);
+            assert!(noise.gradient3(far, far, far).abs() <= 1.);
+        }
+    }
+
+    /// Checks the properties that effects rely on, over a grid that crosses
+    /// the origin and cell boundaries.
+    #[test]
+    fn range_and_lattice_zeros() {
+        let noise = Noise::seed_from_u64(11181981);
+        for i in -100..100 {
+            for j in -100..100 {
+                let (x, y) = (i as f32 * 0.173, j as f32 * 0.131);
+                let n2 = noise.gradient2(x, y);
+                let n3 = noise.gradient3(x, y, x - y);
+                assert!(n2.abs() <= 1. && n3.abs() <= 1., "{} {}", x, y);
+                let f = noise.fbm2(x, y, 5);
+                assert!(f.abs() <= 1.);
+            }
+        }
+        for i in -5..5 {
+            assert_eq!(noise.gradient2(i as f32, 3.), 0.);
+            assert_eq!(noise.gradient3(2., i as f32, -7.), 0.);
+        }
+    }
+
+    #[test]
+    fn smooth() {
+        let noise = Noise::seed_from_u64(3);
+        let step = 1e-3;
+        for i in 0..2000 {
+            let x = i as f32 * 0.0127 - 10.;
+            let a = noise.gradient2(x, 0.4);
+            let b = noise.gradient2(x + step, 0.4);
+            // The gradient is bounded, so nearby samples must be close.
+            assert!((a - b).abs() < 10. * step, "{}", x);
+        }
+    }
+
+    #[test]
+    fn fbm_octaves() {
+        let noise = Noise::seed_from_u64(5);
+        assert_eq!(noise.fbm2(0.3f32, 0.7, 1), noise.gradient2(0.3, 0.7));
+        assert_eq!(
+            noise.fbm3(0.3f32, 0.7, 0.1, 1),
+            noise.gradient3(0.3, 0.7, 0.1)
+        );
+        assert_eq!(noise.fbm2(0.3f32, 0.7, 0), 0.);
+    }
+
+    #[test]
+    fn fixed_tracks_float() {
+        let noise = Noise::seed_from_u64(11181981);
+        for i in -50..50 {
+            let (x, y, z) = (i as f32 * 0.377, i as f32 * -0.211, 1.9);
+            let f = noise.gradient3(x, y, z);
+            let q = noise.gradient3(fx(x), fx(y), fx(z)).to_f32();
+            assert!((f - q).abs() < 1e-3, "{} vs {}", f, q);
+            let f = noise.fbm2(x, y, 4);
+            let q = noise.fbm2(fx(x), fx(y), 4).to_f32();
+            assert!((f - q).abs() < 1e-3, "{} vs {}", f, q);
+        }
+    }
+
+    /// Three-dimensional noise can exceed 1, but not by much.
+    #[test]
+    fn gradient3_bound() {
+        let noise = Noise::seed_from_u64(0);
+        let (x, y, z) = (1.4772441f32, 2.4772317, 4.4999094);
+        let n = noise.gradient3(x, y, z);
+        assert!(n < -1. && n > -1.04, "{}", n);
+        assert_eq!(noise.fbm3(x, y, z, 1), n);
+        assert!(noise.fbm3(x, y, z, 4).abs() <= 1.04);
+
+        let q = noise.gradient3(fx(x), fx(y), fx(z)).to_f32();
+        assert!(q < -1. && q > -1.04, "{}", q);
+
+        let noise = Noise::seed_from_u64(11181981);
+        for i in -100..100 {
+            for j in -100..100 {
+                let (x, y) = (i as f32 * 0.173, j as f32 * 0.131);
+                assert!(noise.fbm3(x, y, x + y, 3).abs() <= 1.04);
+            }
+        }
+    }
+
+    /// Golden values. If these change, every texture generated from noise
+    /// changes too, so this should only happen on purpose.
+    #[test]
+    fn repeatable() {
+        let noise = Noise::seed_from_u64(11181981);
+        let floats = [
+            noise.gradient2(1.3f32, -2.7).to_bits(),
+            noise.gradient3(0.5f32, 10.25, -3.125).to_bits(),
+            noise.fbm2(4.2f32, 0.9, 6).to_bits(),
+            noise.fbm3(-1.1f32, 2.2, 3.3, 4).to_bits(),
+        ];
+        let fixed = [
+            noise.gradient2(fx(1.3), fx(-2.7)).to_bits(),
+            noise.gradient3(fx(0.5), fx(10.25), fx(-3.125)).to_bits(),
+            noise.fbm2(fx(4.2), fx(0.9), 6).to_bits(),
+            noise.fbm3(fx(-1.1), fx(2.2), fx(3.3), 4).to_bits(),
+        ];
+        assert_eq!(
+            floats,
+            [0x3e40_4f11, 0xbe8a_16ce, 0xbca5_c88c, 0xbd9a_bd34]
+        );
+        assert_eq!(fixed, [12309, -17675, -1326, -4952]);
+    }
+}
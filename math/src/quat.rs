@@ -0,0 +1,274 @@
+//! Quaternions, for representing and interpolating rotations.
+//!
+//! Composing rotation matrices by repeated multiplication slowly loses
+//! orthonormality to rounding, which shows up as a model that shears or
+//! shrinks over a long animation. A unit quaternion has only four numbers to
+//! drift, and `normalize` puts it right again cheaply; convert to a matrix
+//! once per frame with `to_mat4` or `to_mat3`.
+
+use num_traits::{one, zero};
+
+use crate::{Mat3, Mat4, Real, Vec3, Vec4};
+
+/// A quaternion `w + xi + yj + zk`. Rotations are unit quaternions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quat<T> {
+    pub w: T,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+impl<T: Real> Quat<T> {
+    pub fn new(w: T, x: T, y: T, z: T) -> Self {
+        Quat { w, x, y, z }
+    }
+
+    /// The rotation that does nothing.
+    pub fn identity() -> Self {
+        Quat::new(one(), zero(), zero(), zero())
+    }
+
+    /// Rotation by `angle` radians around `axis`, which must be unit length.
+    /// The sense of rotation matches `Mat4::rotate_y` and friends.
+    pub fn from_axis_angle(axis: Vec3<T>, angle: T) -> Self {
+        let half = angle / (T::one() + T::one());
+        let (sin, cos) = half.sin_cos();
+        Quat::new(cos, axis.0 * sin, axis.1 * sin, axis.2 * sin)
+    }
+
+    pub fn rotate_x(angle: T) -> Self {
+        Quat::from_axis_angle(Vec3(one(), zero(), zero()), angle)
+    }
+
+    pub fn rotate_y(angle: T) -> Self {
+        Quat::from_axis_angle(Vec3(zero(), one(), zero()), angle)
+    }
+
+    pub fn rotate_z(angle: T) -> Self {
+        Quat::from_axis_angle(Vec3(zero(), zero(), one()), angle)
+    }
+
+    /// The conjugate, which for a unit quaternion is the inverse rotation.
+    pub fn conjugate(self) -> Self {
+        Quat::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn dot(self, other: Self) -> T {
+        self.w * other.w
+            + self.x * other.x
+            + self.y * other.y
+            + self.z * other.z
+    }
+
+    /// Rescales to unit length. The zero quaternion is returned unchanged.
+    pub fn normalize(self) -> Self {
+        let len = self.dot(self).sqrt();
+        if len == zero() {
+            self
+        } else {
+            self.scale(T::one() / len)
+        }
+    }
+
+    /// Rotates `v` by this quaternion, which must be unit length.
+    pub fn rotate(self, v: Vec3<T>) -> Vec3<T> {
+        // v' = v + 2w(u × v) + 2u × (u × v), with u the vector part.
+        let two = T::one() + T::one();
+        let u = Vec3(self.x, self.y, self.z);
+        let t = u.cross(v).map(|c| c * two);
+        let ut = u.cross(t);
+        Vec3(
+            v.0 + self.w * t.0 + ut.0,
+            v.1 + self.w * t.1 + ut.1,
+            v.2 + self.w * t.2 + ut.2,
+        )
+    }
+
+    /// Converts to a rotation matrix. Assumes unit length.
+    pub fn to_mat3(self) -> Mat3<T> {
+        let [r0, r1, r2] = self.rows();
+        Mat3(r0, r1, r2)
+    }
+
+    /// Converts to a homogeneous rotation matrix. Assumes unit length.
+    pub fn to_mat4(self) -> Mat4<T> {
+        let [r0, r1, r2] = self.rows();
+        Mat4(
+            Vec4(r0.0, r0.1, r0.2, zero()),
+            Vec4(r1.0, r1.1, r1.2, zero()),
+            Vec4(r2.0, r2.1, r2.2, zero()),
+            Vec4(zero(), zero(), zero(), one()),
+        )
+    }
+
+    /// Normalized linear interpolation from `self` (at `t = 0`) to `other`
+    /// (at `t = 1`), along the shorter arc. Cheaper than `slerp`, but the
+    /// angular speed isn't constant across `t`.
+    pub fn nlerp(self, other: Self, t: T) -> Self {
+        let other = if self.dot(other) < zero() {
+            other.scale(-T::one())
+        } else {
+            other
+        };
+        let s = T::one() - t;
+        Quat::new(
+            self.w * s + other.w * t,
+            self.x * s + other.x * t,
+            self.y * s + other.y * t,
+            self.z * s + other.z * t,
+        )
+        .normalize()
+    }
+
+    /// Spherical linear interpolation from `self` (at `t = 0`) to `other`
+    /// (at `t = 1`), along the shorter arc at constant angular speed. Both
+    /// must be unit length.
+    pub fn slerp(self, other: Self, t: T) -> Self {
+        let mut d = self.dot(other);
+        let other = if d < zero() {
+            d = -d;
+            other.scale(-T::one())
+        } else {
+            other
+        };
+        // When the two are very close, sin(theta) is too small to divide by,
+        // and a straight line is indistinguishable from the arc anyway.
+        if d > T::from_f32(0.9995) {
+            return self.nlerp(other, t);
+        }
+        let sin_theta = (T::one() - d * d).sqrt();
+        let theta = sin_theta.atan2(d);
+        let a = ((T::one() - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+        Quat::new(
+            self.w * a + other.w * b,
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+        )
+    }
+
+    fn scale(self, k: T) -> Self {
+        Quat::new(self.w * k, self.x * k, self.y * k, self.z * k)
+    }
+
+    fn rows(self) -> [Vec3<T>; 3] {
+        let two = T::one() + T::one();
+        let Quat { w, x, y, z } = self;
+        let (xx, yy, zz) = (x * x * two, y * y * two, z * z * two);
+        let (xy, xz, yz) = (x * y * two, x * z * two, y * z * two);
+        let (wx, wy, wz) = (w * x * two, w * y * two, w * z * two);
+        [
+            Vec3(T::one() - yy - zz, xy - wz, xz + wy),
+            Vec3(xy + wz, T::one() - xx - zz, yz - wx),
+            Vec3(xz - wy, yz + wx, T::one() - xx - yy),
+        ]
+    }
+}
+
+/// Composition: `a * b` rotates by `b`, then by `a`, like the corresponding
+/// matrix product.
+impl<T: Real> core::ops::Mul for Quat<T> {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        Quat::new(
+            self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Augment, Project, Quatf, Vec3f};
+
+    fn close(a: Vec3f, b: Vec3f, tol: f32) -> bool {
+        (a.0 - b.0).abs() <= tol
+            && (a.1 - b.1).abs() <= tol
+            && (a.2 - b.2).abs() <= tol
+    }
+
+    fn apply(m: Mat4<f32>, v: Vec3f) -> Vec3f {
+        (m * v.augment()).project()
+    }
+
+    #[test]
+    fn matches_matrices() {
+        let points = [Vec3(1., 2., 3.), Vec3(-4., 0.5, -2.), Vec3(0., 0., 1.)];
+        for i in 0..40 {
+            let a = i as f32 * 0.17 - 3.;
+            let b = i as f32 * -0.23 + 1.;
+            let q = Quatf::rotate_y(a) * Quatf::rotate_z(b);
+            let m = Mat4::rotate_y(a) * Mat4::rotate_z(b);
+            for &p in &points {
+                let expected = apply(m, p);
+                assert!(close(apply(q.to_mat4(), p), expected, 1e-5));
+                assert!(close(q.to_mat3() * p, expected, 1e-5));
+                assert!(close(q.rotate(p), expected, 1e-5));
+                assert!(close(q.conjugate().rotate(expected), p, 1e-5));
+            }
+        }
+        let id = Quatf::identity().to_mat4();
+        assert!(close(apply(id, Vec3(1., 2., 3.)), Vec3(1., 2., 3.), 0.));
+    }
+
+    /// Many thousands of small steps, renormalized as we go, should still
+    /// produce a rigid rotation.
+    #[test]
+    fn accumulation_stays_rigid() {
+        let step = Quatf::rotate_y(0.02) * Quatf::rotate_z(0.01);
+        let mut q = Quatf::identity();
+        for _ in 0..20_000 {
+            q = (q * step).normalize();
+        }
+        let m = q.to_mat3();
+        for (r, s) in [(m.0, m.1), (m.1, m.2), (m.2, m.0)].iter() {
+            assert!((r.0 * r.0 + r.1 * r.1 + r.2 * r.2 - 1.).abs() < 1e-5);
+            assert!((r.0 * s.0 + r.1 * s.1 + r.2 * s.2).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let a = Quatf::rotate_y(0.3);
+        let b = Quatf::rotate_y(1.9);
+        for i in 0..=10 {
+            let t = i as f32 / 10.;
+            let expected = Quatf::rotate_y(0.3 + 1.6 * t);
+            let s = a.slerp(b, t);
+            assert!((s.dot(expected) - 1.).abs() < 1e-5, "slerp at {}", t);
+            // nlerp follows the same path, at a different pace.
+            let n = a.nlerp(b, t);
+            assert!((n.dot(n) - 1.).abs() < 1e-5);
+            assert!(n.x.abs() < 1e-6 && n.z.abs() < 1e-6);
+        }
+        // Interpolation takes the short way around, even when the quaternions
+        // have opposite signs.
+        let far = Quatf::rotate_y(6.0);
+        let mid = a.slerp(far, 0.5);
+        let expected =
+            Quatf::rotate_y((0.3 + 6.0 - core::f32::consts::PI * 2.) / 2.);
+        assert!(mid.dot(expected).abs() > 1. - 1e-5);
+        // Nearly identical inputs fall back to nlerp rather than dividing by
+        // zero.
+        assert_eq!(a.slerp(a, 0.5), a.nlerp(a, 0.5));
+    }
+
+    #[test]
+    fn fixed_point() {
+        use crate::I16F16;
+        let f = I16F16::from_f32;
+        let q = Quat::rotate_y(f(0.7)) * Quat::rotate_z(f(-0.4));
+        let m = Mat4::rotate_y(0.7) * Mat4::rotate_z(-0.4);
+        let p = Vec3(f(2.), f(-1.), f(0.5));
+        let r = q.rotate(p).map(I16F16::to_f32);
+        assert!(close(r, apply(m, Vec3(2., -1., 0.5)), 1e-3));
+        let s = Quat::rotate_y(f(0.2)).slerp(Quat::rotate_y(f(1.2)), f(0.5));
+        assert!((s.y.to_f32() - 0.35f32.sin()).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,692 @@
+//! Scanline polygon rasterizer for racing the beam.
+//!
+//! There's no room for a framebuffer at high resolutions, so solid 3D has to
+//! be drawn one scanline at a time, in the raster callback, as the beam comes
+//! down the screen. This crate does the bookkeeping for that. Between frames,
+//! `Raster::reset` breaks each triangle into trapezoids with flat tops and
+//! bottoms, and sorts them by starting scanline. Then, for each scanline,
+//! `Raster::step` reports the span of pixels covered by each trapezoid that
+//! crosses it.
+//!
+//! Edges are sub-pixel correct. Vertices are snapped to 1/16 pixel, and a
+//! pixel is covered if its center is inside the triangle. Centers that fall
+//! exactly on an edge are covered if the edge is on the left or top of the
+//! triangle, so that triangles sharing an edge cover every pixel along it
+//! exactly once. Edges are stepped from one scanline to the next with integer
+//! arithmetic, so there's no floating point in the raster callback, and no
+//! accumulated error.
+//!
+//! Everything is clipped to a rectangle given by `Clip`.
+//!
+//! The number of trapezoids that can be in flight is a type parameter, since
+//! the state for each one has to be allocated up front. Each triangle needs one
+//! or two.
+
+#![cfg_attr(not(test), no_std)]
+
+use core::cmp::{max, min};
+use core::ops::Range;
+
+use math::{Vec3, Vec3f};
+
+/// Number of sub-pixel steps per pixel.
+const ONE: i32 = 16;
+/// Offset from the corner of a pixel to its center.
+const HALF: i32 = ONE / 2;
+/// Limit on the magnitude of fixed-point coordinates. Vertices further out are
+/// clamped. This keeps intermediate products of edge setup within an `i64`,
+/// and the stepping values within an `i32`.
+const LIMIT: i32 = 1 << 24;
+
+/// Description of a triangle relative to a vertex buffer.
+#[derive(Copy, Clone, Debug)]
+pub struct Tri {
+    pub vertex_indices: [usize; 3],
+    pub normal_index: usize,
+    pub color: u8,
+}
+
+/// Bounds of the area being drawn, in pixels. Spans are clipped to
+/// `left..right`, and nothing is drawn on scanlines outside `top..bottom`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Clip {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
+impl Clip {
+    /// Makes a clip rectangle covering a whole screen of the given size.
+    pub const fn new(width: usize, height: usize) -> Self {
+        Clip {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        }
+    }
+}
+
+/// Error returned from `Raster::reset` when there isn't room for all the
+/// triangles. The triangles that did fit will still be drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RasterFull;
+
+/// A point in fixed-point screen coordinates, with `ONE` units per pixel.
+type Point = (i32, i32);
+
+/// Converts a screen-space coordinate to fixed point, rounding to the nearest
+/// sub-pixel step.
+fn to_fixed(v: f32) -> i32 {
+    // `as` saturates, and maps NaN to zero, so this is safe for any input.
+    // But it truncates toward zero, where we want to round down.
+    let f = v * ONE as f32 + 0.5;
+    let i = f as i32;
+    let i = if i as f32 > f { i - 1 } else { i };
+    i.clamp(-LIMIT, LIMIT)
+}
+
+/// Finds the first pixel row (or column) whose center is at or past `v`.
+fn first_pixel(v: i32) -> i32 {
+    div_ceil(i64::from(v - HALF), i64::from(ONE)) as i32
+}
+
+fn div_ceil(n: i64, d: i64) -> i64 {
+    -(-n).div_euclid(d)
+}
+
+/// Twice the signed area of triangle `abc`. Positive if the vertices run
+/// clockwise on the screen (with Y increasing downward).
+fn cross(a: Point, b: Point, c: Point) -> i64 {
+    i64::from(b.0 - a.0) * i64::from(c.1 - a.1)
+        - i64::from(b.1 - a.1) * i64::from(c.0 - a.0)
+}
+
+/// Incremental evaluation of a non-horizontal edge.
+///
+/// On each scanline, `x` is the first pixel whose center is at or to the right
+/// of the edge. Pixels from the left edge's `x` up to (but not including) the
+/// right edge's `x` are inside the trapezoid.
+///
+/// Moving down a scanline moves the edge by a rational number of pixels; this
+/// is tracked exactly, as an integer part `step` and a remainder `err_step`
+/// out of `denom`, in the manner of Bresenham's algorithm.
+#[derive(Copy, Clone, Debug)]
+struct Edge {
+    x: i32,
+    /// Distance from the edge to the center of pixel `x`, in units of
+    /// `1 / denom` pixels. Always in `0..denom`.
+    err: i32,
+    step: i32,
+    err_step: i32,
+    denom: i32,
+}
+
+impl Edge {
+    const fn new() -> Self {
+        Edge {
+            x: 0,
+            err: 0,
+            step: 0,
+            err_step: 0,
+            denom: 1,
+        }
+    }
+
+    /// Makes an edge from `p0` to `p1`, which must be further down, and
+    /// positions it at scanline `y`.
+    fn between(p0: Point, p1: Point, y: i32) -> Self {
+        let dx = i64::from(p1.0 - p0.0);
+        let dy = i64::from(p1.1 - p0.1);
+        debug_assert!(dy > 0);
+
+        // The edge crosses the center of scanline `y` at
+        //
+        //     p0.0 + (center - p0.1) * dx / dy
+        //
+        // and we want the first pixel center at or right of that. Work in
+        // units of 1/denom pixel to keep everything integral.
+        let denom = i64::from(ONE) * dy;
+        let center = i64::from(y) * i64::from(ONE) + i64::from(HALF);
+        let n = i64::from(p0.0 - HALF) * dy + (center - i64::from(p0.1)) * dx;
+        let x = div_ceil(n, denom);
+
+        let per_line = i64::from(ONE) * dx;
+        let step = per_line.div_euclid(denom);
+
+        Edge {
+            x: x as i32,
+            err: (x * denom - n) as i32,
+            step: step as i32,
+            err_step: (per_line - step * denom) as i32,
+            denom: denom as i32,
+        }
+    }
+
+    /// Moves the edge down one scanline.
+    fn advance(&mut self) {
+        self.x += self.step;
+        self.err -= self.err_step;
+        if self.err < 0 {
+            self.x += 1;
+            self.err += self.denom;
+        }
+    }
+}
+
+/// State machine for drawing a triangle.
+///
+/// The triangle is defined in screen-space by its left and right edges, top,
+/// and height. In practice, this means it's actually a trapezoid, but in
+/// practice the edges will intersect at the top or bottom.
+///
+/// ```text
+/// top_y --->  -------------           \
+///       left /              ` right   | height
+///      edge /                 `  edge |
+///          `--------------------`     /
+/// ```
+///
+/// Either the top or bottom of the triangle is an axis-aligned edge. Real
+/// triangles don't necessarily sit neatly on a scanline like this; a triangle
+/// like the following requires *two* state machines to render:
+///
+/// ```text
+///    |\
+///    | \
+///    |  \
+///   - - - - split here
+///    |  /
+///    | /
+///    |/
+/// ```
+#[derive(Copy, Clone, Debug)]
+struct TriState {
+    /// Top scanline included in the triangle, after clipping.
+    top_y: usize,
+    /// Last scanline included in this triangle, after clipping. May equal
+    /// `top_y`.
+    last_y: usize,
+    /// Scanline the edges are currently positioned at.
+    edge_y: usize,
+    /// The triangle's left edge.
+    left: Edge,
+    /// The triangle's right edge.
+    right: Edge,
+    /// Color of triangle.
+    color: u8,
+    /// Normal vector of triangle.
+    normal: Vec3f,
+}
+
+impl TriState {
+    const fn new() -> Self {
+        TriState {
+            top_y: 0,
+            last_y: 0,
+            edge_y: 0,
+            left: Edge::new(),
+            right: Edge::new(),
+            color: 0,
+            normal: Vec3(0., 0., 0.),
+        }
+    }
+
+    /// Finds the span covered on `scanline`, which must not be above any
+    /// scanline previously evaluated, and moves the edges down to the next.
+    fn evaluate(&mut self, scanline: usize) -> Range<i32> {
+        while self.edge_y < scanline {
+            self.left.advance();
+            self.right.advance();
+            self.edge_y += 1;
+        }
+        let span = self.left.x..self.right.x;
+        self.left.advance();
+        self.right.advance();
+        self.edge_y += 1;
+        span
+    }
+}
+
+/// Index into a `Raster`'s array of `N` states.
+#[derive(Copy, Clone, Debug)]
+struct StateIndex<const N: usize>(u16);
+
+impl<const N: usize> StateIndex<N> {
+    fn checked(idx: usize) -> Option<Self> {
+        if idx < N {
+            Some(StateIndex(idx as u16))
+        } else {
+            None
+        }
+    }
+
+    fn index<T>(self, array: &[T; N]) -> &T {
+        // Safety: StateIndex is guaranteed in range.
+        unsafe { array.get_unchecked(self.0 as usize) }
+    }
+
+    fn index_mut<T>(self, array: &mut [T; N]) -> &mut T {
+        // Safety: StateIndex is guaranteed in range.
+        unsafe { array.get_unchecked_mut(self.0 as usize) }
+    }
+}
+
+/// Rasterizer with room for `N` trapezoid state machines.
+#[derive(Clone)]
+pub struct Raster<const N: usize> {
+    /// Area to draw in.
+    clip: Clip,
+    /// A triangle state machine for each camera-facing triangle in this frame.
+    ///
+    /// Note: this always contains an entry for each potential state, but some
+    /// may contain garbage. Only the entries indexed by the index arrays are
+    /// guaranteed valid.
+    tris: [TriState; N],
+    /// Indices of pending triangles, sorted by descending Y.
+    ///
+    /// To find starting triangles, inspect `last` and `pop` it while it refers
+    /// to triangles that start on this scanline.
+    ///
+    /// Invariant: each index in this vector must be unique.
+    pending: StateVec<N>,
+    /// Indices of active triangles in no particular order.
+    ///
+    /// Invariant: each index in this vector must be unique.
+    active: StateVec<N>,
+}
+
+impl<const N: usize> Raster<N> {
+    pub const fn new(clip: Clip) -> Self {
+        assert!(N <= u16::MAX as usize + 1);
+        Raster {
+            clip,
+            tris: [TriState::new(); N],
+            pending: StateVec::new(),
+            active: StateVec::new(),
+        }
+    }
+
+    /// Changes the clip rectangle. This takes effect at the next `reset`.
+    pub fn set_clip(&mut self, clip: Clip) {
+        self.clip = clip
+    }
+
+    /// Resets the raster context and prepares to render triangle state machines
+    /// for the triangles described by the index buffer `tris` and vertex buffer
+    /// `vertices`.
+    ///
+    /// Vertices are in screen space, in pixels, with Y increasing downward;
+    /// their Z coordinates are ignored. Only triangles whose vertices run
+    /// clockwise on the screen are drawn. The others are assumed to be facing
+    /// away from the camera.
+    ///
+    /// The normal vector for each triangle is looked up in `normals`, and
+    /// passed through to `step`.
+    ///
+    /// If there are more trapezoids than the rasterizer has room for, the
+    /// triangles that don't fit are skipped, and this returns `RasterFull`.
+    pub fn reset(
+        &mut self,
+        tris: &[Tri],
+        vertices: &[Vec3f],
+        normals: &[Vec3f],
+    ) -> Result<(), RasterFull> {
+        self.pending.clear();
+        self.active.clear();
+
+        let clip_top = min(self.clip.top, LIMIT as usize) as i32;
+        let clip_bottom = min(self.clip.bottom, LIMIT as usize) as i32;
+        // Clips a range of scanlines, returning the first and last.
+        let clip_rows = |top: i32, bottom: i32| {
+            let (top, bottom) = (max(top, clip_top), min(bottom, clip_bottom));
+            if top < bottom {
+                Some((top, bottom - 1))
+            } else {
+                None
+            }
+        };
+
+        let mut result = Ok(());
+        for tri in tris {
+            let [a, b, c] = tri.vertex_indices.map(|i| {
+                let Vec3(x, y, _) = vertices[i];
+                (to_fixed(x), to_fixed(y))
+            });
+
+            // Reject back-facing triangles, and edge-on triangles that would
+            // generate no pixels.
+            if cross(a, b, c) <= 0 {
+                continue;
+            }
+
+            // Sort the vertices top to bottom. The top and bottom vertices are
+            // joined by a long edge, and the middle vertex divides the triangle
+            // into two trapezoids.
+            let mut v = [a, b, c];
+            v.sort_unstable_by_key(|p| p.1);
+            let [top, mid, bot] = v;
+            let mid_y = first_pixel(mid.1);
+            let upper = clip_rows(first_pixel(top.1), mid_y);
+            let lower = clip_rows(mid_y, first_pixel(bot.1));
+
+            let needed = upper.is_some() as usize + lower.is_some() as usize;
+            if self.pending.len() + needed > N {
+                result = Err(RasterFull);
+                continue;
+            }
+
+            // The long edge is on the left if the middle vertex is to its
+            // right.
+            let long_on_left = cross(top, bot, mid) < 0;
+            let normal = normals[tri.normal_index];
+            let parts = [(upper, top, mid), (lower, mid, bot)];
+            for &(rows, short0, short1) in &parts {
+                if let Some((first, last)) = rows {
+                    let long = Edge::between(top, bot, first);
+                    let short = Edge::between(short0, short1, first);
+                    let (left, right) = if long_on_left {
+                        (long, short)
+                    } else {
+                        (short, long)
+                    };
+                    let i = StateIndex::checked(self.pending.len()).unwrap();
+                    *i.index_mut(&mut self.tris) = TriState {
+                        top_y: first as usize,
+                        last_y: last as usize,
+                        edge_y: first as usize,
+                        left,
+                        right,
+                        color: tri.color,
+                        normal,
+                    };
+                    self.pending.push(i);
+                }
+            }
+        }
+
+        let tris = &self.tris;
+        self.pending.sort_unstable_by(|i, j| {
+            j.index(tris).top_y.cmp(&i.index(tris).top_y)
+        });
+        result
+    }
+
+    /// Rasterizes `scanline`, calling `body` with the span, color, and normal
+    /// of each triangle that covers part of it. Spans are clipped, and never
+    /// empty.
+    ///
+    /// This should be called for each scanline in increasing order. It's okay
+    /// to skip scanlines, but not to go back up.
+    pub fn step<F>(&mut self, scanline: usize, mut body: F)
+    where
+        F: FnMut(Range<usize>, u8, Vec3f),
+    {
+        // Move any tris that start on this scanline from pending to active.
+        // Because the tris are sorted descending by top_y, the relevant ones
+        // will be in a suffix of self.pending. Tris we've skipped over entirely
+        // are dropped.
+        while let Some(i) = self.pending.last().cloned() {
+            let tri = i.index(&self.tris);
+            if tri.top_y <= scanline {
+                self.pending.pop();
+                if tri.last_y >= scanline {
+                    self.active.push(i);
+                }
+            } else {
+                break;
+            }
+        }
+
+        // Process the pixel range for each active tri, stepping it forward.
+        let (left, right) = (self.clip.left, self.clip.right);
+        let left = min(left, LIMIT as usize) as i32;
+        let right = min(right, LIMIT as usize) as i32;
+        for i in &*self.active {
+            let tri = i.index_mut(&mut self.tris);
+            if tri.last_y < scanline {
+                continue;
+            }
+            let span = tri.evaluate(scanline);
+            let (start, end) = (max(span.start, left), min(span.end, right));
+            if end > start {
+                body(start as usize..end as usize, tri.color, tri.normal);
+            }
+        }
+
+        // Retire tris that are ending.
+        let tris = &self.tris;
+        self.active
+            .swap_remove_if(|i| i.index(tris).last_y <= scanline);
+    }
+}
+
+#[derive(Clone)]
+struct StateVec<const N: usize> {
+    states: [StateIndex<N>; N],
+    valid: usize,
+}
+
+impl<const N: usize> StateVec<N> {
+    const fn new() -> Self {
+        StateVec {
+            states: [StateIndex(0); N],
+            valid: 0,
+        }
+    }
+
+    fn push(&mut self, val: StateIndex<N>) {
+        self.states[self.valid] = val;
+        self.valid += 1;
+    }
+
+    fn clear(&mut self) {
+        self.valid = 0;
+    }
+
+    fn pop(&mut self) {
+        assert!(self.valid > 0);
+        self.valid -= 1;
+    }
+
+    fn swap_remove_if(&mut self, mut f: impl FnMut(StateIndex<N>) -> bool) {
+        let mut i = 0;
+        while i < self.valid {
+            if f(self.states[i]) {
+                self.states.swap(i, self.valid - 1);
+                self.valid -= 1;
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl<const N: usize> core::ops::Deref for StateVec<N> {
+    type Target = [StateIndex<N>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.states[..self.valid]
+    }
+}
+
+impl<const N: usize> core::ops::DerefMut for StateVec<N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.states[..self.valid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 48;
+    const H: usize = 40;
+
+    /// Counts how many times each pixel is drawn.
+    type Coverage = [[u8; W]; H];
+
+    fn render<const N: usize>(
+        raster: &mut Raster<N>,
+        rows: impl Iterator<Item = usize>,
+    ) -> Coverage {
+        let mut cov = [[0; W]; H];
+        for y in rows {
+            raster.step(y, |span, _, _| {
+                for x in span {
+                    cov[y][x] += 1;
+                }
+            });
+        }
+        cov
+    }
+
+    /// Reference fill: tests each pixel center against the triangle's edge
+    /// functions, applying the top-left rule on ties.
+    fn reference(cov: &mut Coverage, clip: Clip, v: [Point; 3]) {
+        if cross(v[0], v[1], v[2]) <= 0 {
+            return;
+        }
+        for (y, row) in cov.iter_mut().enumerate() {
+            if y < clip.top || y >= clip.bottom {
+                continue;
+            }
+            for (x, n) in row.iter_mut().enumerate() {
+                if x < clip.left || x >= clip.right {
+                    continue;
+                }
+                let p = (x as i32 * ONE + HALF, y as i32 * ONE + HALF);
+                let inside = (0..3).all(|i| {
+                    let (a, b) = (v[i], v[(i + 1) % 3]);
+                    let e = cross(a, b, p);
+                    let top_left = b.1 < a.1 || (b.1 == a.1 && b.0 > a.0);
+                    e > 0 || (e == 0 && top_left)
+                });
+                *n += inside as u8;
+            }
+        }
+    }
+
+    fn reset_with(
+        raster: &mut Raster<16>,
+        points: &[(f32, f32)],
+        tris: &[[usize; 3]],
+    ) -> Result<(), RasterFull> {
+        let vertices: Vec<Vec3f> =
+            points.iter().map(|&(x, y)| Vec3(x, y, 0.)).collect();
+        let tris: Vec<Tri> = tris
+            .iter()
+            .map(|&vertex_indices| Tri {
+                vertex_indices,
+                normal_index: 0,
+                color: 0,
+            })
+            .collect();
+        raster.reset(&tris, &vertices, &[Vec3(0., 0., 1.)])
+    }
+
+    #[test]
+    fn random_triangles_match_reference() {
+        let mut seed = 0x1234_5678_u32;
+        let mut rand = move |range: i32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed % range as u32) as i32
+        };
+        // Vertices range beyond the clip rectangle on all sides.
+        let clip = Clip {
+            left: 5,
+            top: 3,
+            right: W - 4,
+            bottom: H - 6,
+        };
+        let mut raster = Raster::<16>::new(clip);
+        for _ in 0..500 {
+            let v = [(); 3]
+                .map(|_| (rand(64 * ONE) - 8 * ONE, rand(56 * ONE) - 8 * ONE));
+            let points: Vec<(f32, f32)> = v
+                .iter()
+                .map(|p| (p.0 as f32 / ONE as f32, p.1 as f32 / ONE as f32))
+                .collect();
+            reset_with(&mut raster, &points, &[[0, 1, 2]]).unwrap();
+            let actual = render(&mut raster, 0..H);
+
+            let mut expected = [[0; W]; H];
+            reference(&mut expected, clip, v);
+            assert_eq!(&actual[..], &expected[..], "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn shared_edges_cover_once() {
+        // A fan of triangles around a point, with sub-pixel coordinates.
+        let points = [
+            (20.3, 18.7),
+            (2.1, 1.5),
+            (25.5, 0.0),
+            (44.9, 10.25),
+            (40.0, 37.9),
+            (9.6, 30.2),
+        ];
+        let tris = [[0, 1, 2], [0, 2, 3], [0, 3, 4], [0, 4, 5], [0, 5, 1]];
+        let mut raster = Raster::<16>::new(Clip::new(W, H));
+        reset_with(&mut raster, &points, &tris).unwrap();
+        let cov = render(&mut raster, 0..H);
+
+        // Every pixel is drawn at most once, and the fan's interior is solid.
+        assert!(cov.iter().flatten().all(|&n| n <= 1));
+        for row in &cov[6..28] {
+            assert!(row[12..34].iter().all(|&n| n == 1));
+        }
+    }
+
+    #[test]
+    fn back_faces_and_overflow() {
+        let points = [(0., 0.), (10., 0.), (0., 10.), (10., 10.)];
+        let mut raster = Raster::<16>::new(Clip::new(W, H));
+
+        // Counter-clockwise: culled.
+        reset_with(&mut raster, &points, &[[0, 2, 1]]).unwrap();
+        assert_eq!(render(&mut raster, 0..H), [[0; W]; H]);
+
+        // Each of these needs one state (flat top or bottom), so the
+        // seventeenth doesn't fit. The rest still draw.
+        let tris = [[0, 1, 2]; 17];
+        assert_eq!(reset_with(&mut raster, &points, &tris), Err(RasterFull));
+        let cov = render(&mut raster, 0..H);
+        assert_eq!(cov[0][0], 16);
+        assert_eq!(cov[8][0], 16);
+        assert_eq!(cov[0][8], 16);
+        // Centers on the diagonal are on the right edge, and excluded.
+        assert_eq!(cov[9][0], 0);
+        assert_eq!(cov[0][9], 0);
+    }
+
+    #[test]
+    fn skipped_scanlines() {
+        let points = [(4., 2.), (30., 9.5), (12., 33.)];
+        let mut raster = Raster::<16>::new(Clip::new(W, H));
+        reset_with(&mut raster, &points, &[[0, 1, 2]]).unwrap();
+        let all = render(&mut raster, 0..H);
+        reset_with(&mut raster, &points, &[[0, 1, 2]]).unwrap();
+        let some = render(&mut raster, (3..H).step_by(7));
+        for y in 0..H {
+            if y >= 3 && (y - 3) % 7 == 0 {
+                assert_eq!(some[y], all[y]);
+            } else {
+                assert_eq!(some[y], [0; W]);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_point_conversion() {
+        assert_eq!(to_fixed(1.5), 24);
+        assert_eq!(to_fixed(-0.03), 0);
+        assert_eq!(to_fixed(-0.04), -1);
+        assert_eq!(to_fixed(1e30), LIMIT);
+        assert_eq!(to_fixed(f32::NAN), 0);
+    }
+}
@@ -1,22 +1,29 @@
-//! Binary STL converter for wireframe graphics.
+//! Model converter for wireframe and solid graphics.
 //!
-//! This library can read a binary STL model and produce a minimized set of
-//! edges needed to represent the model as a transparent wireframe.
+//! This library can read a model and produce a minimized set of edges needed
+//! to represent it as a transparent wireframe, or of triangles needed to draw
+//! it as a solid. It understands STL (binary or ASCII), Wavefront OBJ, and PLY
+//! (ASCII or binary), and tells them apart by content.
 //!
 //! As STL uses an unordered bag-of-triangles approach with no connectivity
 //! information, we perform some basic quantization and regularization on the
 //! mesh before producing output. This has the side effect of reducing the
-//! amount of drawing required.
+//! amount of drawing required. OBJ and PLY files index their vertices, so they
+//! need none of this, but it does them no harm either, and they go through the
+//! same process.
 
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::hash::Hash;
-use std::io::{self, Read, Seek, Write};
+use std::io::{self, Read, Write};
 
-use byteorder::{LittleEndian, ReadBytesExt};
 use math::Vec3;
 use ordered_float::OrderedFloat;
 
+mod obj;
+mod ply;
+mod stl;
+
 /// Quantization factor. Coordinates are multiplied by this before rounding, so
 /// we preserve about one fractional decimal digit per trailing zero in this
 /// number.
@@ -35,8 +42,12 @@ fn quantize(p: Vec3of) -> Vec3of {
     Vec3(q(p.0), q(p.1), q(p.2))
 }
 
-/// Normalizes a vector to unit length.
+/// Normalizes a vector to unit length. The zero vector has no direction, so
+/// it's returned as is.
 fn normalize(p: Vec3of) -> Vec3of {
+    if p == ZERO {
+        return p;
+    }
     let len =
         ((p.0).0 * (p.0).0 + (p.1).0 * (p.1).0 + (p.2).0 * (p.2).0).sqrt();
     Vec3(
@@ -46,13 +57,81 @@ fn normalize(p: Vec3of) -> Vec3of {
     )
 }
 
-/// Loads a point from binary STL representation.
-fn read_point(mut input: impl Read) -> io::Result<Vec3of> {
-    Ok(Vec3(
-        OrderedFloat(input.read_f32::<LittleEndian>()?),
-        OrderedFloat(input.read_f32::<LittleEndian>()?),
-        OrderedFloat(input.read_f32::<LittleEndian>()?),
-    ))
+/// The zero vector, which stands in for the normal of a degenerate facet.
+const ZERO: Vec3of = Vec3(OrderedFloat(0.), OrderedFloat(0.), OrderedFloat(0.));
+
+/// A polygon as read from a model file, before any processing. STL only has
+/// triangles, but the other formats can have more vertices, which are assumed
+/// to be convex.
+struct Facet {
+    normal: Vec3of,
+    vertices: Vec<Vec3of>,
+}
+
+impl Facet {
+    /// Makes a facet from a polygon in a format that stores normals per
+    /// vertex, if at all. The facet's normal is the average of
+    /// `vertex_normals`, if there's one per vertex and they don't cancel out,
+    /// or is computed from the vertices otherwise.
+    ///
+    /// A polygon with no area, such as one whose vertices are collinear, has
+    /// no normal; it gets `ZERO` instead.
+    fn from_polygon(vertices: Vec<Vec3of>, vertex_normals: &[Vec3of]) -> Self {
+        let raw = |p: &Vec3of| p.map(|c| c.0);
+        let mut normal = Vec3(0., 0., 0.);
+        if vertex_normals.len() == vertices.len() {
+            for n in vertex_normals {
+                normal = normal + raw(n);
+            }
+        }
+        if normal == Vec3(0., 0., 0.) {
+            // Newell's method, which copes with polygons that aren't quite
+            // planar.
+            for (i, a) in vertices.iter().enumerate() {
+                let (a, b) = (raw(a), raw(&vertices[(i + 1) % vertices.len()]));
+                normal = normal
+                    + Vec3(
+                        (a.1 - b.1) * (a.2 + b.2),
+                        (a.2 - b.2) * (a.0 + b.0),
+                        (a.0 - b.0) * (a.1 + b.1),
+                    );
+            }
+        }
+        Facet {
+            normal: normalize(normal.map(OrderedFloat)),
+            vertices,
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads all of `input` and parses it as whichever format it appears to be.
+///
+/// A model without any facets is an error. Input that isn't recognized as PLY
+/// or STL is read as OBJ, which skips lines it doesn't understand, so this is
+/// what catches input that isn't a model at all.
+fn read_facets(mut input: impl Read) -> io::Result<Vec<Facet>> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let facets = if ply::is_ply(&data) {
+        ply::read(&data)?
+    } else if stl::is_stl(&data) {
+        stl::read(&data)?
+    } else {
+        obj::read(&data)?
+    };
+    if facets.is_empty() {
+        let kind = if data.is_empty() {
+            io::ErrorKind::UnexpectedEof
+        } else {
+            io::ErrorKind::InvalidData
+        };
+        return Err(io::Error::new(kind, "no facets found in input"));
+    }
+    Ok(facets)
 }
 
 /// An edge in the connectivity graph. Each edge connects two vertices, which
@@ -77,14 +156,11 @@ impl Edge {
     }
 }
 
-/// Process binary STL file from `input` and produce a `Wireframe` structure
+/// Process model file from `input` and produce a `Wireframe` structure
 /// describing the results.
-fn wireframe_munge(mut input: impl Read + Seek) -> io::Result<Wireframe> {
-    // The first 80 bytes of a binary STL file are a text header. Skip it.
-    input.seek(io::SeekFrom::Current(80))?;
-    // Next we have the triangle count.
-    let tri_count = input.read_u32::<LittleEndian>()?;
-    eprintln!("tri_count = {}", tri_count);
+fn wireframe_munge(input: impl Read) -> io::Result<Wireframe> {
+    let facets = read_facets(input)?;
+    eprintln!("facet_count = {}", facets.len());
 
     // Point-to-ID mapping.
     let mut points = Registry::default();
@@ -93,32 +169,29 @@ fn wireframe_munge(mut input: impl Read + Seek) -> io::Result<Wireframe> {
     // Diagnostic counters.
     let mut trivial_edges = 0;
 
-    for _ in 0..tri_count {
-        // The first 3 floats are the normal vector for the triangle, which we
-        // don't use. Skip it.
-        input.seek(io::SeekFrom::Current(3 * 4))?;
-
-        let mut indices = [0; 3];
-        for index in indices.iter_mut() {
-            // Read the next triangle vertex and quantize it before we can
-            // mistakenly use it raw.
-            let mut p = quantize(read_point(&mut input)?);
-            // This Z-shift drops the rook into the XY plane so that its center
-            // of mass is near the origin. It's a side effect of the model
-            // having been designed for 3D printing, and could be automated by
-            // centering the mesh. (TODO)
-            (p.2).0 -= 20.;
-            // Record the existing index for `p` or assign a new one.
-            *index = points.get(p);
-        }
-
-        // The final two bytes are an "attributes" field that has no meaning to
-        // us. Skip it.
-        input.seek(io::SeekFrom::Current(2))?;
-
-        // Generate three edges between the three vertex indices.
-        for &(si, ei) in &[(0, 1), (1, 2), (2, 0)] {
-            let edge = Edge(indices[si], indices[ei]).normalize();
+    for facet in &facets {
+        // The normal vector for the facet goes unused here.
+        let indices: Vec<_> = facet
+            .vertices
+            .iter()
+            .map(|&p| {
+                // Quantize the vertex before we can mistakenly use it raw.
+                let mut p = quantize(p);
+                // This Z-shift drops the rook into the XY plane so that its
+                // center of mass is near the origin. It's a side effect of the
+                // model having been designed for 3D printing, and could be
+                // automated by centering the mesh. (TODO)
+                (p.2).0 -= 20.;
+                // Record the existing index for `p` or assign a new one.
+                points.get(p)
+            })
+            .collect();
+
+        // Generate edges around the outside of the facet. Polygons are left
+        // whole, so that triangulating them doesn't add diagonals.
+        for (i, &start) in indices.iter().enumerate() {
+            let end = indices[(i + 1) % indices.len()];
+            let edge = Edge(start, end).normalize();
 
             if edge.is_trivial() {
                 trivial_edges += 1;
@@ -153,10 +226,12 @@ struct Wireframe {
     pub edges: Vec<Edge>,
 }
 
-/// Reads a binary STL file on `input` and produces Rust code representing its
+/// Reads a model file on `input` and produces Rust code representing its
 /// vertices and connectivity on `output`.
+///
+/// The file can be STL, OBJ, or PLY; the format is detected from its contents.
 pub fn generate_wireframe(
-    input: impl Read + Seek,
+    input: impl Read,
     mut output: impl Write,
 ) -> io::Result<()> {
     let munged = wireframe_munge(input)?;
@@ -230,14 +305,11 @@ fn center_cloud(points: &mut [Vec3of]) {
     }
 }
 
-/// Process binary STL file from `input` and produce a `Solid` structure
-/// describing the results.
-fn solid_munge(mut input: impl Read + Seek) -> io::Result<Solid> {
-    // The first 80 bytes of a binary STL file are a text header. Skip it.
-    input.seek(io::SeekFrom::Current(80))?;
-    // Next we have the triangle count.
-    let tri_count = input.read_u32::<LittleEndian>()?;
-    eprintln!("tri_count = {}", tri_count);
+/// Process model file from `input` and produce a `Solid` structure describing
+/// the results.
+fn solid_munge(input: impl Read) -> io::Result<Solid> {
+    let facets = read_facets(input)?;
+    eprintln!("facet_count = {}", facets.len());
 
     // Point-to-ID mapping.
     let mut points = Registry::default();
@@ -248,31 +320,34 @@ fn solid_munge(mut input: impl Read + Seek) -> io::Result<Solid> {
     // Diagnostic counters.
     let mut trivial_tris = 0;
 
-    for _ in 0..tri_count {
-        let normal =
-            unique_normals.get(normalize(quantize(read_point(&mut input)?)));
-
-        let mut indices = [0; 3];
-        for index in indices.iter_mut() {
-            // Read the next triangle vertex and quantize it before we can
-            // mistakenly use it raw.
-            let p = quantize(read_point(&mut input)?);
-            // Record the existing index for `p` or assign a new one.
-            *index = points.get(p);
-        }
-
-        // The final two bytes are an "attributes" field that has no meaning to
-        // us. Skip it.
-        input.seek(io::SeekFrom::Current(2))?;
-
-        if indices[0] == indices[1] && indices[0] == indices[2] {
-            trivial_tris += 1;
+    for facet in &facets {
+        if facet.normal == ZERO {
+            // A polygon with no area has nothing to draw, and no normal to
+            // shade it with.
+            trivial_tris += facet.vertices.len() - 2;
             continue;
         }
+        let normal = unique_normals.get(normalize(quantize(facet.normal)));
+
+        // Quantize each vertex before we can mistakenly use it raw, and
+        // record the existing index for it or assign a new one.
+        let indices: Vec<_> = facet
+            .vertices
+            .iter()
+            .map(|&p| points.get(quantize(p)))
+            .collect();
+
+        // Split polygons into a fan of triangles around the first vertex.
+        for i in 2..indices.len() {
+            let (a, b, c) = (indices[0], indices[i - 1], indices[i]);
+            if a == b && a == c {
+                trivial_tris += 1;
+                continue;
+            }
 
-        // Record the triangle.
-        unique_tris
-            .insert(Tri::new(indices[0], indices[1], indices[2], normal));
+            // Record the triangle.
+            unique_tris.insert(Tri::new(a, b, c, normal));
+        }
     }
 
     let mut ordered_points = points.into_vec();
@@ -303,10 +378,12 @@ struct Solid {
     pub tris: Vec<Tri>,
 }
 
-/// Reads a binary STL file on `input` and produces Rust code representing its
-/// vertices and connectivity on `output`.
+/// Reads a model file on `input` and produces Rust code representing its
+/// vertices, normals, and triangles on `output`.
+///
+/// The file can be STL, OBJ, or PLY; the format is detected from its contents.
 pub fn generate_solid(
-    input: impl Read + Seek,
+    input: impl Read,
     mut output: impl Write,
 ) -> io::Result<()> {
     let munged = solid_munge(input)?;
@@ -318,7 +395,7 @@ pub fn generate_solid(
         munged.duplicate_tris
     )?;
     writeln!(output, "use math::{{Vec3, Vec3f}};")?;
-    writeln!(output, "use crate::render::Tri;")?;
+    writeln!(output, "use polyrast::Tri;")?;
 
     writeln!(
         output,
@@ -407,3 +484,208 @@ impl<T: Eq + Hash> DupeSet<T> {
         (self.0.into_iter().collect(), self.1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static BINARY: &[u8] = include_bytes!("../fixtures/tetra.stl");
+    static ASCII: &[u8] = include_bytes!("../fixtures/tetra_ascii.stl");
+    static CUBE_STL: &[u8] = include_bytes!("../fixtures/cube.stl");
+    static CUBE_OBJ: &[u8] = include_bytes!("../fixtures/cube.obj");
+    static CUBE_PLY: &[u8] = include_bytes!("../fixtures/cube.ply");
+    static CUBE_BINARY_PLY: &[u8] =
+        include_bytes!("../fixtures/cube_binary.ply");
+
+    fn wireframe(data: &[u8]) -> String {
+        let mut out = Vec::new();
+        generate_wireframe(data, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn solid(data: &[u8]) -> String {
+        let mut out = Vec::new();
+        generate_solid(data, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// The fixtures hold the same model, and the binary one's header starts
+    /// with `solid` to make sure it's not mistaken for ASCII.
+    #[test]
+    fn binary_and_ascii_agree() {
+        assert!(BINARY.starts_with(b"solid"));
+        assert_eq!(wireframe(BINARY), wireframe(ASCII));
+        assert_eq!(solid(BINARY), solid(ASCII));
+
+        // Four corners and six edges, once the near-duplicate vertex and the
+        // repeated face are merged.
+        let w = wireframe(ASCII);
+        assert!(w.contains("VERTEX_COUNT: usize = 4;"));
+        assert!(w.contains("EDGES: [(u16, u16); 6]"));
+    }
+
+    #[test]
+    fn ascii_facets() {
+        let facets = stl::read(ASCII).unwrap();
+        assert_eq!(facets.len(), 5);
+        assert_eq!(facets[1].normal, Vec3(0., -1., 0.).map(OrderedFloat));
+        assert_eq!(
+            facets[4].vertices[2],
+            Vec3(10.01, 0., 0.).map(OrderedFloat)
+        );
+    }
+
+    #[test]
+    fn ascii_errors() {
+        let text = std::str::from_utf8(ASCII).unwrap();
+        let err = |s: &str| stl::read(s.as_bytes()).err().unwrap().kind();
+
+        // Stop partway through a facet, at the end of a line.
+        let cut = &text[..text[..text.len() / 2].rfind('\n').unwrap()];
+        assert_eq!(err(cut), io::ErrorKind::UnexpectedEof);
+        let bad = text.replacen("1.000000e+01", "ten", 1);
+        assert_eq!(err(&bad), io::ErrorKind::InvalidData);
+        let bad = text.replacen("endloop", "endlop", 1);
+        assert_eq!(err(&bad), io::ErrorKind::InvalidData);
+
+        // Empty solids are unusual but valid.
+        let empty = stl::read(b"solid empty\nendsolid empty\n").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn binary_errors() {
+        let err = |data: &[u8]| stl::read(data).err().unwrap().kind();
+        // Blank the header, so that a truncated file isn't taken for ASCII.
+        let mut data = vec![0; 80];
+        data.extend_from_slice(&BINARY[80..]);
+        assert_eq!(err(&data[..data.len() - 1]), io::ErrorKind::UnexpectedEof);
+
+        // A garbage triangle count mustn't be taken at its word.
+        data[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(err(&data), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn empty_and_unrecognized_input() {
+        let err = |data: &[u8]| {
+            let mut out = Vec::new();
+            let kind = generate_wireframe(data, &mut out).err().unwrap().kind();
+            assert_eq!(
+                generate_solid(data, &mut out).err().unwrap().kind(),
+                kind
+            );
+            kind
+        };
+        assert_eq!(err(b""), io::ErrorKind::UnexpectedEof);
+        assert_eq!(err(b"not a model\n"), io::ErrorKind::InvalidData);
+        // Anything that isn't text is taken for binary STL, here truncated.
+        assert_eq!(err(&[0xFF; 100]), io::ErrorKind::UnexpectedEof);
+        assert_eq!(
+            err(b"solid empty\nendsolid empty\n"),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(err(b"v 0 0 0\nv 1 0 0\n"), io::ErrorKind::InvalidData);
+    }
+
+    /// The cube fixtures hold the same model: in OBJ and PLY as six quads, and
+    /// in STL as the twelve triangles the quads are split into.
+    #[test]
+    fn cube_formats_agree() {
+        for &data in &[CUBE_PLY, CUBE_BINARY_PLY] {
+            assert_eq!(wireframe(data), wireframe(CUBE_OBJ));
+            assert_eq!(solid(data), solid(CUBE_OBJ));
+        }
+        assert_eq!(solid(CUBE_STL), solid(CUBE_OBJ));
+
+        let s = solid(CUBE_OBJ);
+        assert!(s.contains("VERTEX_COUNT: usize = 8;"));
+        assert!(s.contains("NORMAL_COUNT: usize = 6;"));
+        assert!(s.contains("TRIS: [Tri; 12]"));
+    }
+
+    /// Wireframes of polygons don't show how they were triangulated.
+    #[test]
+    fn polygon_outlines() {
+        assert!(wireframe(CUBE_OBJ).contains("EDGES: [(u16, u16); 12]"));
+        assert!(wireframe(CUBE_STL).contains("EDGES: [(u16, u16); 18]"));
+    }
+
+    #[test]
+    fn obj_references() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 2\n\
+                   f 1//1 2//1 3//1\nf -3 -2 -1 # comment\n";
+        let facets = obj::read(obj.as_bytes()).unwrap();
+        assert_eq!(facets.len(), 2);
+        for f in &facets {
+            assert_eq!(f.vertices[1], Vec3(1., 0., 0.).map(OrderedFloat));
+            assert_eq!(f.normal, Vec3(0., 0., 1.).map(OrderedFloat));
+        }
+
+        let err = |s: &str| obj::read(s.as_bytes()).err().unwrap().to_string();
+        assert_eq!(err("v 0 0 0\nf 1 1 2\n"), "line 2: bad vertex index");
+        assert_eq!(err("v 0 0 0\nf 1 0 1\n"), "line 2: bad vertex index");
+        assert_eq!(err("v 0 0\n"), "line 1: expected three numbers after v");
+        assert_eq!(
+            err("v 0 0 0\nf 1 1\n"),
+            "line 2: face has fewer than three vertices"
+        );
+    }
+
+    /// Polygons with no area have no normal, so they can't be shaded, and are
+    /// counted as trivial instead.
+    #[test]
+    fn degenerate_polygons() {
+        let obj = b"v 0 0 0\nv 1 0 0\nv 2 0 0\nv 0 1 0\nf 1 2 3\nf 1 2 4\n";
+        let facets = obj::read(obj).unwrap();
+        assert_eq!(facets[0].normal, ZERO);
+        let munged = solid_munge(&obj[..]).unwrap();
+        assert_eq!(munged.trivial_tris, 1);
+        assert_eq!(munged.tris.len(), 1);
+        assert_eq!(munged.normals.len(), 1);
+        assert!(!solid(obj).contains("NaN"));
+
+        // STL facets can have a zero normal too, but that's usually laziness
+        // on the exporter's part, so it's computed from the vertices instead.
+        let stl = "solid lazy\nfacet normal 0 0 0\nouter loop\n\
+                   vertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\n\
+                   endloop\nendfacet\nendsolid lazy\n";
+        let facets = stl::read(stl.as_bytes()).unwrap();
+        assert_eq!(facets[0].normal, Vec3(0., 0., 1.).map(OrderedFloat));
+    }
+
+    #[test]
+    fn ply_big_endian_and_errors() {
+        let mut ply = b"ply\nformat binary_big_endian 1.0\n\
+                        element vertex 3\nproperty short x\n\
+                        property short y\nproperty short z\n\
+                        element face 1\n\
+                        property list uchar ushort vertex_index\n\
+                        end_header\n"
+            .to_vec();
+        for v in &[[0i16, 0, 0], [2, 0, 0], [0, 2, 0]] {
+            for c in v {
+                ply.extend(&c.to_be_bytes());
+            }
+        }
+        ply.push(3);
+        for i in 0..3u16 {
+            ply.extend(&i.to_be_bytes());
+        }
+
+        let facets = ply::read(&ply).unwrap();
+        assert_eq!(facets.len(), 1);
+        assert_eq!(facets[0].vertices[1], Vec3(2., 0., 0.).map(OrderedFloat));
+        assert_eq!(facets[0].normal, Vec3(0., 0., 1.).map(OrderedFloat));
+
+        let kind = |data: &[u8]| ply::read(data).err().unwrap().kind();
+        assert_eq!(kind(&ply[..ply.len() - 1]), io::ErrorKind::UnexpectedEof);
+        let last = ply.len() - 1;
+        ply[last] = 3;
+        assert_eq!(kind(&ply), io::ErrorKind::InvalidData);
+        assert_eq!(
+            kind(b"ply\nformat ascii 1.0\n"),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Wavefront OBJ reader.
+//!
+//! Only geometry is used: vertex positions (`v`), vertex normals (`vn`), and
+//! faces (`f`). Texture coordinates, groups, materials, and the like are
+//! ignored.
+
+use std::io;
+
+use math::Vec3;
+use ordered_float::OrderedFloat;
+
+use crate::{invalid, Facet, Vec3of};
+
+/// Parses an OBJ file.
+pub fn read(data: &[u8]) -> io::Result<Vec<Facet>> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| invalid("OBJ is not valid text"))?;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut facets = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let error =
+            |msg: &str| invalid(&format!("line {}: {}", number + 1, msg));
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_ascii_whitespace();
+        match words.next() {
+            Some("v") => positions.push(
+                point(&mut words)
+                    .ok_or_else(|| error("expected three numbers after v"))?,
+            ),
+            Some("vn") => normals.push(
+                point(&mut words)
+                    .ok_or_else(|| error("expected three numbers after vn"))?,
+            ),
+            Some("f") => {
+                let mut vertices = Vec::new();
+                let mut vertex_normals = Vec::new();
+                // Each vertex is `v`, `v/vt`, `v//vn`, or `v/vt/vn`.
+                for word in words {
+                    let mut refs = word.split('/');
+                    let v = refs.next().unwrap();
+                    vertices.push(
+                        positions[index(v, positions.len())
+                            .ok_or_else(|| error("bad vertex index"))?],
+                    );
+                    if let Some(vn) = refs.nth(1) {
+                        vertex_normals.push(
+                            normals[index(vn, normals.len())
+                                .ok_or_else(|| error("bad normal index"))?],
+                        );
+                    }
+                }
+                if vertices.len() < 3 {
+                    return Err(error("face has fewer than three vertices"));
+                }
+                facets.push(Facet::from_polygon(vertices, &vertex_normals));
+            }
+            _ => (),
+        }
+    }
+    Ok(facets)
+}
+
+/// Parses the three coordinates that start `words`. Some files give a fourth,
+/// or a color, after them; these are ignored.
+fn point<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Vec3of> {
+    let mut number = || words.next()?.parse().ok().map(OrderedFloat);
+    Some(Vec3(number()?, number()?, number()?))
+}
+
+/// Resolves a reference to one of the `count` elements defined so far. These
+/// count from 1, or back from the most recent if negative.
+fn index(word: &str, count: usize) -> Option<usize> {
+    let i: isize = word.parse().ok()?;
+    let i = if i < 0 { count as isize + i } else { i - 1 };
+    if i >= 0 && (i as usize) < count {
+        Some(i as usize)
+    } else {
+        None
+    }
+}
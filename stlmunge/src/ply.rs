@@ -0,0 +1,254 @@
+//! PLY reader, for the ASCII and both binary encodings.
+//!
+//! Vertex positions come from the `x`, `y`, and `z` properties of the
+//! `vertex` element, and normals from `nx`, `ny`, and `nz` if they're all
+//! present. Polygons come from the `vertex_indices` (or `vertex_index`) list
+//! of the `face` element. Anything else, such as colors, is read past and
+//! ignored.
+
+use std::io;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use math::Vec3;
+use ordered_float::OrderedFloat;
+
+use crate::{invalid, Facet};
+
+/// Checks for the magic line that starts every PLY file.
+pub fn is_ply(data: &[u8]) -> bool {
+    data.starts_with(b"ply\n") || data.starts_with(b"ply\r\n")
+}
+
+/// Parses a PLY file.
+pub fn read(data: &[u8]) -> io::Result<Vec<Facet>> {
+    let (header, mut body) = parse_header(data)?;
+
+    let mut vertices = Vec::new();
+    let mut faces = Vec::new();
+    for element in &header.elements {
+        let find =
+            |name: &str| element.properties.iter().position(|p| p.name == name);
+        let position = (find("x"), find("y"), find("z"));
+        let normal = (find("nx"), find("ny"), find("nz"));
+        let indices = find("vertex_indices").or_else(|| find("vertex_index"));
+
+        for _ in 0..element.count {
+            // Each property's value, or for lists, the values it holds.
+            let mut values = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                let list = match property.count {
+                    Some(count) => {
+                        let n = body.scalar(count)?;
+                        if n < 0. || n.fract() != 0. {
+                            return Err(invalid("bad PLY list length"));
+                        }
+                        (0..n as usize)
+                            .map(|_| body.scalar(property.kind))
+                            .collect::<io::Result<_>>()?
+                    }
+                    None => vec![body.scalar(property.kind)?],
+                };
+                values.push(list);
+            }
+            let point =
+                |(x, y, z): (Option<usize>, Option<usize>, Option<usize>)| {
+                    let c = |i: usize| OrderedFloat(values[i][0] as f32);
+                    Some(Vec3(c(x?), c(y?), c(z?)))
+                };
+
+            if element.name == "vertex" {
+                let p = point(position)
+                    .ok_or_else(|| invalid("PLY vertex lacks x, y, or z"))?;
+                vertices.push((p, point(normal)));
+            } else if element.name == "face" {
+                let i = indices
+                    .ok_or_else(|| invalid("PLY face lacks vertex_indices"))?;
+                faces.push(values.swap_remove(i));
+            }
+        }
+    }
+
+    // Faces could come before vertices, so look them up only once everything
+    // has been read.
+    faces
+        .into_iter()
+        .map(|indices| {
+            if indices.len() < 3 {
+                return Err(invalid("PLY face has fewer than three vertices"));
+            }
+            let mut points = Vec::with_capacity(indices.len());
+            let mut normals = Vec::with_capacity(indices.len());
+            for i in indices {
+                let &(p, n) = vertices
+                    .get(i as usize)
+                    .filter(|_| i >= 0.)
+                    .ok_or_else(|| invalid("bad PLY vertex index"))?;
+                points.push(p);
+                normals.extend(n);
+            }
+            Ok(Facet::from_polygon(points, &normals))
+        })
+        .collect()
+}
+
+struct Header {
+    elements: Vec<Element>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Property {
+    name: String,
+    kind: Scalar,
+    /// For list properties, the type of the length that precedes the values.
+    count: Option<Scalar>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => {
+                return Err(invalid(&format!("unknown PLY type {}", name)));
+            }
+        })
+    }
+}
+
+/// Splits a PLY file into its parsed header and its body.
+fn parse_header(data: &[u8]) -> io::Result<(Header, Body<'_>)> {
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|w| w == END)
+        .ok_or_else(|| invalid("PLY header has no end"))?;
+    // The body starts on the line after `end_header`.
+    let body_start = data[end..]
+        .iter()
+        .position(|&c| c == b'\n')
+        .map_or(data.len(), |i| end + i + 1);
+    let text = std::str::from_utf8(&data[..end])
+        .map_err(|_| invalid("PLY header is not valid text"))?;
+    let body = &data[body_start..];
+
+    let mut header = Header {
+        elements: Vec::new(),
+    };
+    let mut format = None;
+    for line in text.lines().skip(1) {
+        let words: Vec<_> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => {
+                let text = std::str::from_utf8(body)
+                    .map_err(|_| invalid("PLY body is not valid text"))?;
+                format = Some(Body::Ascii(text.split_ascii_whitespace()));
+            }
+            ["format", "binary_little_endian", _] => {
+                format = Some(Body::Little(body))
+            }
+            ["format", "binary_big_endian", _] => {
+                format = Some(Body::Big(body))
+            }
+            ["element", name, count] => header.elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid("bad PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, kind, name] => {
+                element(&mut header)?.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Scalar::parse(kind)?,
+                    count: Some(Scalar::parse(count)?),
+                })
+            }
+            ["property", kind, name] => {
+                element(&mut header)?.properties.push(Property {
+                    name: name.to_string(),
+                    kind: Scalar::parse(kind)?,
+                    count: None,
+                })
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => (),
+            _ => {
+                return Err(invalid(&format!("bad PLY header line: {}", line)))
+            }
+        }
+    }
+
+    let body = format.ok_or_else(|| invalid("PLY header has no format"))?;
+    Ok((header, body))
+}
+
+/// Finds the element that a property line belongs to.
+fn element(header: &mut Header) -> io::Result<&mut Element> {
+    header
+        .elements
+        .last_mut()
+        .ok_or_else(|| invalid("PLY property comes before any element"))
+}
+
+/// The data following the header, in whichever encoding it uses.
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Little(&'a [u8]),
+    Big(&'a [u8]),
+}
+
+impl Body<'_> {
+    /// Reads the next value, of type `kind`.
+    fn scalar(&mut self, kind: Scalar) -> io::Result<f64> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(truncated)?;
+                word.parse().map_err(|_| {
+                    invalid(&format!("expected a number, found {}", word))
+                })
+            }
+            Body::Little(input) => binary::<LittleEndian>(input, kind),
+            Body::Big(input) => binary::<BigEndian>(input, kind),
+        }
+    }
+}
+
+fn binary<B: ByteOrder>(input: &mut &[u8], kind: Scalar) -> io::Result<f64> {
+    let value = match kind {
+        Scalar::I8 => input.read_i8().map(f64::from),
+        Scalar::U8 => input.read_u8().map(f64::from),
+        Scalar::I16 => input.read_i16::<B>().map(f64::from),
+        Scalar::U16 => input.read_u16::<B>().map(f64::from),
+        Scalar::I32 => input.read_i32::<B>().map(f64::from),
+        Scalar::U32 => input.read_u32::<B>().map(f64::from),
+        Scalar::F32 => input.read_f32::<B>().map(f64::from),
+        Scalar::F64 => input.read_f64::<B>(),
+    };
+    value.map_err(|_| truncated())
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "PLY data truncated")
+}
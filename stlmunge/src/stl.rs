@@ -0,0 +1,205 @@
+//! STL reader, for both the binary and ASCII flavors.
+
+use std::io::{self, Cursor, Read};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use math::Vec3;
+use ordered_float::OrderedFloat;
+
+use crate::{invalid, Facet, Vec3of};
+
+const HEADER_SIZE: usize = 80;
+/// Size of a binary facet record: normal, three vertices, and a 16-bit
+/// attribute field.
+const FACET_SIZE: usize = 4 * 3 * 4 + 2;
+
+/// Parses an STL file, detecting whether it's binary or ASCII.
+pub fn read(data: &[u8]) -> io::Result<Vec<Facet>> {
+    if is_binary(data) {
+        read_binary(data)
+    } else {
+        read_ascii(data)
+    }
+}
+
+/// Guesses whether `data` is STL of either flavor, rather than some other
+/// format.
+///
+/// Anything that isn't text is taken to be binary STL, so that damaged files
+/// get a reasonable error.
+pub fn is_stl(data: &[u8]) -> bool {
+    sized_as_binary(data)
+        || starts_with_solid(data)
+        || std::str::from_utf8(data).is_err()
+}
+
+/// Guesses whether `data` is binary STL.
+///
+/// ASCII files start with `solid`, but so do the headers of many binary ones,
+/// so that's not enough by itself. A binary file's size is determined exactly
+/// by the triangle count after its header, so check that first.
+fn is_binary(data: &[u8]) -> bool {
+    sized_as_binary(data) || !starts_with_solid(data)
+}
+
+fn sized_as_binary(data: &[u8]) -> bool {
+    if data.len() < HEADER_SIZE + 4 {
+        return false;
+    }
+    let mut count = &data[HEADER_SIZE..HEADER_SIZE + 4];
+    let count = count.read_u32::<LittleEndian>().unwrap() as usize;
+    count
+        .checked_mul(FACET_SIZE)
+        .and_then(|n| n.checked_add(HEADER_SIZE + 4))
+        == Some(data.len())
+}
+
+fn starts_with_solid(data: &[u8]) -> bool {
+    let start = data.iter().position(|c| !c.is_ascii_whitespace());
+    let text = &data[start.unwrap_or(data.len())..];
+    text.len() >= 5 && text[..5].eq_ignore_ascii_case(b"solid")
+}
+
+/// Loads a point from binary STL representation.
+fn read_point(mut input: impl Read) -> io::Result<Vec3of> {
+    Ok(Vec3(
+        OrderedFloat(input.read_f32::<LittleEndian>()?),
+        OrderedFloat(input.read_f32::<LittleEndian>()?),
+        OrderedFloat(input.read_f32::<LittleEndian>()?),
+    ))
+}
+
+fn read_binary(data: &[u8]) -> io::Result<Vec<Facet>> {
+    let mut input = Cursor::new(data);
+    // The first 80 bytes of a binary STL file are a text header. Skip it.
+    input.set_position(HEADER_SIZE as u64);
+    // Next we have the triangle count.
+    let tri_count = input.read_u32::<LittleEndian>()?;
+
+    // A corrupt count could be enormous, so don't allocate more facets than
+    // the data could possibly hold.
+    let capacity = (tri_count as usize).min(data.len() / FACET_SIZE);
+    let mut facets = Vec::with_capacity(capacity);
+    for _ in 0..tri_count {
+        let normal = read_point(&mut input)?;
+        let vertices = vec![
+            read_point(&mut input)?,
+            read_point(&mut input)?,
+            read_point(&mut input)?,
+        ];
+        // The final two bytes are an "attributes" field that has no meaning
+        // to us. Skip it.
+        input.read_u16::<LittleEndian>()?;
+        facets.push(facet(normal, vertices));
+    }
+    Ok(facets)
+}
+
+/// Parses ASCII STL:
+///
+/// ```text
+/// solid name
+///   facet normal nx ny nz
+///     outer loop
+///       vertex x y z
+///       vertex x y z
+///       vertex x y z
+///     endloop
+///   endfacet
+///   ...
+/// endsolid name
+/// ```
+///
+/// Keywords are matched without regard to case, and files containing several
+/// solids in a row are accepted, with their facets combined.
+fn read_ascii(data: &[u8]) -> io::Result<Vec<Facet>> {
+    let text = std::str::from_utf8(data)
+        .map_err(|_| invalid("ASCII STL is not valid text"))?;
+    let mut tokens = Tokens(text.split_ascii_whitespace());
+
+    let mut facets = Vec::new();
+    tokens.expect("solid")?;
+    loop {
+        // The solid's name runs up to the first facet, and may be empty or
+        // contain spaces.
+        let mut ended = false;
+        for t in tokens.0.by_ref() {
+            if t.eq_ignore_ascii_case("facet") {
+                break;
+            }
+            if t.eq_ignore_ascii_case("endsolid") {
+                ended = true;
+                break;
+            }
+        }
+
+        while !ended {
+            tokens.expect("normal")?;
+            let normal = tokens.point()?;
+            tokens.expect("outer")?;
+            tokens.expect("loop")?;
+            let mut vertex = || -> io::Result<_> {
+                tokens.expect("vertex")?;
+                tokens.point()
+            };
+            let vertices = vec![vertex()?, vertex()?, vertex()?];
+            tokens.expect("endloop")?;
+            tokens.expect("endfacet")?;
+            facets.push(facet(normal, vertices));
+
+            match tokens.0.next() {
+                Some(t) if t.eq_ignore_ascii_case("facet") => (),
+                Some(t) if t.eq_ignore_ascii_case("endsolid") => ended = true,
+                Some(_) => return Err(invalid("expected facet or endsolid")),
+                None => return Err(truncated()),
+            }
+        }
+
+        // Skip the name after `endsolid`, and stop unless another solid
+        // follows.
+        loop {
+            match tokens.0.next() {
+                None => return Ok(facets),
+                Some(t) if t.eq_ignore_ascii_case("solid") => break,
+                Some(_) => (),
+            }
+        }
+    }
+}
+
+/// Makes a facet from a triangle and its stored normal. Some exporters leave
+/// the normal zero; passing it as each vertex's normal means it's recomputed
+/// in that case.
+fn facet(normal: Vec3of, vertices: Vec<Vec3of>) -> Facet {
+    Facet::from_polygon(vertices, &[normal; 3])
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "STL data truncated")
+}
+
+/// Whitespace-separated words of an ASCII STL file.
+struct Tokens<'a>(std::str::SplitAsciiWhitespace<'a>);
+
+impl Tokens<'_> {
+    fn expect(&mut self, keyword: &str) -> io::Result<()> {
+        match self.0.next() {
+            Some(t) if t.eq_ignore_ascii_case(keyword) => Ok(()),
+            Some(t) => {
+                Err(invalid(&format!("expected {}, found {}", keyword, t)))
+            }
+            None => Err(truncated()),
+        }
+    }
+
+    fn point(&mut self) -> io::Result<Vec3of> {
+        let mut number = || -> io::Result<_> {
+            let t = self.0.next().ok_or_else(truncated)?;
+            let v: f32 = t.parse().map_err(|_| {
+                invalid(&format!("expected a number, found {}", t))
+            })?;
+            Ok(OrderedFloat(v))
+        };
+        Ok(Vec3(number()?, number()?, number()?))
+    }
+}
@@ -1,6 +1,7 @@
 mod utils;
 
-use m4vga::util::spin_lock::SpinLock;
+use m4vga::color::Color;
+use m4vga::util::flip_buf::FlipBuffer;
 use wasm_bindgen::prelude::*;
 
 use m4vga_fx_common::{Demo, Raster, Render};
@@ -11,8 +12,8 @@ use m4vga_fx_tunnel as tunnel;
 const FIXED_WIDTH: usize = 800;
 const FIXED_HEIGHT: usize = 600;
 
-const RED_X4: u32 = 0x03_03_03_03;
-const BLUE_X4: u32 = 0x30_30_30_30;
+const RED_X4: u32 = Color::RED.to_pixel() as u32 * 0x01_01_01_01;
+const BLUE_X4: u32 = Color::BLUE.to_pixel() as u32 * 0x01_01_01_01;
 const GREEN32: u32 = 0xFF_00_FF_00;
 
 #[wasm_bindgen]
@@ -55,9 +56,6 @@ where
     S: Demo<'a>,
 {
     pub fn step(&'a mut self) {
-        // Safety: wasm is not a concurrent environment right now, so preemption
-        // is not an issue.
-        let i_priority = unsafe { m4vga::priority::I0::new() };
         let t_priority = m4vga::priority::Thread::new_checked().unwrap();
 
         let (mut raster, mut render) = self.state.split();
@@ -81,6 +79,8 @@ where
         for (ln, target32) in
             self.framebuffer.chunks_mut(FIXED_WIDTH).enumerate()
         {
+            // Measurement traces (if enabled) count time in scanlines.
+            m4vga::util::measurement::trace::advance(1);
             if ctx.repeat_lines > 0 {
                 ctx.repeat_lines -= 1;
             } else {
@@ -89,7 +89,9 @@ where
                     repeat_lines: 0,
                     target_range: 0..0,
                 };
-                raster.raster_callback(ln, target, &mut ctx, i_priority);
+                m4vga::priority::simulate_i0(|i_priority| {
+                    raster.raster_callback(ln, target, &mut ctx, i_priority)
+                });
             }
             secondary_unpack(&ctx, target.as_words(), target32);
         }
@@ -139,11 +141,9 @@ fn secondary_unpack(
 }
 
 fn unpack_color8(src: u8) -> u32 {
-    // HACK: we're little-endian, so we want ABGR
-    let r = (src as u32 & 0b11) << 6;
-    let g = (src as u32 & 0b11_00) << (4 + 8);
-    let b = (src as u32 & 0b11_00_00) << (2 + 16);
-    0xFF_00_00_00 | r | g | b
+    // The canvas wants RGBA bytes in memory order.
+    let (r, g, b) = Color::from_pixel(src).to_rgb8();
+    u32::from_le_bytes([r, g, b, 0xFF])
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -165,8 +165,10 @@ impl Tunnel {
 
         Tunnel(
             tunnel::State {
-                fg: SpinLock::new(vec![RED_X4; tunnel::BUFFER_WORDS]),
-                bg: vec![RED_X4; tunnel::BUFFER_WORDS],
+                buffers: FlipBuffer::new([
+                    vec![RED_X4; tunnel::BUFFER_WORDS],
+                    vec![RED_X4; tunnel::BUFFER_WORDS],
+                ]),
                 table,
             }
             .into(),
@@ -231,8 +233,8 @@ impl Conway {
         Conway(Sim::from(conway::State::new(
             vec![0; 800 * 600 / 32],
             vec![0; 800 * 600 / 32],
-            0b11_11_11,
-            0b00_00_00,
+            Color::WHITE,
+            Color::BLACK,
         )))
     }
 